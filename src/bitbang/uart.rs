@@ -1,11 +1,18 @@
 use embedded_hal as hal;
 use nb;
 
-use crate::{time::spin_for, gpio::pin::{PushPullOutput, AnyPin, SpecificPin}};
-use hal::{digital::v2::OutputPin, serial::Write};
+use crate::{
+    executor, profile,
+    time::{spin_until, Instant, Overshoot},
+};
+use hal::{
+    digital::v2::{InputPin, OutputPin},
+    serial::{Read, Write},
+};
 
-use core::{time::Duration, convert::Infallible};
+use core::{future::poll_fn, task::Poll, time::Duration};
 
+#[derive(Copy, Clone)]
 pub enum ParityMode{
     None,
     Odd,
@@ -19,16 +26,44 @@ pub enum StopBitsOption{
     Two
 }
 
-pub struct SoftUartTransmitter<T> where T:AnyPin<Mode = PushPullOutput>{
-    tx_pin: SpecificPin<T>,
+/// Time from the start of a frame to the start of bit `bit`, without accumulating the rounding of
+/// [`SoftUartTransmitter::bit_time()`].
+fn bit_offset(baud_rate: u32, bit: u64) -> Duration {
+    Duration::from_nanos(bit * 1_000_000_000 / baud_rate as u64)
+}
+
+/// Errors of the [`SoftUartReceiver`].
+#[derive(Debug)]
+pub enum ReadError<E> {
+    /// Reading the pin failed.
+    Pin(E),
+    /// A stop bit was low.
+    Framing,
+    /// The parity bit didn't match the data.
+    Parity,
+}
+
+/// Bit-banged UART transmitter on any output pin, be it a type-level `Pin` or a `DynPin`.
+///
+/// Characters are either sent blocking with `write()`, which spins for every bit, or shifted out
+/// by the caller's own bit clock with [`start()`](SoftUartTransmitter::start) and
+/// [`tick()`](SoftUartTransmitter::tick).
+///
+/// `write()` times every bit from the start of the character, and keeps track of how late the
+/// latest bit edge was, see [`worst_lateness()`](SoftUartTransmitter::worst_lateness).
+pub struct SoftUartTransmitter<P> where P: OutputPin{
+    tx_pin: P,
     baud_rate: u32,    // Baud rate in bauds/s
     stop_bits: StopBitsOption,  // Number of stop bits
     parity: ParityMode,  // Parity mode
+    frame: u16,  // Bits of the current character still to send, LSB first
+    frame_bits: u8,  // Number of bits in frame
+    worst_lateness: Overshoot,  // Latest bit edge of write() so far
 }
 
-impl<T> SoftUartTransmitter<T> where T:AnyPin<Mode = PushPullOutput>{
+impl<P> SoftUartTransmitter<P> where P: OutputPin{
     pub fn new(
-        tx_pin: SpecificPin<T>,
+        tx_pin: P,
         baud_rate: u32,    // Baud rate in bauds/s
         stop_bits: StopBitsOption,  // Number of stop bits
         parity: ParityMode,  // Parity mode
@@ -38,6 +73,9 @@ impl<T> SoftUartTransmitter<T> where T:AnyPin<Mode = PushPullOutput>{
             baud_rate,
             stop_bits,
             parity,
+            frame: 0,
+            frame_bits: 0,
+            worst_lateness: Overshoot::default(),
         }
     }
 
@@ -45,52 +83,107 @@ impl<T> SoftUartTransmitter<T> where T:AnyPin<Mode = PushPullOutput>{
         self.baud_rate
     }
 
-}
+    /// Time every bit stays on the wire.
+    pub fn bit_time(&self) -> Duration {
+        Duration::from_nanos(1_000_000_000/self.baud_rate as u64)
+    }
 
-impl <T> Write<u8> for SoftUartTransmitter<T>
-where
-    T: AnyPin<Mode = PushPullOutput>
-{
-    type Error = Infallible;
+    /// Start a character. Replaces the rest of a character still being sent.
+    ///
+    /// Nothing is put on the wire yet. Every following [`tick()`](Self::tick) puts the next bit
+    /// there, so it must be called once per [`bit_time()`](Self::bit_time).
+    pub fn start(&mut self, word: u8) {
+        // Start bit, low.
+        let mut frame = (word as u16) << 1;
+        let mut bits = 9;
 
-    fn write(&mut self, word:u8) -> nb::Result<(), Self::Error>{
-        // Emmit start bit
-        // println!("Simulated transmission:");  -- Maybe add debug print
-        self.tx_pin.set_low().unwrap();
-        spin_for(Duration::from_nanos(1_000_000_000/self.baud_rate as u64));
-
-        // Emmit data
-        for shift in 0..8 {
-            let curr_data = (word>>shift)&1;
-            self.tx_pin.set_state((curr_data != 0).into()).unwrap();
-            spin_for(Duration::from_nanos(1_000_000_000/self.baud_rate as u64));
+        let p = word.count_ones() % 2;
+        let parity_bit = match self.parity {
+            ParityMode::Even => Some(p != 0),
+            ParityMode::Odd => Some(p == 0),
+            ParityMode::None => None,
+        };
+        if let Some(parity_bit) = parity_bit {
+            frame |= (parity_bit as u16) << bits;
+            bits += 1;
         }
 
-        // Emmit parity
-        let p = word.count_ones() % 2;
-        match self.parity {
-            ParityMode::Even => {
-                self.tx_pin.set_state((p != 0).into()).unwrap();
-                spin_for(Duration::from_nanos(1_000_000_000/self.baud_rate as u64));
-            }
-            ParityMode::Odd => {
-                self.tx_pin.set_state((p != 0).into()).unwrap();
-                spin_for(Duration::from_nanos(1_000_000_000/self.baud_rate as u64));
-            },
-            ParityMode::None => {
+        for _i in 0..self.stop_bits as u8 {
+            frame |= 1 << bits;
+            bits += 1;
+        }
 
-            }
+        self.frame = frame;
+        self.frame_bits = bits;
+    }
+
+    /// Put the next bit of the current character on the wire.
+    ///
+    /// Returns `false`, and does nothing, once the last bit has been on the wire for a tick.
+    pub fn tick(&mut self) -> Result<bool, P::Error> {
+        if self.frame_bits == 0 {
+            return Ok(false);
         }
 
-        // Emmit stop bits
-        for _i in 0..self.stop_bits as u8 {
-            self.tx_pin.set_high().unwrap();
-            spin_for(Duration::from_nanos(1_000_000_000/self.baud_rate as u64));
+        self.tx_pin.set_state((self.frame & 1 != 0).into())?;
+        self.frame >>= 1;
+        self.frame_bits -= 1;
+
+        Ok(true)
+    }
+
+    /// Whether a character is still being sent.
+    pub fn is_busy(&self) -> bool {
+        self.frame_bits != 0
+    }
+
+    /// How late the latest bit edge put on the wire by `write()` was, since creation or the last
+    /// [`reset_lateness()`](Self::reset_lateness).
+    ///
+    /// A receiver samples in the middle of a bit, so anything approaching half a
+    /// [`bit_time()`](Self::bit_time) garbles characters.
+    pub fn worst_lateness(&self) -> Duration {
+        self.worst_lateness.duration()
+    }
+
+    pub fn reset_lateness(&mut self) {
+        self.worst_lateness = Overshoot::default();
+    }
+
+    /// Send `bytes` from an async task, letting the other tasks run between characters.
+    ///
+    /// Every character is still sent with `write()`, spinning for its bits.
+    pub async fn write_async(&mut self, bytes: &[u8]) -> Result<(), P::Error> {
+        for &byte in bytes {
+            nb::block!(Write::<u8>::write(self, byte))?;
+            executor::yield_now().await;
         }
 
-        // println!("End of transmission"); -- Maybe add debug print
         Ok(())
     }
+}
+
+impl <P> Write<u8> for SoftUartTransmitter<P>
+where
+    P: OutputPin,
+{
+    type Error = P::Error;
+
+    fn write(&mut self, word:u8) -> nb::Result<(), Self::Error>{
+        profile!("softuart write", {
+            self.start(word);
+
+            let start = Instant::now();
+            let mut bit = 0;
+            while self.tick()? {
+                bit += 1;
+                let overshoot = spin_until(start + bit_offset(self.baud_rate, bit));
+                self.worst_lateness = self.worst_lateness.max(overshoot);
+            }
+
+            Ok(())
+        })
+    }
 
     fn flush(&mut self) -> nb::Result<(), Self::Error> {
         // Does nothing, since there is no buffer
@@ -98,19 +191,232 @@ where
     }
 }
 
-impl <T> Write<&'static str> for SoftUartTransmitter<T>
+impl <P> Write<&'static str> for SoftUartTransmitter<P>
 where
-    T: AnyPin<Mode = PushPullOutput>
+    P: OutputPin,
 {
-    type Error = Infallible;
+    type Error = P::Error;
     fn flush(&mut self) -> nb::Result<(), Self::Error> {
         Ok(())
     }
 
     fn write(&mut self, word: &'static str) -> nb::Result<(), Self::Error> {
         for character in word.bytes() {
-            self.write(character).unwrap();
+            self.write(character)?;
         }
        Ok(())
     }
 }
+
+/// Bit-banged UART receiver on any input pin.
+///
+/// Characters are either received blocking with `read()`, which spins for every bit, or sampled
+/// by the caller's own bit clock with [`begin()`](SoftUartReceiver::begin) and
+/// [`tick()`](SoftUartReceiver::tick), e.g. started by the falling edge of the start bit.
+///
+/// There is no buffering, so with `read()` a character is only received if it is polled before
+/// its start bit ends. Good enough for typing, not for pasting.
+///
+/// Like the transmitter, it keeps track of how late it sampled, see
+/// [`worst_lateness()`](SoftUartReceiver::worst_lateness).
+pub struct SoftUartReceiver<P> where P: InputPin{
+    rx_pin: P,
+    baud_rate: u32,    // Baud rate in bauds/s
+    stop_bits: StopBitsOption,  // Number of stop bits
+    parity: ParityMode,  // Parity mode
+    frame_start: Option<Instant>,  // Start of the character being received
+    frame_bit: u8,  // Next bit of it to sample, 0 being the start bit
+    word: u8,  // Data bits sampled so far
+    worst_lateness: Overshoot,  // Latest sample so far
+}
+
+impl<P> SoftUartReceiver<P> where P: InputPin{
+    pub fn new(
+        rx_pin: P,
+        baud_rate: u32,    // Baud rate in bauds/s
+        stop_bits: StopBitsOption,  // Number of stop bits
+        parity: ParityMode,  // Parity mode
+    ) -> Self {
+        SoftUartReceiver{
+            rx_pin,
+            baud_rate,
+            stop_bits,
+            parity,
+            frame_start: None,
+            frame_bit: 0,
+            word: 0,
+            worst_lateness: Overshoot::default(),
+        }
+    }
+
+    pub fn get_baud_rate(&self) -> u32{
+        self.baud_rate
+    }
+
+    /// The pin received on, e.g. to detect start bits on it.
+    pub fn rx_pin(&self) -> &P {
+        &self.rx_pin
+    }
+
+    /// How late the latest sample was taken, relative to the middle of its bit, since creation or
+    /// the last [`reset_lateness()`](Self::reset_lateness). The time it took to see the start bit
+    /// at all is not included.
+    pub fn worst_lateness(&self) -> Duration {
+        self.worst_lateness.duration()
+    }
+
+    pub fn reset_lateness(&mut self) {
+        self.worst_lateness = Overshoot::default();
+    }
+
+    fn sample(&self) -> Result<bool, ReadError<P::Error>> {
+        self.rx_pin.is_high().map_err(ReadError::Pin)
+    }
+
+    /// Number of bits in a frame, including the start bit.
+    fn frame_bits(&self) -> u8 {
+        let parity_bits = match self.parity {
+            ParityMode::None => 0,
+            _ => 1,
+        };
+
+        9 + parity_bits + self.stop_bits as u8
+    }
+
+    /// Start a character whose start bit began at `start`, e.g. when its falling edge was seen.
+    /// Replaces the rest of a character still being received.
+    ///
+    /// Nothing is sampled yet. Every following [`tick()`](Self::tick) samples the next bit, so it
+    /// must be called at [`next_sample()`](Self::next_sample).
+    pub fn begin(&mut self, start: Instant) {
+        self.frame_start = Some(start);
+        self.frame_bit = 0;
+        self.word = 0;
+    }
+
+    /// Give up on the character being received, if any.
+    pub fn cancel(&mut self) {
+        self.frame_start = None;
+    }
+
+    /// Whether a character is being received.
+    pub fn is_busy(&self) -> bool {
+        self.frame_start.is_some()
+    }
+
+    /// When the next bit is due for sampling, i.e. its middle. `None` once the character is done.
+    ///
+    /// Timed from the start of the character, so the bit clock's lateness doesn't accumulate.
+    pub fn next_sample(&self) -> Option<Instant> {
+        let start = self.frame_start?;
+
+        Some(start + bit_offset(self.baud_rate, 2 * self.frame_bit as u64 + 1) / 2)
+    }
+
+    /// Sample the next bit of the current character.
+    ///
+    /// Returns the character once its last bit is sampled, and `WouldBlock` before. A start bit
+    /// that is high again was just a glitch, which ends the character with `WouldBlock` too, see
+    /// [`is_busy()`](Self::is_busy). So do parity and framing errors, with the error instead.
+    pub fn tick(&mut self) -> nb::Result<u8, ReadError<P::Error>> {
+        let Some(middle) = self.next_sample() else {
+            return Err(nb::Error::WouldBlock);
+        };
+        self.worst_lateness = self.worst_lateness.max(Overshoot::since(middle));
+
+        let bit = self.frame_bit;
+        self.frame_bit += 1;
+        let done = self.frame_bit == self.frame_bits();
+        if done {
+            self.frame_start = None;
+        }
+
+        let high = match self.sample() {
+            Ok(high) => high,
+            Err(e) => {
+                self.frame_start = None;
+                return Err(nb::Error::Other(e));
+            }
+        };
+
+        let odd_ones = self.word.count_ones() % 2 != 0;
+        let expected_parity = match self.parity {
+            ParityMode::Even => Some(odd_ones),
+            ParityMode::Odd => Some(!odd_ones),
+            ParityMode::None => None,
+        };
+
+        let error = match bit {
+            0 if high => {
+                // Just a glitch.
+                self.frame_start = None;
+                return Err(nb::Error::WouldBlock);
+            }
+            0 => None,
+            1..=8 => {
+                self.word |= (high as u8) << (bit - 1);
+                None
+            }
+            9 if expected_parity.is_some() => {
+                (Some(high) != expected_parity).then_some(ReadError::Parity)
+            }
+            _ => (!high).then_some(ReadError::Framing),
+        };
+
+        if let Some(error) = error {
+            self.frame_start = None;
+            return Err(nb::Error::Other(error));
+        }
+
+        if done {
+            Ok(self.word)
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Receive a character from an async task, letting the other tasks run while there is none.
+    ///
+    /// The line is checked each time the task is polled, and the task is woken again right away.
+    /// A character is only received if the other tasks yield before its start bit ends, and its
+    /// bits are then received spinning.
+    pub async fn read_async(&mut self) -> Result<u8, ReadError<P::Error>> {
+        poll_fn(|cx| match self.read() {
+            Ok(word) => Poll::Ready(Ok(word)),
+            Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+            Err(nb::Error::WouldBlock) => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+impl <P> Read<u8> for SoftUartReceiver<P>
+where
+    P: InputPin,
+{
+    type Error = ReadError<P::Error>;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error>{
+        // The line idles high, a low level is a start bit.
+        if self.sample()? {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.begin(Instant::now());
+
+        // Sample every bit in its middle, starting with the start bit.
+        while let Some(middle) = self.next_sample() {
+            spin_until(middle);
+
+            match self.tick() {
+                // More bits to come, unless it was just a glitch.
+                Err(nb::Error::WouldBlock) if self.is_busy() => continue,
+                result => return result,
+            }
+        }
+
+        Err(nb::Error::WouldBlock)
+    }
+}
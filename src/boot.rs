@@ -1,8 +1,8 @@
 use core::arch::asm;
 use crate::time::ARCH_TIMER_COUNTER_FREQUENCY;
 
-use aarch64_cpu::registers::MPIDR_EL1;
-use tock_registers::interfaces::Readable;
+use aarch64_cpu::{asm, registers::*};
+use tock_registers::interfaces::{Readable, Writeable};
 
 // Defined from linked script
 extern "C" {
@@ -83,12 +83,64 @@ pub unsafe fn setup_timer() {
 }
 
 
+/// Prepare the switch from EL2 to EL1, returning into `kernel_init()`.
+///
+/// The firmware starts the kernel in EL2. The kernel runs in EL1, where the EL1 physical timer and
+/// the exception vectors in `VBAR_EL1` live.
+#[inline(always)]
+unsafe fn prepare_el2_to_el1_transition() {
+    // Enable timer counter registers for EL1.
+    CNTHCTL_EL2.write(CNTHCTL_EL2::EL1PCEN::SET + CNTHCTL_EL2::EL1PCTEN::SET);
+
+    // No offset for reading the counters.
+    CNTVOFF_EL2.set(0);
+
+    // Give EL1 all PMU counters, untrapped: MDCR_EL2.HPMN = PMCR_EL0.N, all trap bits clear.
+    asm!("
+          mrs {0}, pmcr_el0
+          ubfx {0}, {0}, #11, #5
+          msr mdcr_el2, {0}
+      ",
+      out(reg) _,
+    );
+
+    // Set EL1 execution state to AArch64.
+    HCR_EL2.write(HCR_EL2::RW::EL1IsAarch64);
+
+    // Set up a simulated exception return.
+    //
+    // First, fake a saved program status where all interrupts were masked and SP_EL1 was used as a
+    // stack pointer.
+    SPSR_EL2.write(
+        SPSR_EL2::D::Masked
+            + SPSR_EL2::A::Masked
+            + SPSR_EL2::I::Masked
+            + SPSR_EL2::F::Masked
+            + SPSR_EL2::M::EL1h,
+    );
+
+    // Second, let the link register point to kernel_init().
+    ELR_EL2.set(crate::kernel_init as *const () as u64);
+
+    // Set up SP_EL1 (stack pointer), which will be used by EL1 once we "return" to it. Nothing
+    // from EL2 is needed anymore, so the whole boot core stack is reused.
+    SP_EL1.set(core::ptr::addr_of!(_stack_end) as u64);
+}
+
 #[allow(dead_code)]
 pub unsafe fn _start_rust() -> ! {
     hald_minors_cpus();
     zero_bss();
     setup_timer();
 
-    crate::kernel_init()
+    // Emulators and bootloaders might already have dropped to EL1.
+    if CurrentEL.read_as_enum(CurrentEL::EL) != Some(CurrentEL::EL::Value::EL2) {
+        crate::kernel_init()
+    }
+
+    prepare_el2_to_el1_transition();
+
+    // Use `eret` to "return" to EL1. This results in execution of kernel_init() in EL1.
+    asm::eret()
 }
 
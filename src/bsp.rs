@@ -1,4 +1,4 @@
+pub mod board;
 mod device_driver;
 
 pub use device_driver::*;
-
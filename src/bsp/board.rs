@@ -0,0 +1,177 @@
+//! Board description.
+//!
+//! Everything that differs between the supported Raspberry Pi models is collected in the [`Board`]
+//! trait. Exactly one board is selected at compile time through the `bsp_rpi3` or `bsp_rpi4` cargo
+//! feature, and is available as [`CurrentBoard`].
+//!
+//! # QEMU
+//!
+//! Both boards boot under their QEMU machine models:
+//!
+//! ```console
+//! $ cargo build --release --no-default-features --features bsp_rpi3
+//! $ qemu-system-aarch64 -M raspi3b -serial stdio -display none -kernel target/aarch64-unknown-none-softfloat/release/kernel
+//!
+//! $ cargo build --release --features bsp_rpi4
+//! $ qemu-system-aarch64 -M raspi4b -serial stdio -display none -kernel target/aarch64-unknown-none-softfloat/release/kernel
+//! ```
+
+#[cfg(all(feature = "bsp_rpi3", feature = "bsp_rpi4"))]
+compile_error!("Features `bsp_rpi3` and `bsp_rpi4` are mutually exclusive");
+
+#[cfg(not(any(feature = "bsp_rpi3", feature = "bsp_rpi4")))]
+compile_error!("Either feature `bsp_rpi3` or `bsp_rpi4` must be enabled");
+
+#[cfg(all(feature = "extra_uarts", not(feature = "bsp_rpi4")))]
+compile_error!("Feature `extra_uarts` requires `bsp_rpi4`");
+
+use crate::driver::IRQNumber;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// How the GPIO pull-up/down resistors are programmed.
+#[allow(dead_code)]
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum PullControl {
+    /// BCM2837: write the control signal to `GPPUD`, then clock it into the pins with `GPPUDCLKn`.
+    GppudClock,
+
+    /// BCM2711: two bits per pin in the `GPIO_PUP_PDN_CNTRL_REGn` registers.
+    PupPdn,
+}
+
+/// Board specific constants.
+pub trait Board {
+    /// Human readable board name.
+    const NAME: &'static str;
+
+    /// Start of the peripheral MMIO window, as seen by the ARM cores.
+    const MMIO_START: usize;
+
+    /// Reference clock of the PL011 UART in Hz. Set with `init_uart_clock` in `config.txt`.
+    const UART_CLOCK_HZ: u32;
+
+    /// VPU core clock in Hz, which also clocks the mini UART. Fixed by `enable_uart=1`.
+    const CORE_CLOCK_HZ: u32;
+
+    /// Nominal clock of the ARM cores in Hz, the default of `arm_freq` in `config.txt`.
+    const CPU_CLOCK_HZ: u32;
+
+    /// Number of GPIO pins of the SoC.
+    const GPIO_COUNT: u8;
+
+    /// GPIO driving the ACT LED, if it is directly wired to the SoC.
+    const ACT_LED_PIN: Option<u8>;
+
+    /// Pull-up/down programming scheme of the GPIO block.
+    const PULL_CONTROL: PullControl;
+
+    /// Interrupt number of the PL011 UARTs. All instances share a single line.
+    const PL011_UART_IRQ: IRQNumber;
+
+    /// Interrupt number of core 0's EL1 physical timer, `CNTP`.
+    const ARCH_TIMER_IRQ: IRQNumber;
+
+    /// Interrupt number of compare channel 0 of the System Timer. Channel n is n higher.
+    const SYSTEM_TIMER_IRQ: IRQNumber;
+
+    /// Interrupt number of the GPIO event detection, for the pins of all banks.
+    const GPIO_IRQ: IRQNumber;
+}
+
+/// Raspberry Pi 3 (BCM2837).
+#[allow(dead_code)]
+pub enum RaspberryPi3 {}
+
+/// Raspberry Pi 4 (BCM2711).
+#[allow(dead_code)]
+pub enum RaspberryPi4 {}
+
+/// The board selected at compile time.
+#[cfg(feature = "bsp_rpi3")]
+pub type CurrentBoard = RaspberryPi3;
+
+/// The board selected at compile time.
+#[cfg(feature = "bsp_rpi4")]
+pub type CurrentBoard = RaspberryPi4;
+
+/// Peripheral MMIO addresses of the current board.
+pub mod mmio {
+    use super::{Board, CurrentBoard};
+
+    pub const START: usize = CurrentBoard::MMIO_START;
+    pub const SYSTEM_TIMER_START: usize = START + 0x0000_3000;
+    pub const PM_START: usize = START + 0x0010_0000;
+    pub const GPIO_START: usize = START + 0x0020_0000;
+    pub const PL011_UART_START: usize = START + 0x0020_1000;
+    pub const AUX_START: usize = START + 0x0021_5000;
+
+    // Additional PL011 instances. BCM2711 only, see the `extra_uarts` feature.
+    #[cfg(feature = "extra_uarts")]
+    pub const PL011_UART3_START: usize = START + 0x0020_1600;
+    #[cfg(feature = "extra_uarts")]
+    pub const PL011_UART4_START: usize = START + 0x0020_1800;
+    #[cfg(feature = "extra_uarts")]
+    pub const PL011_UART5_START: usize = START + 0x0020_1A00;
+
+    // Interrupt controllers. The BCM2837 has the legacy peripheral controller plus the ARM local
+    // one, which is outside the peripheral window. The BCM2711 has a GIC-400.
+    #[cfg(feature = "bsp_rpi3")]
+    pub const PERIPHERAL_IC_START: usize = START + 0x0000_B200;
+    #[cfg(feature = "bsp_rpi3")]
+    pub const LOCAL_IC_START: usize = 0x4000_0000;
+    #[cfg(feature = "bsp_rpi4")]
+    pub const GICD_START: usize = 0xFF84_1000;
+    #[cfg(feature = "bsp_rpi4")]
+    pub const GICC_START: usize = 0xFF84_2000;
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Board for RaspberryPi3 {
+    const NAME: &'static str = "Raspberry Pi 3";
+    const MMIO_START: usize = 0x3F00_0000;
+    const UART_CLOCK_HZ: u32 = 48_000_000;
+    const CORE_CLOCK_HZ: u32 = 250_000_000;
+    const CPU_CLOCK_HZ: u32 = 1_200_000_000;
+    const GPIO_COUNT: u8 = 54;
+    // The ACT LED hangs off the firmware controlled GPIO expander.
+    const ACT_LED_PIN: Option<u8> = None;
+    const PULL_CONTROL: PullControl = PullControl::GppudClock;
+    // VideoCore IRQ 57, routed through the legacy interrupt controller.
+    const PL011_UART_IRQ: IRQNumber = 57;
+    // Source bit 1 of the local interrupt controller, which numbers its sources from 64 on.
+    const ARCH_TIMER_IRQ: IRQNumber = 64 + 1;
+    // VideoCore IRQs 0 to 3.
+    const SYSTEM_TIMER_IRQ: IRQNumber = 0;
+    // VideoCore IRQ 52, gpio_int[3]. IRQs 49 to 51 only cover a single bank each.
+    const GPIO_IRQ: IRQNumber = 52;
+}
+
+impl Board for RaspberryPi4 {
+    const NAME: &'static str = "Raspberry Pi 4";
+    const MMIO_START: usize = 0xFE00_0000;
+    const UART_CLOCK_HZ: u32 = 48_000_000;
+    const CORE_CLOCK_HZ: u32 = 500_000_000;
+    const CPU_CLOCK_HZ: u32 = 1_500_000_000;
+    const GPIO_COUNT: u8 = 58;
+    const ACT_LED_PIN: Option<u8> = Some(42);
+    const PULL_CONTROL: PullControl = PullControl::PupPdn;
+    // VideoCore IRQ 57, which the GIC-400 sees as SPI 96 + 57.
+    const PL011_UART_IRQ: IRQNumber = 153;
+    // PPI 14.
+    const ARCH_TIMER_IRQ: IRQNumber = 30;
+    // VideoCore IRQs 0 to 3, SPIs 96 to 99.
+    const SYSTEM_TIMER_IRQ: IRQNumber = 96;
+    // VideoCore IRQ 52, gpio_int[3], SPI 96 + 52.
+    const GPIO_IRQ: IRQNumber = 148;
+}
+
+/// Return the name of the board the kernel was built for.
+pub fn board_name() -> &'static str {
+    CurrentBoard::NAME
+}
@@ -1,43 +1,207 @@
+#[cfg(feature = "bsp_rpi4")]
+mod arm;
 mod bcm;
 mod common;
 
+#[cfg(feature = "bsp_rpi4")]
+pub use arm::*;
 pub use bcm::*;
+pub use common::MMIODerefWrapper;
 
-use crate::{bsp::device_driver, console, driver as generic_driver};
+use crate::{
+    bsp::{
+        board::{mmio, Board, CurrentBoard},
+        device_driver,
+    },
+    console, driver as generic_driver,
+    exception::{
+        self,
+        asynchronous::{interface::IRQHandler, IRQHandlerDescriptor},
+    },
+};
 use core::sync::atomic::{AtomicBool, Ordering};
 
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(feature = "extra_uarts")]
+struct ExtraUart {
+    number: usize,
+    uart: &'static device_driver::PL011Uart,
+    tx_pin: usize,
+    rx_pin: usize,
+    flow_control_pins: Option<(usize, usize)>,
+}
+
+/// The interrupt shared by all PL011 instances.
+struct PL011Interrupt;
+
 //--------------------------------------------------------------------------------------------------
 // Global instances
 //--------------------------------------------------------------------------------------------------
 
-static PL011_UART: device_driver::PL011Uart = unsafe { device_driver::PL011Uart::new() };
-pub static GPIO: device_driver::GPIO = unsafe { device_driver::GPIO::new() };
+static PL011_UART: device_driver::PL011Uart =
+    unsafe { device_driver::PL011Uart::new(mmio::PL011_UART_START, CurrentBoard::PL011_UART_IRQ) };
+static MINI_UART: device_driver::MiniUart = unsafe { device_driver::MiniUart::new() };
+pub static GPIO: device_driver::GPIO = unsafe { device_driver::GPIO::new(CurrentBoard::GPIO_IRQ) };
+static POWER_MANAGEMENT: device_driver::PowerManagement =
+    unsafe { device_driver::PowerManagement::new() };
+static SYSTEM_TIMER: device_driver::SystemTimer = unsafe {
+    device_driver::SystemTimer::new(mmio::SYSTEM_TIMER_START, CurrentBoard::SYSTEM_TIMER_IRQ)
+};
+static PL011_INTERRUPT: PL011Interrupt = PL011Interrupt;
+
+#[cfg(feature = "bsp_rpi3")]
+static INTERRUPT_CONTROLLER: device_driver::InterruptController = unsafe {
+    device_driver::InterruptController::new(mmio::PERIPHERAL_IC_START, mmio::LOCAL_IC_START)
+};
+#[cfg(feature = "bsp_rpi4")]
+static INTERRUPT_CONTROLLER: device_driver::GICv2 =
+    unsafe { device_driver::GICv2::new(mmio::GICD_START, mmio::GICC_START) };
+
+#[cfg(feature = "extra_uarts")]
+static PL011_UART3: device_driver::PL011Uart =
+    unsafe { device_driver::PL011Uart::new(mmio::PL011_UART3_START, CurrentBoard::PL011_UART_IRQ) };
+#[cfg(feature = "extra_uarts")]
+static PL011_UART4: device_driver::PL011Uart =
+    unsafe { device_driver::PL011Uart::new(mmio::PL011_UART4_START, CurrentBoard::PL011_UART_IRQ) };
+#[cfg(feature = "extra_uarts")]
+static PL011_UART5: device_driver::PL011Uart =
+    unsafe { device_driver::PL011Uart::new(mmio::PL011_UART5_START, CurrentBoard::PL011_UART_IRQ) };
+
+/// The additional BCM2711 PL011 instances, with their TX/RX and CTS/RTS pins on alternate
+/// function 4.
+///
+/// UART2 is left out, its TX/RX would be on pins 0 and 1, which belong to the timestamp UART.
+/// UART5's CTS/RTS would be on pins 14 and 15, which belong to the console.
+#[cfg(feature = "extra_uarts")]
+static EXTRA_UARTS: [ExtraUart; 3] = [
+    ExtraUart::new(3, &PL011_UART3, 4, 5, Some((6, 7))),
+    ExtraUart::new(4, &PL011_UART4, 8, 9, Some((10, 11))),
+    ExtraUart::new(5, &PL011_UART5, 12, 13, None),
+];
+
+/// CTS and RTS of the PL011 UART0 on alternate function 3. Identical on both boards.
+#[cfg(all(feature = "pl011_flow_control", not(feature = "console_mini_uart")))]
+const PL011_UART_FLOW_CONTROL_PINS: (usize, usize) = (16, 17);
 
 //--------------------------------------------------------------------------------------------------
 // Private Code
 //--------------------------------------------------------------------------------------------------
 
+#[cfg(feature = "extra_uarts")]
+impl ExtraUart {
+    const fn new(
+        number: usize,
+        uart: &'static device_driver::PL011Uart,
+        tx_pin: usize,
+        rx_pin: usize,
+        flow_control_pins: Option<(usize, usize)>,
+    ) -> Self {
+        Self {
+            number,
+            uart,
+            tx_pin,
+            rx_pin,
+            flow_control_pins,
+        }
+    }
+}
+
 /// This must be called only after successful init of the UART driver.
+#[cfg(not(feature = "console_mini_uart"))]
 fn post_init_uart() -> Result<(), &'static str> {
-    console::register_console(&PL011_UART);
+    console::register_console("PL011 UART0", &PL011_UART)?;
+
+    Ok(())
+}
+
+/// This must be called only after successful init of the mini UART driver.
+#[cfg(feature = "console_mini_uart")]
+fn post_init_mini_uart() -> Result<(), &'static str> {
+    console::register_console("Mini UART", &MINI_UART)?;
 
     Ok(())
 }
 
+/// Route CTS/RTS and switch the PL011 UARTs to hardware flow control.
+///
+/// Must run after the UARTs have been initialized.
+#[cfg(feature = "pl011_flow_control")]
+fn enable_pl011_flow_control() {
+    #[cfg(not(feature = "console_mini_uart"))]
+    {
+        let (cts_pin, rts_pin) = PL011_UART_FLOW_CONTROL_PINS;
+        GPIO.map_pl011_flow_control_pins(cts_pin, rts_pin, device_driver::AltFunction::Alt3);
+        PL011_UART.set_flow_control(device_driver::FlowControl::Hardware);
+    }
+
+    #[cfg(feature = "extra_uarts")]
+    for extra in &EXTRA_UARTS {
+        if let Some((cts_pin, rts_pin)) = extra.flow_control_pins {
+            GPIO.map_pl011_flow_control_pins(cts_pin, rts_pin, device_driver::AltFunction::Alt4);
+            extra
+                .uart
+                .set_flow_control(device_driver::FlowControl::Hardware);
+        }
+    }
+}
+
 /// This must be called only after successful init of the GPIO driver.
+///
+/// Pins 14 and 15 go to whichever UART is the console.
 fn post_init_gpio() -> Result<(), &'static str> {
+    #[cfg(not(feature = "console_mini_uart"))]
     GPIO.map_pl011_uart();
+    #[cfg(feature = "console_mini_uart")]
+    GPIO.map_mini_uart();
+
+    #[cfg(feature = "extra_uarts")]
+    for extra in &EXTRA_UARTS {
+        GPIO.map_pl011_uart_pins(extra.tx_pin, extra.rx_pin, device_driver::AltFunction::Alt4);
+    }
+
+    #[cfg(feature = "pl011_flow_control")]
+    enable_pl011_flow_control();
+
     Ok(())
 }
 
 fn driver_uart() -> Result<(), &'static str> {
-    let uart_descriptor =
-        generic_driver::DeviceDriverDescriptor::new(&PL011_UART, Some(post_init_uart));
+    #[cfg(not(feature = "console_mini_uart"))]
+    let post_init: Option<generic_driver::DeviceDriverPostInitCallback> = Some(post_init_uart);
+    #[cfg(feature = "console_mini_uart")]
+    let post_init: Option<generic_driver::DeviceDriverPostInitCallback> = None;
+
+    let uart_descriptor = generic_driver::DeviceDriverDescriptor::new(&PL011_UART, post_init);
     generic_driver::driver_manager().register_driver(uart_descriptor);
 
     Ok(())
 }
 
+fn driver_mini_uart() -> Result<(), &'static str> {
+    #[cfg(feature = "console_mini_uart")]
+    let post_init: Option<generic_driver::DeviceDriverPostInitCallback> = Some(post_init_mini_uart);
+    #[cfg(not(feature = "console_mini_uart"))]
+    let post_init: Option<generic_driver::DeviceDriverPostInitCallback> = None;
+
+    let mini_uart_descriptor = generic_driver::DeviceDriverDescriptor::new(&MINI_UART, post_init);
+    generic_driver::driver_manager().register_driver(mini_uart_descriptor);
+
+    Ok(())
+}
+
+#[cfg(feature = "extra_uarts")]
+fn driver_extra_uarts() -> Result<(), &'static str> {
+    for extra in &EXTRA_UARTS {
+        let uart_descriptor = generic_driver::DeviceDriverDescriptor::new(extra.uart, None);
+        generic_driver::driver_manager().register_driver(uart_descriptor);
+    }
+
+    Ok(())
+}
+
 fn driver_gpio() -> Result<(), &'static str> {
     let gpio_descriptor = generic_driver::DeviceDriverDescriptor::new(&GPIO, Some(post_init_gpio));
     generic_driver::driver_manager().register_driver(gpio_descriptor);
@@ -45,6 +209,62 @@ fn driver_gpio() -> Result<(), &'static str> {
     Ok(())
 }
 
+fn driver_power_management() -> Result<(), &'static str> {
+    let pm_descriptor = generic_driver::DeviceDriverDescriptor::new(&POWER_MANAGEMENT, None);
+    generic_driver::driver_manager().register_driver(pm_descriptor);
+
+    Ok(())
+}
+
+/// This must be called only after successful init of the interrupt controller.
+fn post_init_system_timer() -> Result<(), &'static str> {
+    SYSTEM_TIMER.register_irq_handlers()
+}
+
+fn driver_system_timer() -> Result<(), &'static str> {
+    let system_timer_descriptor =
+        generic_driver::DeviceDriverDescriptor::new(&SYSTEM_TIMER, Some(post_init_system_timer));
+    generic_driver::driver_manager().register_driver(system_timer_descriptor);
+
+    Ok(())
+}
+
+/// Hook up the interrupts of the drivers that are initialized before the interrupt controller.
+fn post_init_interrupt_controller() -> Result<(), &'static str> {
+    GPIO.register_irq_handler()?;
+
+    let irq_manager = exception::asynchronous::irq_manager();
+    irq_manager.register_handler(IRQHandlerDescriptor::new(
+        PL011_UART.irq_number(),
+        device_driver::PL011Uart::COMPATIBLE,
+        &PL011_INTERRUPT,
+    ))?;
+    irq_manager.enable(PL011_UART.irq_number())
+}
+
+fn driver_interrupt_controller() -> Result<(), &'static str> {
+    let interrupt_controller_descriptor = generic_driver::DeviceDriverDescriptor::new(
+        &INTERRUPT_CONTROLLER,
+        Some(post_init_interrupt_controller),
+    );
+    generic_driver::driver_manager().register_driver(interrupt_controller_descriptor);
+
+    Ok(())
+}
+
+impl IRQHandler for PL011Interrupt {
+    fn handle(&self) -> Result<(), &'static str> {
+        PL011_UART.handle_interrupt();
+
+        #[cfg(feature = "extra_uarts")]
+        for extra in &EXTRA_UARTS {
+            extra.uart.handle_interrupt();
+        }
+
+        Ok(())
+    }
+}
+
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
@@ -72,8 +292,67 @@ pub unsafe fn init() -> Result<(), &'static str> {
     }
 
     driver_uart()?;
+    driver_mini_uart()?;
+    #[cfg(feature = "extra_uarts")]
+    driver_extra_uarts()?;
     driver_gpio()?;
+    driver_power_management()?;
+    driver_interrupt_controller()?;
+    driver_system_timer()?;
 
     INIT_DONE.store(true, Ordering::Relaxed);
     Ok(())
 }
+
+/// Return the PL011 UART0.
+///
+/// Gives access to functionality beyond the console interface, like manual RTS/CTS handling.
+pub fn pl011_uart() -> &'static device_driver::PL011Uart {
+    &PL011_UART
+}
+
+/// Return the additional PL011 UART with the given number (3 to 5).
+///
+/// The instances are fully initialized once the driver manager has run, and can be used
+/// concurrently with the console UART.
+#[cfg(feature = "extra_uarts")]
+pub fn extra_uart(number: usize) -> Option<&'static device_driver::PL011Uart> {
+    EXTRA_UARTS
+        .iter()
+        .find(|extra| extra.number == number)
+        .map(|extra| extra.uart)
+}
+
+/// Whether the given pin belongs to one of the additional PL011 UARTs, as TX, RX, CTS or RTS.
+#[cfg(feature = "extra_uarts")]
+pub fn is_extra_uart_pin(num: usize) -> bool {
+    EXTRA_UARTS.iter().any(|extra| {
+        [extra.tx_pin, extra.rx_pin].contains(&num)
+            || extra
+                .flow_control_pins
+                .is_some_and(|(cts_pin, rts_pin)| [cts_pin, rts_pin].contains(&num))
+    })
+}
+
+/// Return the System Timer.
+///
+/// Besides being a second clock next to the architectural counter, it has compare channels of its
+/// own.
+pub fn system_timer() -> &'static device_driver::SystemTimer {
+    &SYSTEM_TIMER
+}
+
+/// Return the power management block, which has the watchdog.
+pub fn power_management() -> &'static device_driver::PowerManagement {
+    &POWER_MANAGEMENT
+}
+
+/// Return a reference to the interrupt controller's IRQ manager.
+pub fn irq_manager() -> &'static (dyn exception::asynchronous::interface::IRQManager + Sync) {
+    &INTERRUPT_CONTROLLER
+}
+
+/// Reset the board.
+pub fn reboot() -> ! {
+    POWER_MANAGEMENT.reset()
+}
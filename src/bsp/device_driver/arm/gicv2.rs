@@ -0,0 +1,276 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020-2022 Andre Richter <andre.o.richter@gmail.com>
+
+//! GICv2 Driver - ARM Generic Interrupt Controller v2.
+//!
+//! The BCM2711 has a GIC-400. Only what a single core needs is used: the distributor routes every
+//! shared peripheral interrupt (SPI) to core 0, and the CPU interface lets all priorities through.
+//! Pending interrupts are acknowledged and handled one at a time until none is left.
+//!
+//! The firmware sets the GIC up for non-secure use and routes the interrupts to it instead of the
+//! legacy controller, as long as `enable_gic=1`, which is the default on the Raspberry Pi 4.
+//!
+//! # Resources
+//!
+//! - <https://developer.arm.com/documentation/ihi0048/b/>
+
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper,
+    driver::{self, IRQNumber},
+    exception::asynchronous::{interface, IRQHandlerDescriptor},
+    info, synchronization,
+    synchronization::IRQSafeNullLock,
+    warn,
+};
+use tock_registers::{
+    interfaces::{Readable, Writeable},
+    register_bitfields, register_structs,
+    registers::{ReadOnly, ReadWrite, WriteOnly},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+register_bitfields! {
+    u32,
+
+    /// Distributor Control Register.
+    GICD_CTLR [
+        Enable OFFSET(0) NUMBITS(1) []
+    ],
+
+    /// Interrupt Controller Type Register.
+    GICD_TYPER [
+        /// Number of implemented interrupt lines, in multiples of 32, minus one.
+        ITLinesNumber OFFSET(0) NUMBITS(5) []
+    ],
+
+    /// CPU Interface Control Register.
+    GICC_CTLR [
+        Enable OFFSET(0) NUMBITS(1) []
+    ],
+
+    /// Interrupt Priority Mask Register.
+    GICC_PMR [
+        /// Only interrupts of a higher priority, i.e. a lower value, are signaled.
+        Priority OFFSET(0) NUMBITS(8) []
+    ],
+
+    /// Interrupt Acknowledge Register.
+    GICC_IAR [
+        InterruptID OFFSET(0) NUMBITS(10) []
+    ],
+
+    /// End of Interrupt Register.
+    GICC_EOIR [
+        EOIINTID OFFSET(0) NUMBITS(10) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    DistributorRegisterBlock {
+        (0x000 => CTLR: ReadWrite<u32, GICD_CTLR::Register>),
+        (0x004 => TYPER: ReadOnly<u32, GICD_TYPER::Register>),
+        (0x008 => _reserved1),
+        (0x100 => ISENABLER: [ReadWrite<u32>; 32]),
+        (0x180 => _reserved2),
+        (0x800 => ITARGETSR: [ReadWrite<u32>; 256]),
+        (0xC00 => @END),
+    }
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    CPUInterfaceRegisterBlock {
+        (0x000 => CTLR: ReadWrite<u32, GICC_CTLR::Register>),
+        (0x004 => PMR: ReadWrite<u32, GICC_PMR::Register>),
+        (0x008 => _reserved1),
+        (0x00C => IAR: ReadOnly<u32, GICC_IAR::Register>),
+        (0x010 => EOIR: WriteOnly<u32, GICC_EOIR::Register>),
+        (0x014 => @END),
+    }
+}
+
+/// Abstraction for the associated MMIO registers.
+type DistributorRegisters = MMIODerefWrapper<DistributorRegisterBlock>;
+type CPUInterfaceRegisters = MMIODerefWrapper<CPUInterfaceRegisterBlock>;
+
+/// Interrupt IDs handled by the driver. The GIC-400 of the BCM2711 implements 256.
+const NUM_IRQS: usize = 256;
+
+/// Interrupt IDs 0 to 31 are the banked SGIs and PPIs of each core. SPIs start from here.
+const FIRST_SPI: usize = 32;
+
+/// Interrupt ID returned by IAR when nothing is pending.
+const SPURIOUS_IRQ: IRQNumber = 1023;
+
+/// Routes an SPI to core 0, for each of the four SPIs of an `ITARGETSR` register.
+const ALL_TO_CORE_0: u32 = 0x0101_0101;
+
+struct GICv2Inner {
+    gicd: DistributorRegisters,
+    gicc: CPUInterfaceRegisters,
+    handler_table: [Option<IRQHandlerDescriptor>; NUM_IRQS],
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Representation of the GIC.
+pub struct GICv2 {
+    inner: IRQSafeNullLock<GICv2Inner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl GICv2Inner {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide correct MMIO start addresses.
+    pub const unsafe fn new(gicd_mmio_start_addr: usize, gicc_mmio_start_addr: usize) -> Self {
+        Self {
+            gicd: DistributorRegisters::new(gicd_mmio_start_addr),
+            gicc: CPUInterfaceRegisters::new(gicc_mmio_start_addr),
+            handler_table: [None; NUM_IRQS],
+        }
+    }
+
+    /// Number of interrupt IDs the distributor implements, limited to the handler table.
+    fn num_irqs(&self) -> usize {
+        let lines = self.gicd.TYPER.read(GICD_TYPER::ITLinesNumber) as usize + 1;
+
+        (lines * 32).min(NUM_IRQS)
+    }
+
+    fn init(&mut self) {
+        // Route all SPIs to core 0. The targets of SGIs and PPIs are fixed.
+        for n in FIRST_SPI / 4..self.num_irqs() / 4 {
+            self.gicd.ITARGETSR[n].set(ALL_TO_CORE_0);
+        }
+        self.gicd.CTLR.write(GICD_CTLR::Enable::SET);
+
+        // Let all priorities through.
+        self.gicc.PMR.write(GICC_PMR::Priority.val(255));
+        self.gicc.CTLR.write(GICC_CTLR::Enable::SET);
+    }
+
+    fn enable(&mut self, irq_number: IRQNumber) -> Result<(), &'static str> {
+        if irq_number >= self.num_irqs() {
+            return Err("IRQ number out of range");
+        }
+
+        // Set-enable registers ignore zero bits, so no read-modify-write.
+        self.gicd.ISENABLER[irq_number / 32].set(1 << (irq_number % 32));
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl GICv2 {
+    pub const COMPATIBLE: &'static str = "GICv2 (ARM Generic Interrupt Controller v2)";
+
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide correct MMIO start addresses.
+    pub const unsafe fn new(gicd_mmio_start_addr: usize, gicc_mmio_start_addr: usize) -> Self {
+        Self {
+            inner: IRQSafeNullLock::new(GICv2Inner::new(
+                gicd_mmio_start_addr,
+                gicc_mmio_start_addr,
+            )),
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+use synchronization::interface::Mutex;
+
+impl driver::interface::DeviceDriver for GICv2 {
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+
+    unsafe fn init(&self) -> Result<(), &'static str> {
+        self.inner.lock(|inner| inner.init());
+
+        Ok(())
+    }
+}
+
+impl interface::IRQManager for GICv2 {
+    fn register_handler(&self, descriptor: IRQHandlerDescriptor) -> Result<(), &'static str> {
+        self.inner.lock(|inner| {
+            let slot = inner
+                .handler_table
+                .get_mut(descriptor.number())
+                .ok_or("IRQ number out of range")?;
+            if slot.is_some() {
+                return Err("IRQ handler already registered");
+            }
+
+            *slot = Some(descriptor);
+
+            Ok(())
+        })
+    }
+
+    fn enable(&self, irq_number: IRQNumber) -> Result<(), &'static str> {
+        self.inner.lock(|inner| inner.enable(irq_number))
+    }
+
+    fn handle_pending_irqs(&self) {
+        loop {
+            // Acknowledging marks the interrupt active, so it isn't signaled again until its end.
+            let (irq_number, descriptor) = self.inner.lock(|inner| {
+                let irq_number = inner.gicc.IAR.read(GICC_IAR::InterruptID) as IRQNumber;
+                let descriptor = inner.handler_table.get(irq_number).copied().flatten();
+
+                (irq_number, descriptor)
+            });
+            if irq_number == SPURIOUS_IRQ {
+                break;
+            }
+
+            // Called without the lock, so the handler can use the controller itself.
+            match descriptor {
+                None => panic!("No handler registered for IRQ {}", irq_number),
+                Some(descriptor) => {
+                    if let Err(x) = descriptor.handler().handle() {
+                        warn!("IRQ handler {}: {}", descriptor.name(), x);
+                    }
+                }
+            }
+
+            self.inner.lock(|inner| {
+                inner
+                    .gicc
+                    .EOIR
+                    .write(GICC_EOIR::EOIINTID.val(irq_number as u32))
+            });
+        }
+    }
+
+    fn enumerate(&self) {
+        self.inner.lock(|inner| {
+            for descriptor in inner.handler_table.iter().flatten() {
+                info!("      {: >3}. {}", descriptor.number(), descriptor.name());
+            }
+        });
+    }
+}
@@ -5,11 +5,19 @@
 //! GPIO Driver.
 
 use crate::{
-    bsp::device_driver::common::MMIODerefWrapper, driver, synchronization,
-    synchronization::NullLock,
+    bsp::{
+        board::{mmio, Board, CurrentBoard, PullControl},
+        device_driver::common::MMIODerefWrapper,
+    },
+    driver::{self, IRQNumber},
+    exception::asynchronous::{self, interface, IRQHandlerDescriptor},
+    synchronization,
+    synchronization::IRQSafeNullLock,
+    time::{self, Instant},
 };
+use core::time::Duration;
 use tock_registers::{
-    interfaces::{ReadWriteable, Writeable},
+    interfaces::{ReadWriteable, Readable, Writeable},
     register_bitfields, register_structs,
     registers::ReadWrite,
 };
@@ -18,6 +26,10 @@ use tock_registers::{
 // Private Definitions
 //--------------------------------------------------------------------------------------------------
 
+/// Time between the steps of the `GPPUD`/`GPPUDCLKn` sequence. The Linux 2837 GPIO driver waits
+/// 1 µs. Spun for, as the sequence runs with the driver locked.
+const PUD_DELAY: Duration = Duration::from_micros(1);
+
 // GPIO registers.
 //
 // Descriptions taken from
@@ -33,15 +45,16 @@ register_bitfields! {
         FSEL14 OFFSET(12) NUMBITS(3) [
             Input = 0b000,
             Output = 0b001,
-            AltFunc0 = 0b100  // PL011 UART TX
+            AltFunc0 = 0b100,  // PL011 UART TX
+            AltFunc5 = 0b010   // Mini UART TX
         ],
 
         /// Pin 15
         FSEL15 OFFSET(15) NUMBITS(3) [
             Input = 0b000,
             Output = 0b001,
-            AltFunc0 = 0b100  // PL011 UART RX
-
+            AltFunc0 = 0b100,  // PL011 UART RX
+            AltFunc5 = 0b010   // Mini UART RX
         ]
     ],
 
@@ -71,6 +84,35 @@ register_bitfields! {
     ],
 
 
+    /// GPIO Pull-up/down Register
+    ///
+    /// BCM2837 only.
+    GPPUD [
+        /// Controls the actuation of the internal pull-up/down control line to ALL the GPIO pins.
+        PUD OFFSET(0) NUMBITS(2) [
+            Off = 0b00,
+            PullDown = 0b01,
+            PullUp = 0b10
+        ]
+    ],
+
+    /// GPIO Pull-up/down Clock Register 0
+    ///
+    /// BCM2837 only.
+    GPPUDCLK0 [
+        /// Pin 15
+        PUDCLK15 OFFSET(15) NUMBITS(1) [
+            NoEffect = 0,
+            AssertClock = 1
+        ],
+
+        /// Pin 14
+        PUDCLK14 OFFSET(14) NUMBITS(1) [
+            NoEffect = 0,
+            AssertClock = 1
+        ]
+    ],
+
     /// GPIO Pull-up / Pull-down Register 0
     ///
     /// BCM2711 only.
@@ -91,7 +133,7 @@ register_bitfields! {
 
 register_structs! {
     RegisterBlock {
-        (0x00 => _reserved1),
+        (0x00 => gpfsel0: ReadWrite<u32>),
         (0x04 => gpfsel1: ReadWrite<u32, GPFSEL1::Register>),
         (0x08 => gpfsel2: ReadWrite<u32>),
         (0x0C => gpfsel3: ReadWrite<u32>),
@@ -128,6 +170,10 @@ register_structs! {
         (0x88 => gpafen0: ReadWrite<u32>),
         (0x8C => gpafen1: ReadWrite<u32>),
         (0x90 => _reserved12),
+        (0x94 => gppud: ReadWrite<u32, GPPUD::Register>),
+        (0x98 => gppudclk0: ReadWrite<u32, GPPUDCLK0::Register>),
+        (0x9C => gppudclk1: ReadWrite<u32>),
+        (0xA0 => _reserved13),
 
         (0xE4 => gpio_pup_pdn_cntrl_reg0: ReadWrite<u32, GPIO_PUP_PDN_CNTRL_REG0::Register>),
         (0xE8 => gpio_pup_pdn_cntrl_reg1: ReadWrite<u32>),
@@ -140,6 +186,9 @@ register_structs! {
 /// Abstraction for the associated MMIO registers.
 type Registers = MMIODerefWrapper<RegisterBlock>;
 
+/// Pins covered by the two banks of the event detect registers.
+const NUM_EVENT_PINS: usize = 64;
+
 struct GPIOInner {
     registers: Registers,
 }
@@ -148,17 +197,52 @@ struct GPIOInner {
 // Public Definitions
 //--------------------------------------------------------------------------------------------------
 
+/// Alternate pin functions, as encoded in the `GPFSELn` registers.
+#[allow(dead_code)]
+#[derive(Copy, Clone)]
+pub enum AltFunction {
+    Alt0 = 0b100,
+    Alt1 = 0b101,
+    Alt2 = 0b110,
+    Alt3 = 0b111,
+    Alt4 = 0b011,
+    Alt5 = 0b010,
+}
+
+/// Pin pull resistor configuration.
+#[allow(dead_code)]
+#[derive(Copy, Clone)]
+pub enum Pull {
+    None,
+    Up,
+    Down,
+}
+
+/// Pin level transitions the event detection can report.
+#[allow(dead_code)]
+#[derive(Copy, Clone)]
+pub enum Edge {
+    Rising,
+    Falling,
+    Both,
+}
+
+/// Called in interrupt context when an edge is detected, with the pin number and the time the
+/// interrupt was taken.
+pub type EdgeCallback = fn(usize, Instant);
+
 /// Representation of the GPIO HW.
 pub struct GPIO {
-    inner: NullLock<GPIOInner>,
+    /// Also locked by the interrupt handler, to take the detected events.
+    inner: IRQSafeNullLock<GPIOInner>,
+    edge_callbacks: IRQSafeNullLock<[Option<EdgeCallback>; NUM_EVENT_PINS]>,
+    irq_number: IRQNumber,
 }
 
 //--------------------------------------------------------------------------------------------------
 // Private Code
 //--------------------------------------------------------------------------------------------------
 
-const GPIO_START: usize = 0xFE20_0000; // Based on section 1.2 of manual
-
 impl GPIOInner {
     /// Create an instance.
     ///
@@ -167,7 +251,7 @@ impl GPIOInner {
     /// - The user must ensure to provide a correct MMIO start address.
     pub const unsafe fn new() -> Self {
         Self {
-            registers: Registers::new(GPIO_START),
+            registers: Registers::new(mmio::GPIO_START),
         }
     }
 
@@ -183,6 +267,20 @@ impl GPIOInner {
         self.registers.gpclr1.write(GPCLR1::CLR14::Clear);
     }
 
+    /// Disable pull-up/down on pins 14 and 15.
+    fn disable_pud_14_15_bcm2837(&mut self) {
+        self.registers.gppud.write(GPPUD::PUD::Off);
+        time::spin_for(PUD_DELAY);
+
+        self.registers
+            .gppudclk0
+            .write(GPPUDCLK0::PUDCLK15::AssertClock + GPPUDCLK0::PUDCLK14::AssertClock);
+        time::spin_for(PUD_DELAY);
+
+        self.registers.gppud.write(GPPUD::PUD::Off);
+        self.registers.gppudclk0.set(0);
+    }
+
     /// Disable pull-up/down on pins 14 and 15.
     fn disable_pud_14_15_bcm2711(&mut self) {
         self.registers.gpio_pup_pdn_cntrl_reg0.write(
@@ -191,6 +289,155 @@ impl GPIOInner {
         );
     }
 
+    /// Disable pull-up/down on pins 14 and 15, using the board's pull control scheme.
+    fn disable_pud_14_15(&mut self) {
+        match CurrentBoard::PULL_CONTROL {
+            PullControl::GppudClock => self.disable_pud_14_15_bcm2837(),
+            PullControl::PupPdn => self.disable_pud_14_15_bcm2711(),
+        }
+    }
+
+    /// Enable detection of `edge` on a single pin, or disable it with `None`. Clears an event that
+    /// is still pending from before.
+    fn set_edge_detect(&mut self, pin: usize, edge: Option<Edge>) {
+        let mask = 1 << (pin % 32);
+        let regs = &self.registers;
+        let (gpren, gpfen, gpeds) = match pin / 32 {
+            0 => (&regs.gpren0, &regs.gpfen0, &regs.gpeds0),
+            1 => (&regs.gpren1, &regs.gpfen1, &regs.gpeds1),
+            _ => unreachable!(),
+        };
+        let (rising, falling) = match edge {
+            None => (false, false),
+            Some(Edge::Rising) => (true, false),
+            Some(Edge::Falling) => (false, true),
+            Some(Edge::Both) => (true, true),
+        };
+
+        let update = |value: u32, enable: bool| if enable { value | mask } else { value & !mask };
+        gpren.set(update(gpren.get(), rising));
+        gpfen.set(update(gpfen.get(), falling));
+        gpeds.set(mask);
+    }
+
+    /// Acknowledge the detected events and return them, one bit per pin.
+    fn take_events(&mut self) -> u64 {
+        let regs = &self.registers;
+        let low = regs.gpeds0.get();
+        let high = regs.gpeds1.get();
+
+        // Writing 1 clears an event, and with the last one the interrupt.
+        regs.gpeds0.set(low);
+        regs.gpeds1.set(high);
+
+        u64::from(high) << 32 | u64::from(low)
+    }
+
+    /// Select an alternate function for a single pin, leaving the other pins of the register
+    /// untouched.
+    fn set_alt_function(&mut self, pin: usize, function: AltFunction) {
+        let shift = (pin % 10) * 3;
+        let update = |value: u32| (value & !(0b111 << shift)) | ((function as u32) << shift);
+        let regs = &self.registers;
+
+        match pin / 10 {
+            0 => regs.gpfsel0.set(update(regs.gpfsel0.get())),
+            1 => regs.gpfsel1.set(update(regs.gpfsel1.get())),
+            2 => regs.gpfsel2.set(update(regs.gpfsel2.get())),
+            3 => regs.gpfsel3.set(update(regs.gpfsel3.get())),
+            4 => regs.gpfsel4.set(update(regs.gpfsel4.get())),
+            5 => regs.gpfsel5.set(update(regs.gpfsel5.get())),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Configure the pull resistor of a single pin with the `GPPUD`/`GPPUDCLKn` sequence.
+    fn set_pull_bcm2837(&mut self, pin: usize, pull: Pull) {
+        let pud = match pull {
+            Pull::None => GPPUD::PUD::Off,
+            Pull::Up => GPPUD::PUD::PullUp,
+            Pull::Down => GPPUD::PUD::PullDown,
+        };
+        let clock = 1 << (pin % 32);
+
+        self.registers.gppud.write(pud);
+        time::spin_for(PUD_DELAY);
+
+        match pin / 32 {
+            0 => self.registers.gppudclk0.set(clock),
+            _ => self.registers.gppudclk1.set(clock),
+        }
+        time::spin_for(PUD_DELAY);
+
+        self.registers.gppud.write(GPPUD::PUD::Off);
+        self.registers.gppudclk0.set(0);
+        self.registers.gppudclk1.set(0);
+    }
+
+    /// Configure the pull resistor of a single pin in the `GPIO_PUP_PDN_CNTRL_REGn` registers.
+    fn set_pull_bcm2711(&mut self, pin: usize, pull: Pull) {
+        let bits: u32 = match pull {
+            Pull::None => 0b00,
+            Pull::Up => 0b01,
+            Pull::Down => 0b10,
+        };
+        let shift = (pin % 16) * 2;
+        let update = |value: u32| (value & !(0b11 << shift)) | (bits << shift);
+        let regs = &self.registers;
+
+        match pin / 16 {
+            0 => regs
+                .gpio_pup_pdn_cntrl_reg0
+                .set(update(regs.gpio_pup_pdn_cntrl_reg0.get())),
+            1 => regs
+                .gpio_pup_pdn_cntrl_reg1
+                .set(update(regs.gpio_pup_pdn_cntrl_reg1.get())),
+            2 => regs
+                .gpio_pup_pdn_cntrl_reg2
+                .set(update(regs.gpio_pup_pdn_cntrl_reg2.get())),
+            3 => regs
+                .gpio_pup_pdn_cntrl_reg3
+                .set(update(regs.gpio_pup_pdn_cntrl_reg3.get())),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Configure the pull resistor of a single pin, using the board's pull control scheme.
+    fn set_pull(&mut self, pin: usize, pull: Pull) {
+        match CurrentBoard::PULL_CONTROL {
+            PullControl::GppudClock => self.set_pull_bcm2837(pin, pull),
+            PullControl::PupPdn => self.set_pull_bcm2711(pin, pull),
+        }
+    }
+
+    /// Map a PL011 UART to the given pins.
+    ///
+    /// RX gets a pull-up so that an unconnected line reads as idle instead of a break.
+    pub fn map_pl011_uart_pins(&mut self, tx_pin: usize, rx_pin: usize, function: AltFunction) {
+        self.set_alt_function(tx_pin, function);
+        self.set_alt_function(rx_pin, function);
+
+        self.set_pull(tx_pin, Pull::None);
+        self.set_pull(rx_pin, Pull::Up);
+    }
+
+    /// Route the CTS and RTS signals of a PL011 UART to the given pins.
+    ///
+    /// CTS is active low and gets a pull-up, so an unconnected line reads as "not clear to send"
+    /// instead of letting the UART transmit into the void.
+    pub fn map_pl011_flow_control_pins(
+        &mut self,
+        cts_pin: usize,
+        rts_pin: usize,
+        function: AltFunction,
+    ) {
+        self.set_alt_function(cts_pin, function);
+        self.set_alt_function(rts_pin, function);
+
+        self.set_pull(cts_pin, Pull::Up);
+        self.set_pull(rts_pin, Pull::None);
+    }
+
     /// Map PL011 UART as standard output.
     ///
     /// TX to pin 14
@@ -202,7 +449,21 @@ impl GPIOInner {
             .modify(GPFSEL1::FSEL15::AltFunc0 + GPFSEL1::FSEL14::AltFunc0);
 
         // Disable pull-up/down on pins 14 and 15.
-        self.disable_pud_14_15_bcm2711();
+        self.disable_pud_14_15();
+    }
+
+    /// Map the mini UART as standard output.
+    ///
+    /// TX to pin 14
+    /// RX to pin 15
+    pub fn map_mini_uart(&mut self) {
+        // Select the mini UART on pins 14 and 15.
+        self.registers
+            .gpfsel1
+            .modify(GPFSEL1::FSEL15::AltFunc5 + GPFSEL1::FSEL14::AltFunc5);
+
+        // Disable pull-up/down on pins 14 and 15.
+        self.disable_pud_14_15();
     }
 }
 
@@ -218,12 +479,62 @@ impl GPIO {
     /// # Safety
     ///
     /// - The user must ensure to provide a correct MMIO start address.
-    pub const unsafe fn new() -> Self {
+    pub const unsafe fn new(irq_number: IRQNumber) -> Self {
         Self {
-            inner: NullLock::new(GPIOInner::new()),
+            inner: IRQSafeNullLock::new(GPIOInner::new()),
+            edge_callbacks: IRQSafeNullLock::new([None; NUM_EVENT_PINS]),
+            irq_number,
         }
     }
 
+    /// Register and enable the event detection interrupt.
+    pub fn register_irq_handler(&'static self) -> Result<(), &'static str> {
+        let irq_manager = asynchronous::irq_manager();
+
+        irq_manager.register_handler(IRQHandlerDescriptor::new(
+            self.irq_number,
+            Self::COMPATIBLE,
+            self,
+        ))?;
+        irq_manager.enable(self.irq_number)
+    }
+
+    /// Call `callback`, in interrupt context, whenever `edge` is detected on pin `pin`.
+    ///
+    /// Replaces the pin's previous callback. The pin must be an input. Edges are detected
+    /// synchronously to the VPU clock, and an interrupt handler only runs after the ones already in
+    /// progress, so timestamps carry some jitter.
+    pub fn set_edge_callback(
+        &self,
+        pin: usize,
+        edge: Edge,
+        callback: EdgeCallback,
+    ) -> Result<(), &'static str> {
+        if pin >= CurrentBoard::GPIO_COUNT as usize {
+            return Err("No such pin");
+        }
+
+        self.edge_callbacks
+            .lock(|callbacks| callbacks[pin] = Some(callback));
+        self.inner
+            .lock(|inner| inner.set_edge_detect(pin, Some(edge)));
+
+        Ok(())
+    }
+
+    /// Stop detecting edges on a pin. Returns whether a callback was set.
+    pub fn clear_edge_callback(&self, pin: usize) -> Result<bool, &'static str> {
+        if pin >= CurrentBoard::GPIO_COUNT as usize {
+            return Err("No such pin");
+        }
+
+        self.inner.lock(|inner| inner.set_edge_detect(pin, None));
+
+        Ok(self
+            .edge_callbacks
+            .lock(|callbacks| callbacks[pin].take().is_some()))
+    }
+
     pub fn pin_42_config_output(&self) {
         self.inner.lock(|inner| inner.pin_42_config_output())
     }
@@ -240,6 +551,28 @@ impl GPIO {
     pub fn map_pl011_uart(&self) {
         self.inner.lock(|inner| inner.map_pl011_uart())
     }
+
+    /// Concurrency safe version of `GPIOInner.map_mini_uart()`
+    pub fn map_mini_uart(&self) {
+        self.inner.lock(|inner| inner.map_mini_uart())
+    }
+
+    /// Concurrency safe version of `GPIOInner.map_pl011_flow_control_pins()`
+    pub fn map_pl011_flow_control_pins(
+        &self,
+        cts_pin: usize,
+        rts_pin: usize,
+        function: AltFunction,
+    ) {
+        self.inner
+            .lock(|inner| inner.map_pl011_flow_control_pins(cts_pin, rts_pin, function))
+    }
+
+    /// Concurrency safe version of `GPIOInner.map_pl011_uart_pins()`
+    pub fn map_pl011_uart_pins(&self, tx_pin: usize, rx_pin: usize, function: AltFunction) {
+        self.inner
+            .lock(|inner| inner.map_pl011_uart_pins(tx_pin, rx_pin, function))
+    }
 }
 
 //------------------------------------------------------------------------------
@@ -252,3 +585,23 @@ impl driver::interface::DeviceDriver for GPIO {
         Self::COMPATIBLE
     }
 }
+
+impl interface::IRQHandler for GPIO {
+    fn handle(&self) -> Result<(), &'static str> {
+        // Taken first, so the work below doesn't add to the latency.
+        let timestamp = Instant::now();
+        let mut events = self.inner.lock(|inner| inner.take_events());
+
+        while events != 0 {
+            let pin = events.trailing_zeros() as usize;
+            events &= events - 1;
+
+            // Called without the lock, so callbacks can change the pin's detection.
+            if let Some(callback) = self.edge_callbacks.lock(|callbacks| callbacks[pin]) {
+                callback(pin, timestamp);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2020-2022 Andre Richter <andre.o.richter@gmail.com>
+
+//! Interrupt Controller Driver for the BCM2837.
+//!
+//! Two controllers are chained. The ARM local controller collects the per-core sources, like the
+//! architectural timers, and routes the combined VideoCore interrupt of the legacy peripheral
+//! controller to core 0.
+//!
+//! IRQ numbers 0 to 63 are the VideoCore peripheral interrupts. The local sources follow from 64
+//! on, by their bit in the core's interrupt source register: 64 is `CNTPSIRQ`, 65 `CNTPNSIRQ`, 66
+//! `CNTHPIRQ` and 67 `CNTVIRQ`. Only these four timers can be enabled from the local controller.
+
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper,
+    driver::{self, IRQNumber},
+    exception::asynchronous::{interface, IRQHandlerDescriptor},
+    info, synchronization,
+    synchronization::IRQSafeNullLock,
+    warn,
+};
+use tock_registers::{
+    interfaces::{Readable, Writeable},
+    register_structs,
+    registers::{ReadOnly, ReadWrite, WriteOnly},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+register_structs! {
+    #[allow(non_snake_case)]
+    PeripheralRegisterBlock {
+        (0x00 => _reserved1),
+        (0x04 => PENDING_1: ReadOnly<u32>),
+        (0x08 => PENDING_2: ReadOnly<u32>),
+        (0x0C => _reserved2),
+        (0x10 => ENABLE_1: WriteOnly<u32>),
+        (0x14 => ENABLE_2: WriteOnly<u32>),
+        (0x18 => @END),
+    }
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    LocalRegisterBlock {
+        (0x00 => _reserved1),
+        (0x40 => CORE0_TIMER_INTERRUPT_CONTROL: ReadWrite<u32>),
+        (0x44 => _reserved2),
+        (0x60 => CORE0_INTERRUPT_SOURCE: ReadOnly<u32>),
+        (0x64 => @END),
+    }
+}
+
+/// Abstraction for the associated MMIO registers.
+type PeripheralRegisters = MMIODerefWrapper<PeripheralRegisterBlock>;
+type LocalRegisters = MMIODerefWrapper<LocalRegisterBlock>;
+
+/// Number of VideoCore peripheral interrupts.
+const NUM_PERIPHERAL_IRQS: usize = 64;
+
+/// Number of local interrupt sources, i.e. bits in the core's interrupt source register.
+const NUM_LOCAL_IRQS: usize = 12;
+
+const NUM_IRQS: usize = NUM_PERIPHERAL_IRQS + NUM_LOCAL_IRQS;
+
+/// Local sources that can be enabled: the four architectural timers.
+const NUM_LOCAL_TIMER_IRQS: usize = 4;
+
+/// Bit of the local interrupt source register signaling a peripheral interrupt.
+const LOCAL_SOURCE_GPU: u32 = 1 << 8;
+
+struct InterruptControllerInner {
+    periph: PeripheralRegisters,
+    local: LocalRegisters,
+    /// Peripheral interrupts enabled so far. The enable registers are write-only.
+    periph_enabled: u64,
+    handler_table: [Option<IRQHandlerDescriptor>; NUM_IRQS],
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Representation of the BCM2837 interrupt controllers.
+pub struct InterruptController {
+    inner: IRQSafeNullLock<InterruptControllerInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl InterruptControllerInner {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide correct MMIO start addresses.
+    pub const unsafe fn new(periph_mmio_start_addr: usize, local_mmio_start_addr: usize) -> Self {
+        Self {
+            periph: PeripheralRegisters::new(periph_mmio_start_addr),
+            local: LocalRegisters::new(local_mmio_start_addr),
+            periph_enabled: 0,
+            handler_table: [None; NUM_IRQS],
+        }
+    }
+
+    fn enable(&mut self, irq_number: IRQNumber) -> Result<(), &'static str> {
+        match irq_number {
+            // Enable registers ignore zero bits, so no read-modify-write.
+            0..=31 => self.periph.ENABLE_1.set(1 << irq_number),
+            32..=63 => self.periph.ENABLE_2.set(1 << (irq_number - 32)),
+            _ => {
+                let bit = irq_number - NUM_PERIPHERAL_IRQS;
+                if bit >= NUM_LOCAL_TIMER_IRQS {
+                    return Err("Only the local timer IRQs can be enabled");
+                }
+
+                let control = self.local.CORE0_TIMER_INTERRUPT_CONTROL.get();
+                self.local
+                    .CORE0_TIMER_INTERRUPT_CONTROL
+                    .set(control | 1 << bit);
+                return Ok(());
+            }
+        }
+        self.periph_enabled |= 1 << irq_number;
+
+        Ok(())
+    }
+
+    /// Bitmask of the pending interrupts, peripheral ones in the lower 64 bits.
+    fn pending(&self) -> u128 {
+        let local = self.local.CORE0_INTERRUPT_SOURCE.get() & ((1 << NUM_LOCAL_IRQS) - 1);
+
+        let mut periph = 0;
+        if local & LOCAL_SOURCE_GPU != 0 {
+            periph = (u64::from(self.periph.PENDING_2.get()) << 32)
+                | u64::from(self.periph.PENDING_1.get());
+        }
+
+        // The GPU source only stands for the peripheral interrupts.
+        let local = local & !LOCAL_SOURCE_GPU;
+
+        u128::from(local) << NUM_PERIPHERAL_IRQS | u128::from(periph & self.periph_enabled)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl InterruptController {
+    pub const COMPATIBLE: &'static str = "BCM Interrupt Controller";
+
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide correct MMIO start addresses.
+    pub const unsafe fn new(periph_mmio_start_addr: usize, local_mmio_start_addr: usize) -> Self {
+        Self {
+            inner: IRQSafeNullLock::new(InterruptControllerInner::new(
+                periph_mmio_start_addr,
+                local_mmio_start_addr,
+            )),
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+use synchronization::interface::Mutex;
+
+impl driver::interface::DeviceDriver for InterruptController {
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+}
+
+impl interface::IRQManager for InterruptController {
+    fn register_handler(&self, descriptor: IRQHandlerDescriptor) -> Result<(), &'static str> {
+        self.inner.lock(|inner| {
+            let slot = inner
+                .handler_table
+                .get_mut(descriptor.number())
+                .ok_or("IRQ number out of range")?;
+            if slot.is_some() {
+                return Err("IRQ handler already registered");
+            }
+
+            *slot = Some(descriptor);
+
+            Ok(())
+        })
+    }
+
+    fn enable(&self, irq_number: IRQNumber) -> Result<(), &'static str> {
+        if irq_number >= NUM_IRQS {
+            return Err("IRQ number out of range");
+        }
+
+        self.inner.lock(|inner| inner.enable(irq_number))
+    }
+
+    fn handle_pending_irqs(&self) {
+        let mut pending = self.inner.lock(|inner| inner.pending());
+
+        while pending != 0 {
+            let irq_number = pending.trailing_zeros() as IRQNumber;
+            pending &= pending - 1;
+
+            // Called without the lock, so the handler can use the controller itself.
+            match self.inner.lock(|inner| inner.handler_table[irq_number]) {
+                None => panic!("No handler registered for IRQ {}", irq_number),
+                Some(descriptor) => {
+                    if let Err(x) = descriptor.handler().handle() {
+                        warn!("IRQ handler {}: {}", descriptor.name(), x);
+                    }
+                }
+            }
+        }
+    }
+
+    fn enumerate(&self) {
+        self.inner.lock(|inner| {
+            for descriptor in inner.handler_table.iter().flatten() {
+                info!("      {: >3}. {}", descriptor.number(), descriptor.name());
+            }
+        });
+    }
+}
@@ -0,0 +1,443 @@
+//! Mini UART (AUX UART1) driver.
+//!
+//! The mini UART is clocked from the VPU core clock, so its baud rate is only stable if the core
+//! frequency is fixed. Setting `enable_uart=1` in `config.txt` takes care of that.
+//!
+//! # Resources
+//!
+//! - <https://github.com/raspberrypi/documentation/files/1888662/BCM2837-ARM-Peripherals.-.Revised.-.V2-1.pdf>
+//! - <https://datasheets.raspberrypi.org/bcm2711/bcm2711-peripherals.pdf>
+
+use crate::{
+    bsp::{
+        board::{mmio, Board, CurrentBoard},
+        device_driver::common::MMIODerefWrapper,
+    },
+    console, driver, synchronization,
+    synchronization::IRQSafeNullLock,
+};
+use core::{
+    fmt,
+    task::{Context, Poll},
+};
+use tock_registers::{
+    interfaces::{ReadWriteable, Readable, Writeable},
+    register_bitfields, register_structs,
+    registers::{ReadOnly, ReadWrite, WriteOnly},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+// Auxiliary peripheral and mini UART registers.
+//
+// Descriptions taken from section 2 of the BCM2835/BCM2711 ARM peripherals manuals.
+register_bitfields! {
+    u32,
+
+    /// Auxiliary Enables.
+    AUX_ENABLES [
+        /// If set the mini UART is enabled. The UART will immediately start receiving data,
+        /// especially if the UART1_RX line is low. If clear the mini UART is disabled. That also
+        /// disables any mini UART register access.
+        MINI_UART OFFSET(0) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ]
+    ],
+
+    /// Mini UART Interrupt Enable.
+    AUX_MU_IER [
+        /// If this bit is set the interrupt line is asserted whenever the transmit FIFO is empty.
+        TX_INT OFFSET(1) NUMBITS(1) [],
+
+        /// If this bit is set the interrupt line is asserted whenever the receive FIFO holds at
+        /// least 1 byte.
+        RX_INT OFFSET(0) NUMBITS(1) []
+    ],
+
+    /// Mini UART Interrupt Identify.
+    AUX_MU_IIR [
+        /// On write:
+        ///
+        /// - Writing with bit 1 set will clear the receive FIFO.
+        /// - Writing with bit 2 set will clear the transmit FIFO.
+        FIFO_CLEAR OFFSET(1) NUMBITS(2) [
+            Rx = 0b01,
+            Tx = 0b10,
+            All = 0b11
+        ]
+    ],
+
+    /// Mini UART Line Control.
+    AUX_MU_LCR [
+        /// If set the mini UART works in 8-bit mode. Bit 1 must be set as well, which the
+        /// datasheet fails to mention.
+        DATA_SIZE OFFSET(0) NUMBITS(2) [
+            SevenBit = 0b00,
+            EightBit = 0b11
+        ]
+    ],
+
+    /// Mini UART Line Status.
+    AUX_MU_LSR [
+        /// This bit is set if the transmit FIFO is empty and the transmitter is idle (finished
+        /// shifting out the last bit).
+        TX_IDLE OFFSET(6) NUMBITS(1) [],
+
+        /// This bit is set if the transmit FIFO can accept at least one byte.
+        TX_EMPTY OFFSET(5) NUMBITS(1) [],
+
+        /// This bit is set if there was a receiver overrun. That is: one or more characters
+        /// arrived whilst the receive FIFO was full. Cleared each time this register is read.
+        RX_OVERRUN OFFSET(1) NUMBITS(1) [],
+
+        /// This bit is set if the receive FIFO holds at least 1 symbol.
+        DATA_READY OFFSET(0) NUMBITS(1) []
+    ],
+
+    /// Mini UART Extra Control.
+    AUX_MU_CNTL [
+        /// If this bit is set the mini UART transmitter is enabled.
+        TX_ENABLE OFFSET(1) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
+
+        /// If this bit is set the mini UART receiver is enabled.
+        RX_ENABLE OFFSET(0) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ]
+    ],
+
+    /// Mini UART Extra Status.
+    AUX_MU_STAT [
+        /// These bits show how many symbols are stored in the transmit FIFO. The value is in the
+        /// range 0-8.
+        TX_FIFO_FILL_LEVEL OFFSET(24) NUMBITS(4) [],
+
+        /// These bits show how many symbols are stored in the receive FIFO. The value is in the
+        /// range 0-8.
+        RX_FIFO_FILL_LEVEL OFFSET(16) NUMBITS(4) []
+    ],
+
+    /// Mini UART Baudrate.
+    AUX_MU_BAUD [
+        /// Mini UART baudrate counter.
+        BAUDRATE OFFSET(0) NUMBITS(16) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    pub RegisterBlock {
+        (0x00 => AUX_IRQ: ReadOnly<u32>),
+        (0x04 => AUX_ENABLES: ReadWrite<u32, AUX_ENABLES::Register>),
+        (0x08 => _reserved1),
+        (0x40 => AUX_MU_IO: ReadWrite<u32>),
+        (0x44 => AUX_MU_IER: ReadWrite<u32, AUX_MU_IER::Register>),
+        (0x48 => AUX_MU_IIR: WriteOnly<u32, AUX_MU_IIR::Register>),
+        (0x4c => AUX_MU_LCR: WriteOnly<u32, AUX_MU_LCR::Register>),
+        (0x50 => AUX_MU_MCR: WriteOnly<u32>),
+        (0x54 => AUX_MU_LSR: ReadOnly<u32, AUX_MU_LSR::Register>),
+        (0x58 => AUX_MU_MSR: ReadOnly<u32>),
+        (0x5c => AUX_MU_SCRATCH: ReadWrite<u32>),
+        (0x60 => AUX_MU_CNTL: WriteOnly<u32, AUX_MU_CNTL::Register>),
+        (0x64 => AUX_MU_STAT: ReadOnly<u32, AUX_MU_STAT::Register>),
+        (0x68 => AUX_MU_BAUD: WriteOnly<u32, AUX_MU_BAUD::Register>),
+        (0x6c => @END),
+    }
+}
+
+/// Abstraction for the associated MMIO registers.
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+#[derive(PartialEq)]
+enum BlockingMode {
+    Blocking,
+    NonBlocking,
+}
+
+struct MiniUartInner {
+    registers: Registers,
+    chars_written: usize,
+    chars_read: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Representation of the mini UART.
+pub struct MiniUart {
+    inner: IRQSafeNullLock<MiniUartInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+const MINI_UART_START: usize = mmio::AUX_START;
+
+/// The baud rate the console is operated at.
+const BAUD_RATE: u32 = 921_600;
+
+/// Compute the `AUX_MU_BAUD` value for a given core clock and baud rate.
+///
+/// The mini UART generates `baud = core_clock / (8 * (AUX_MU_BAUD + 1))`. Solve for the register
+/// value and round to nearest.
+const fn baud_rate_register(core_clock_hz: u32, baud_rate: u32) -> u32 {
+    let divisor = 8 * baud_rate as u64;
+
+    (((core_clock_hz as u64 + (divisor / 2)) / divisor) - 1) as u32
+}
+
+impl MiniUartInner {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new() -> Self {
+        Self {
+            registers: Registers::new(MINI_UART_START),
+            chars_written: 0,
+            chars_read: 0,
+        }
+    }
+
+    /// Set up baud rate and characteristics.
+    ///
+    /// This results in 8N1 and 921_600 baud. The mini UART has no parity or stop bit
+    /// configuration, and its 16 bit divider only has integer steps:
+    ///
+    /// - Raspberry Pi 3, 250 MHz core clock: `250_000_000 / (8 * 34) = 919_118` (Error 0.27%).
+    /// - Raspberry Pi 4, 500 MHz core clock: `500_000_000 / (8 * 68) = 919_118` (Error 0.27%).
+    pub fn init(&mut self) {
+        // Flush any characters still queued from a previous instance, see the PL011 driver.
+        if self
+            .registers
+            .AUX_ENABLES
+            .matches_all(AUX_ENABLES::MINI_UART::Enabled)
+        {
+            self.flush();
+        }
+
+        // Enable the mini UART. This also makes its register accessible.
+        self.registers
+            .AUX_ENABLES
+            .modify(AUX_ENABLES::MINI_UART::Enabled);
+
+        // Disable RX/TX and interrupts while configuring.
+        self.registers.AUX_MU_CNTL.set(0);
+        self.registers.AUX_MU_IER.set(0);
+        self.registers.AUX_MU_MCR.set(0);
+
+        // Throw away whatever is still in the FIFOs.
+        self.registers.AUX_MU_IIR.write(AUX_MU_IIR::FIFO_CLEAR::All);
+
+        // 8N1 at the given baud rate.
+        self.registers
+            .AUX_MU_LCR
+            .write(AUX_MU_LCR::DATA_SIZE::EightBit);
+        self.registers.AUX_MU_BAUD.write(
+            AUX_MU_BAUD::BAUDRATE.val(baud_rate_register(CurrentBoard::CORE_CLOCK_HZ, BAUD_RATE)),
+        );
+
+        // Turn the UART on.
+        self.registers
+            .AUX_MU_CNTL
+            .write(AUX_MU_CNTL::TX_ENABLE::Enabled + AUX_MU_CNTL::RX_ENABLE::Enabled);
+    }
+
+    /// Send a character.
+    fn write_char(&mut self, c: char) {
+        // Spin until the TX FIFO can accept at least one more byte.
+        while !self
+            .registers
+            .AUX_MU_LSR
+            .matches_all(AUX_MU_LSR::TX_EMPTY::SET)
+        {
+            aarch64_cpu::asm::nop();
+        }
+
+        // Write the character to the buffer.
+        self.registers.AUX_MU_IO.set(c as u32);
+
+        self.chars_written += 1;
+    }
+
+    /// Block execution until the last buffered character has been physically put on the TX wire.
+    fn flush(&self) {
+        // Spin until the TX FIFO is empty and the last stop bit has been shifted out.
+        while !self
+            .registers
+            .AUX_MU_LSR
+            .matches_all(AUX_MU_LSR::TX_IDLE::SET)
+        {
+            aarch64_cpu::asm::nop();
+        }
+    }
+
+    /// Retrieve a character.
+    fn read_char_converting(&mut self, blocking_mode: BlockingMode) -> Option<char> {
+        // If RX FIFO is empty,
+        if !self
+            .registers
+            .AUX_MU_LSR
+            .matches_all(AUX_MU_LSR::DATA_READY::SET)
+        {
+            // immediately return in non-blocking mode.
+            if blocking_mode == BlockingMode::NonBlocking {
+                return None;
+            }
+
+            // Otherwise, wait until a char was received.
+            while !self
+                .registers
+                .AUX_MU_LSR
+                .matches_all(AUX_MU_LSR::DATA_READY::SET)
+            {
+                aarch64_cpu::asm::nop();
+            }
+        }
+
+        // Read one character.
+        let mut ret = self.registers.AUX_MU_IO.get() as u8 as char;
+
+        // Convert carrige return to newline.
+        if ret == '\r' {
+            ret = '\n'
+        }
+
+        // Update statistics.
+        self.chars_read += 1;
+
+        Some(ret)
+    }
+
+    /// Number of characters waiting in the RX FIFO.
+    fn rx_fifo_level(&self) -> u32 {
+        self.registers
+            .AUX_MU_STAT
+            .read(AUX_MU_STAT::RX_FIFO_FILL_LEVEL)
+    }
+}
+
+/// Implementing `core::fmt::Write` enables usage of the `format_args!` macros.
+///
+/// See the PL011 driver for details.
+impl fmt::Write for MiniUartInner {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.write_char(c);
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl MiniUart {
+    pub const COMPATIBLE: &'static str = "BCM Mini UART (AUX UART1)";
+
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new() -> Self {
+        Self {
+            inner: IRQSafeNullLock::new(MiniUartInner::new()),
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+use synchronization::interface::Mutex;
+
+impl driver::interface::DeviceDriver for MiniUart {
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+
+    unsafe fn init(&self) -> Result<(), &'static str> {
+        self.inner.lock(|inner| inner.init());
+
+        Ok(())
+    }
+}
+
+impl console::interface::Write for MiniUart {
+    /// Passthrough of `args` to the `core::fmt::Write` implementation, but guarded by a Mutex to
+    /// serialize access.
+    fn write_char(&self, c: char) {
+        self.inner.lock(|inner| inner.write_char(c));
+    }
+
+    /// Writes all bytes under a single lock, so a binary log frame can't be torn apart.
+    fn write_bytes(&self, bytes: &[u8]) {
+        self.inner.lock(|inner| {
+            for &byte in bytes {
+                inner.write_char(byte as char);
+            }
+        });
+    }
+
+    fn write_fmt(&self, args: core::fmt::Arguments) -> fmt::Result {
+        self.inner.lock(|inner| fmt::Write::write_fmt(inner, args))
+    }
+
+    fn flush(&self) {
+        self.inner.lock(|inner| inner.flush());
+    }
+}
+
+impl console::interface::Read for MiniUart {
+    fn read_char(&self) -> char {
+        self.inner
+            .lock(|inner| inner.read_char_converting(BlockingMode::Blocking).unwrap())
+    }
+
+    fn clear_rx(&self) {
+        // Read from the RX FIFO until its fill level drops to zero.
+        self.inner.lock(|inner| {
+            while inner.rx_fifo_level() > 0 {
+                inner.read_char_converting(BlockingMode::NonBlocking);
+            }
+        })
+    }
+
+    /// The AUX interrupt isn't used, so the task is woken right away to poll again.
+    fn poll_read_char(&self, cx: &mut Context<'_>) -> Poll<char> {
+        match self
+            .inner
+            .lock(|inner| inner.read_char_converting(BlockingMode::NonBlocking))
+        {
+            Some(c) => Poll::Ready(c),
+            None => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl console::interface::Statistics for MiniUart {
+    fn chars_written(&self) -> usize {
+        self.inner.lock(|inner| inner.chars_written)
+    }
+
+    fn chars_read(&self) -> usize {
+        self.inner.lock(|inner| inner.chars_read)
+    }
+}
+
+impl console::interface::All for MiniUart {}
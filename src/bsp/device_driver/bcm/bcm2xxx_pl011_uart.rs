@@ -10,12 +10,23 @@
 //! - <https://developer.arm.com/documentation/ddi0183/latest>
 
 use crate::{
-    bsp::device_driver::common::MMIODerefWrapper, console, driver, synchronization,
-    synchronization::NullLock,
+    bsp::{
+        board::{Board, CurrentBoard},
+        device_driver::common::MMIODerefWrapper,
+    },
+    console,
+    driver::{self, IRQNumber},
+    synchronization,
+    synchronization::IRQSafeNullLock,
+    time,
+};
+use core::{
+    fmt,
+    task::{Context, Poll, Waker},
+    time::Duration,
 };
-use core::fmt;
 use tock_registers::{
-    interfaces::{Readable, Writeable},
+    interfaces::{ReadWriteable, Readable, Writeable},
     register_bitfields, register_structs,
     registers::{ReadOnly, ReadWrite, WriteOnly},
 };
@@ -30,6 +41,27 @@ use tock_registers::{
 register_bitfields! {
     u32,
 
+    /// Data Register. On reads, the upper bits carry the error flags of the received character.
+    DR [
+        /// Overrun error. This bit is set to 1 if data is received and the receive FIFO is already
+        /// full.
+        OE OFFSET(11) NUMBITS(1) [],
+
+        /// Break error. This bit is set to 1 if a break condition was detected.
+        BE OFFSET(10) NUMBITS(1) [],
+
+        /// Parity error. When set to 1, it indicates that the parity of the received data
+        /// character does not match the parity that the EPS and SPS bits in LCR_H select.
+        PE OFFSET(9) NUMBITS(1) [],
+
+        /// Framing error. When set to 1, it indicates that the received character did not have a
+        /// valid stop bit.
+        FE OFFSET(8) NUMBITS(1) [],
+
+        /// Receive (read) data character. Transmit (write) data character.
+        DATA OFFSET(0) NUMBITS(8) []
+    ],
+
     /// Flag Register.
     FR [
         /// Transmit FIFO empty. The meaning of this bit depends on the state of the FEN bit in the
@@ -60,7 +92,11 @@ register_bitfields! {
         ///
         /// This bit is set as soon as the transmit FIFO becomes non-empty, regardless of whether
         /// the UART is enabled or not.
-        BUSY OFFSET(3) NUMBITS(1) []
+        BUSY OFFSET(3) NUMBITS(1) [],
+
+        /// Clear to send. This bit is the complement of the UART clear to send, nUARTCTS, modem
+        /// status input. That is, the bit is 1 when nUARTCTS is LOW.
+        CTS OFFSET(0) NUMBITS(1) []
     ],
 
     /// Integer Baud Rate Divisor.
@@ -77,6 +113,9 @@ register_bitfields! {
 
     /// Line Control Register.
     LCR_H [
+        /// Stick parity select. Not used by this driver.
+        SPS OFFSET(7) NUMBITS(1) [],
+
         /// Word length. These bits indicate the number of data bits transmitted or received in a
         /// frame.
         #[allow(clippy::enum_variant_names)]
@@ -96,11 +135,60 @@ register_bitfields! {
         FEN  OFFSET(4) NUMBITS(1) [
             FifosDisabled = 0,
             FifosEnabled = 1
+        ],
+
+        /// Two stop bits select. If this bit is set to 1, two stop bits are transmitted at the end
+        /// of the frame. The receive logic does not check for two stop bits being received.
+        STP2 OFFSET(3) NUMBITS(1) [
+            One = 0,
+            Two = 1
+        ],
+
+        /// Even parity select. Controls the type of parity the UART uses during transmission and
+        /// reception. Has no effect when parity is disabled by the PEN bit.
+        EPS OFFSET(2) NUMBITS(1) [
+            Odd = 0,
+            Even = 1
+        ],
+
+        /// Parity enable.
+        PEN OFFSET(1) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
         ]
     ],
 
     /// Control Register.
     CR [
+        /// CTS hardware flow control enable. If this bit is set to 1, CTS hardware flow control is
+        /// enabled. Data is only transmitted when the nUARTCTS signal is asserted.
+        CTSEN OFFSET(15) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
+
+        /// RTS hardware flow control enable. If this bit is set to 1, RTS hardware flow control is
+        /// enabled. Data is only requested when there is space in the receive FIFO for it to be
+        /// received.
+        RTSEN OFFSET(14) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
+
+        /// Request to send. This bit is the complement of the UART request to send, nUARTRTS,
+        /// modem status output. That is, when the bit is programmed to a 1 then nUARTRTS is LOW.
+        RTS OFFSET(11) NUMBITS(1) [
+            Deasserted = 0,
+            Asserted = 1
+        ],
+
+        /// Loopback enable. If this bit is set to 1, the UARTTXD path is fed through to the
+        /// UARTRXD path. Used for self-test.
+        LBE OFFSET(7) NUMBITS(1) [
+            Disabled = 0,
+            Enabled = 1
+        ],
+
         /// Receive enable. If this bit is set to 1, the receive section of the UART is enabled.
         /// Data reception occurs for either UART signals or SIR signals depending on the setting of
         /// the SIREN bit. When the UART is disabled in the middle of reception, it completes the
@@ -134,6 +222,33 @@ register_bitfields! {
         ]
     ],
 
+    /// Interrupt Mask Set/Clear Register. A bit set to 1 lets the interrupt through.
+    IMSC [
+        /// Receive timeout interrupt mask. Asserted when the receive FIFO is not empty and no more
+        /// data was received for 32 bit periods.
+        RTIM OFFSET(6) NUMBITS(1) [],
+
+        /// Transmit interrupt mask. Asserted when the transmit FIFO drops to or below the
+        /// programmed trigger level, half full by default.
+        TXIM OFFSET(5) NUMBITS(1) [],
+
+        /// Receive interrupt mask. Asserted when the receive FIFO reaches the programmed trigger
+        /// level, half full by default.
+        RXIM OFFSET(4) NUMBITS(1) []
+    ],
+
+    /// Masked Interrupt Status Register. The raw interrupt status, ANDed with the mask.
+    MIS [
+        /// Receive timeout masked interrupt status.
+        RTMIS OFFSET(6) NUMBITS(1) [],
+
+        /// Transmit masked interrupt status.
+        TXMIS OFFSET(5) NUMBITS(1) [],
+
+        /// Receive masked interrupt status.
+        RXMIS OFFSET(4) NUMBITS(1) []
+    ],
+
     /// Interrupt Clear Register.
     ICR [
         /// Meta field for all pending interrupts.
@@ -144,15 +259,18 @@ register_bitfields! {
 register_structs! {
     #[allow(non_snake_case)]
     pub RegisterBlock {
-        (0x00 => DR: ReadWrite<u32>),
+        (0x00 => DR: ReadWrite<u32, DR::Register>),
         (0x04 => _reserved1),
         (0x18 => FR: ReadOnly<u32, FR::Register>),
         (0x1c => _reserved2),
         (0x24 => IBRD: WriteOnly<u32, IBRD::Register>),
         (0x28 => FBRD: WriteOnly<u32, FBRD::Register>),
         (0x2c => LCR_H: WriteOnly<u32, LCR_H::Register>),
-        (0x30 => CR: WriteOnly<u32, CR::Register>),
+        (0x30 => CR: ReadWrite<u32, CR::Register>),
         (0x34 => _reserved3),
+        (0x38 => IMSC: ReadWrite<u32, IMSC::Register>),
+        (0x3c => _reserved4),
+        (0x40 => MIS: ReadOnly<u32, MIS::Register>),
         (0x44 => ICR: WriteOnly<u32, ICR::Register>),
         (0x48 => @END),
     }
@@ -169,24 +287,106 @@ enum BlockingMode {
 
 struct PL011UartInner {
     registers: Registers,
+    line_config: LineConfig,
+    flow_control: FlowControl,
     chars_written: usize,
     chars_read: usize,
+    /// The tasks waiting for the UART's interrupt, see [`PL011Uart::poll_read()`].
+    rx_waker: Option<Waker>,
+    tx_waker: Option<Waker>,
 }
 
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
 //--------------------------------------------------------------------------------------------------
 
+/// Flow control of the UART.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum FlowControl {
+    /// No flow control. `RTS` can be driven manually with [`PL011Uart::set_rts()`].
+    None,
+
+    /// `RTS` is driven by the RX FIFO fill level and transmission pauses while `CTS` is
+    /// deasserted.
+    Hardware,
+}
+
+/// Parity of a UART frame.
+#[allow(dead_code)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+/// Line settings of the UART.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct LineConfig {
+    pub baud_rate: u32,
+    /// 5 to 8.
+    pub data_bits: u8,
+    pub parity: Parity,
+    /// 1 or 2.
+    pub stop_bits: u8,
+}
+
+/// A single character that did not make it through the loopback unscathed.
+#[derive(Copy, Clone, Debug)]
+pub struct SelfTestFailure {
+    /// The line settings the character was sent with.
+    pub line_config: LineConfig,
+    /// The character that was sent.
+    pub sent: u8,
+    /// The raw `DR` value that came back, including the error flags. `None` on timeout.
+    pub received: Option<u32>,
+}
+
+/// Outcome of [`PL011Uart::self_test()`].
+#[derive(Copy, Clone, Debug)]
+pub struct SelfTestReport {
+    /// Number of line settings that were exercised.
+    pub line_configs_tested: usize,
+    /// Number of characters sent in total.
+    pub chars_sent: usize,
+    /// Number of characters that came back with the same value and without error flags.
+    pub chars_matched: usize,
+    /// The first mismatch, if any.
+    pub first_failure: Option<SelfTestFailure>,
+}
+
 /// Representation of the UART.
 pub struct PL011Uart {
-    inner: NullLock<PL011UartInner>,
+    inner: IRQSafeNullLock<PL011UartInner>,
+    irq_number: IRQNumber,
 }
 
 //--------------------------------------------------------------------------------------------------
 // Private Code
 //--------------------------------------------------------------------------------------------------
 
-const PL011_UART_START: usize = 0xFE20_1000;
+/// Line settings the self-test runs through, in addition to the currently configured one.
+const SELF_TEST_LINE_CONFIGS: [LineConfig; 4] = [
+    LineConfig::new(115_200, 8, Parity::None, 1),
+    LineConfig::new(19_200, 8, Parity::Even, 1),
+    LineConfig::new(9_600, 7, Parity::Odd, 2),
+    LineConfig::new(230_400, 5, Parity::Even, 2),
+];
+
+/// Characters sent at every line setting. Covers alternating bits, all zeros and all ones.
+const SELF_TEST_PATTERN: [u8; 8] = [0x55, 0xAA, 0x00, 0xFF, 0x0F, 0xF0, 0x3C, 0xC3];
+
+/// Compute the `(IBRD, FBRD)` pair for a given reference clock and baud rate.
+///
+/// The baud rate divisor is `clock / (16 * baud)`, with the fractional part stored in 1/64ths.
+/// Scaling everything by 64 up front yields both parts with a single rounded integer division.
+const fn baud_rate_divisors(clock_hz: u32, baud_rate: u32) -> (u32, u32) {
+    let clock_hz = clock_hz as u64;
+    let baud_rate = baud_rate as u64;
+    let divisor_x64 = ((clock_hz * 4) + (baud_rate / 2)) / baud_rate;
+
+    ((divisor_x64 >> 6) as u32, (divisor_x64 & 0x3f) as u32)
+}
 
 impl PL011UartInner {
     /// Create an instance.
@@ -194,19 +394,24 @@ impl PL011UartInner {
     /// # Safety
     ///
     /// - The user must ensure to provide a correct MMIO start address.
-    pub const unsafe fn new() -> Self {
+    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
         Self {
-            registers: Registers::new(PL011_UART_START),
+            registers: Registers::new(mmio_start_addr),
+            line_config: LineConfig::DEFAULT,
+            flow_control: FlowControl::None,
             chars_written: 0,
             chars_read: 0,
+            rx_waker: None,
+            tx_waker: None,
         }
     }
 
     /// Set up baud rate and characteristics.
     ///
-    /// This results in 8N1 and 921_600 baud.
+    /// By default, this results in 8N1 and 921_600 baud.
     ///
-    /// The calculation for the BRD is (we set the clock to 48 MHz in config.txt):
+    /// The reference clock is taken from the board description. The calculation for the BRD is
+    /// (we set the clock to 48 MHz in config.txt):
     /// `(48_000_000 / 16) / 921_600 = 3.2552083`.
     ///
     /// This means the integer part is `3` and goes into the `IBRD`.
@@ -242,17 +447,148 @@ impl PL011UartInner {
         // updated on a single write strobe generated by a LCR_H write. So, to internally update the
         // contents of IBRD or FBRD, a LCR_H write must always be performed at the end.
         //
-        // Set the baud rate, 8N1 and FIFO enabled.
-        self.registers.IBRD.write(IBRD::BAUD_DIVINT.val(3));
-        self.registers.FBRD.write(FBRD::BAUD_DIVFRAC.val(16));
-        self.registers
-            .LCR_H
-            .write(LCR_H::WLEN::EightBit + LCR_H::FEN::FifosEnabled);
+        // Set the baud rate, frame format and FIFO enabled.
+        self.configure_line(self.line_config);
 
         // Turn the UART on.
         self.registers
             .CR
             .write(CR::UARTEN::Enabled + CR::TXE::Enabled + CR::RXE::Enabled);
+        self.set_flow_control(self.flow_control);
+    }
+
+    /// Program baud rate and frame format. The UART must be disabled.
+    fn configure_line(&mut self, line_config: LineConfig) {
+        let (divint, divfrac) =
+            baud_rate_divisors(CurrentBoard::UART_CLOCK_HZ, line_config.baud_rate);
+        self.registers.IBRD.write(IBRD::BAUD_DIVINT.val(divint));
+        self.registers.FBRD.write(FBRD::BAUD_DIVFRAC.val(divfrac));
+
+        let wlen = match line_config.data_bits {
+            5 => LCR_H::WLEN::FiveBit,
+            6 => LCR_H::WLEN::SixBit,
+            7 => LCR_H::WLEN::SevenBit,
+            _ => LCR_H::WLEN::EightBit,
+        };
+        let parity = match line_config.parity {
+            Parity::None => LCR_H::PEN::Disabled,
+            Parity::Even => LCR_H::PEN::Enabled + LCR_H::EPS::Even,
+            Parity::Odd => LCR_H::PEN::Enabled + LCR_H::EPS::Odd,
+        };
+        let stop_bits = match line_config.stop_bits {
+            2 => LCR_H::STP2::Two,
+            _ => LCR_H::STP2::One,
+        };
+
+        // LCR_H must be written last, see `init()`.
+        self.registers
+            .LCR_H
+            .write(wlen + parity + stop_bits + LCR_H::FEN::FifosEnabled);
+    }
+
+    /// Send one character through the internal loopback and wait for it to come back.
+    ///
+    /// Returns the raw `DR` value, or `None` if nothing arrived within a generous timeout of
+    /// 100 character times.
+    fn loopback_char(&mut self, c: u8, line_config: LineConfig) -> Option<u32> {
+        let timeout = Duration::from_micros(100 * 12 * 1_000_000 / line_config.baud_rate as u64);
+
+        self.registers.DR.write(DR::DATA.val(c as u32));
+
+        let deadline = time::Deadline::after(timeout);
+        while self.registers.FR.matches_all(FR::RXFE::SET) {
+            if deadline.has_passed() {
+                return None;
+            }
+        }
+
+        Some(self.registers.DR.get())
+    }
+
+    /// See [`PL011Uart::self_test()`].
+    fn self_test(&mut self) -> SelfTestReport {
+        let mut report = SelfTestReport {
+            line_configs_tested: 0,
+            chars_sent: 0,
+            chars_matched: 0,
+            first_failure: None,
+        };
+        let current = self.line_config;
+
+        // Don't cut off what is still being sent with the original settings.
+        self.flush();
+
+        for line_config in core::iter::once(current).chain(SELF_TEST_LINE_CONFIGS) {
+            // Reconfigure with the UART off, loop TX back to RX and keep flow control out of the
+            // way.
+            self.registers.CR.set(0);
+            self.registers.ICR.write(ICR::ALL::CLEAR);
+            self.configure_line(line_config);
+            self.registers.CR.write(
+                CR::UARTEN::Enabled + CR::TXE::Enabled + CR::RXE::Enabled + CR::LBE::Enabled,
+            );
+
+            // Drop anything that arrived before the loopback was active.
+            while !self.registers.FR.matches_all(FR::RXFE::SET) {
+                self.registers.DR.get();
+            }
+
+            let data_mask = (1u32 << line_config.data_bits) - 1;
+            for c in SELF_TEST_PATTERN {
+                let sent = (c as u32 & data_mask) as u8;
+                let received = self.loopback_char(sent, line_config);
+
+                report.chars_sent += 1;
+                // Error flags live above the data bits, so a plain compare checks both.
+                if received == Some(sent as u32) {
+                    report.chars_matched += 1;
+                } else if report.first_failure.is_none() {
+                    report.first_failure = Some(SelfTestFailure {
+                        line_config,
+                        sent,
+                        received,
+                    });
+                }
+            }
+
+            report.line_configs_tested += 1;
+        }
+
+        // Restore the original configuration.
+        self.line_config = current;
+        self.init();
+
+        report
+    }
+
+    /// Enable or disable RTS/CTS hardware flow control.
+    fn set_flow_control(&mut self, flow_control: FlowControl) {
+        self.flow_control = flow_control;
+
+        match flow_control {
+            FlowControl::None => self
+                .registers
+                .CR
+                .modify(CR::CTSEN::Disabled + CR::RTSEN::Disabled),
+            FlowControl::Hardware => self
+                .registers
+                .CR
+                .modify(CR::CTSEN::Enabled + CR::RTSEN::Enabled),
+        }
+    }
+
+    /// Drive the RTS output manually. Has no effect while hardware flow control is enabled.
+    fn set_rts(&mut self, asserted: bool) {
+        if asserted {
+            self.registers.CR.modify(CR::RTS::Asserted);
+        } else {
+            self.registers.CR.modify(CR::RTS::Deasserted);
+        }
+    }
+
+    /// Return whether the remote end asserts CTS.
+    fn cts(&self) -> bool {
+        self.registers.FR.is_set(FR::CTS)
     }
 
     /// Send a character.
@@ -304,6 +640,18 @@ impl PL011UartInner {
 
         Some(ret)
     }
+
+    /// Send a character if there is room in the TX FIFO. Returns whether it was sent.
+    fn try_write_char(&mut self, c: char) -> bool {
+        if self.registers.FR.matches_all(FR::TXFF::SET) {
+            return false;
+        }
+
+        self.registers.DR.set(c as u32);
+        self.chars_written += 1;
+
+        true
+    }
 }
 
 /// Implementing `core::fmt::Write` enables usage of the `format_args!` macros, which in turn are
@@ -329,6 +677,45 @@ impl fmt::Write for PL011UartInner {
 // Public Code
 //--------------------------------------------------------------------------------------------------
 
+impl LineConfig {
+    /// 921_600 baud, 8N1.
+    pub const DEFAULT: Self = Self::new(921_600, 8, Parity::None, 1);
+
+    /// Create an instance.
+    pub const fn new(baud_rate: u32, data_bits: u8, parity: Parity, stop_bits: u8) -> Self {
+        Self {
+            baud_rate,
+            data_bits,
+            parity,
+            stop_bits,
+        }
+    }
+}
+
+impl fmt::Display for LineConfig {
+    /// Formats as e.g. `921600 baud 8N1`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Even => 'E',
+            Parity::Odd => 'O',
+        };
+
+        write!(
+            f,
+            "{} baud {}{}{}",
+            self.baud_rate, self.data_bits, parity, self.stop_bits
+        )
+    }
+}
+
+impl SelfTestReport {
+    /// Return whether every character came back intact.
+    pub fn passed(&self) -> bool {
+        self.first_failure.is_none()
+    }
+}
+
 impl PL011Uart {
     pub const COMPATIBLE: &'static str = "BCM PL011 UART";
 
@@ -337,9 +724,136 @@ impl PL011Uart {
     /// # Safety
     ///
     /// - The user must ensure to provide a correct MMIO start address.
-    pub const unsafe fn new() -> Self {
+    pub const unsafe fn new(mmio_start_addr: usize, irq_number: IRQNumber) -> Self {
         Self {
-            inner: NullLock::new(PL011UartInner::new()),
+            inner: IRQSafeNullLock::new(PL011UartInner::new(mmio_start_addr)),
+            irq_number,
+        }
+    }
+
+    /// Return the interrupt number this instance signals on.
+    pub fn irq_number(&self) -> IRQNumber {
+        self.irq_number
+    }
+
+    /// Select the flow control mode. The setting survives a re-init of the UART.
+    ///
+    /// The CTS and RTS pins must have been routed to the UART for hardware flow control to work.
+    pub fn set_flow_control(&self, flow_control: FlowControl) {
+        self.inner.lock(|inner| {
+            // Don't switch while characters are still on their way out.
+            inner.flush();
+            inner.set_flow_control(flow_control)
+        })
+    }
+
+    /// Drive the RTS output manually, e.g. for modem-style devices.
+    ///
+    /// Only has an effect while flow control is [`FlowControl::None`].
+    pub fn set_rts(&self, asserted: bool) {
+        self.inner.lock(|inner| inner.set_rts(asserted))
+    }
+
+    /// Return whether the remote end currently asserts CTS.
+    pub fn cts(&self) -> bool {
+        self.inner.lock(|inner| inner.cts())
+    }
+
+    /// Change baud rate and frame format. Pending output is flushed with the old settings first.
+    pub fn set_line_config(&self, line_config: LineConfig) {
+        self.inner.lock(|inner| {
+            inner.line_config = line_config;
+            inner.init()
+        })
+    }
+
+    /// Return the current baud rate and frame format.
+    pub fn line_config(&self) -> LineConfig {
+        self.inner.lock(|inner| inner.line_config)
+    }
+
+    /// Run a loopback self-test.
+    ///
+    /// Enables the internal loopback (`CR.LBE`), so no external wiring is needed and nothing
+    /// appears on the TX pin. A test pattern is sent with the current line settings and a set of
+    /// additional ones, and every received character is checked for its value and error flags.
+    /// The original configuration is restored afterwards.
+    ///
+    /// The UART can't be used for anything else while the test runs, so don't print from here.
+    pub fn self_test(&self) -> SelfTestReport {
+        self.inner.lock(|inner| inner.self_test())
+    }
+
+    /// Read a character if one arrived, for async readers.
+    ///
+    /// Otherwise returns `Pending`, and the RX interrupts wake the task of `cx` once characters
+    /// arrive. That takes [`handle_interrupt()`](Self::handle_interrupt) to be called from the
+    /// UART's interrupt handler.
+    pub fn poll_read(&self, cx: &mut Context<'_>) -> Poll<char> {
+        // IRQs stay masked from the check until the interrupt is unmasked, so none is missed.
+        self.inner.lock(|inner| {
+            if let Some(c) = inner.read_char_converting(BlockingMode::NonBlocking) {
+                return Poll::Ready(c);
+            }
+
+            inner.rx_waker = Some(cx.waker().clone());
+            inner
+                .registers
+                .IMSC
+                .modify(IMSC::RXIM::SET + IMSC::RTIM::SET);
+
+            Poll::Pending
+        })
+    }
+
+    /// Send a character if there is room in the TX FIFO, for async writers.
+    ///
+    /// Otherwise returns `Pending`, and the TX interrupt wakes the task of `cx` once the FIFO has
+    /// drained to half full, see [`poll_read()`](Self::poll_read).
+    pub fn poll_write(&self, cx: &mut Context<'_>, c: char) -> Poll<()> {
+        self.inner.lock(|inner| {
+            if inner.try_write_char(c) {
+                return Poll::Ready(());
+            }
+
+            inner.tx_waker = Some(cx.waker().clone());
+            inner.registers.IMSC.modify(IMSC::TXIM::SET);
+
+            Poll::Pending
+        })
+    }
+
+    /// Wake the tasks waiting for the interrupts that fired, and mask those again.
+    ///
+    /// The PL011 instances of a board share a single interrupt, so this does nothing if the
+    /// interrupt came from another instance.
+    pub fn handle_interrupt(&self) {
+        let (rx, tx) = self.inner.lock(|inner| {
+            let mis = inner.registers.MIS.extract();
+            let rx = mis.is_set(MIS::RXMIS) || mis.is_set(MIS::RTMIS);
+            let tx = mis.is_set(MIS::TXMIS);
+
+            if rx {
+                inner
+                    .registers
+                    .IMSC
+                    .modify(IMSC::RXIM::CLEAR + IMSC::RTIM::CLEAR);
+            }
+            if tx {
+                inner.registers.IMSC.modify(IMSC::TXIM::CLEAR);
+            }
+
+            (
+                if rx { inner.rx_waker.take() } else { None },
+                if tx { inner.tx_waker.take() } else { None },
+            )
+        });
+
+        if let Some(waker) = rx {
+            waker.wake();
+        }
+        if let Some(waker) = tx {
+            waker.wake();
         }
     }
 }
@@ -359,6 +873,16 @@ impl driver::interface::DeviceDriver for PL011Uart {
 
         Ok(())
     }
+
+    fn power_on_self_test(&self) -> Result<(), &'static str> {
+        let report = self.self_test();
+
+        match report.first_failure {
+            None => Ok(()),
+            Some(SelfTestFailure { received: None, .. }) => Err("Loopback timed out"),
+            Some(_) => Err("Loopback data mismatch"),
+        }
+    }
 }
 
 impl console::interface::Write for PL011Uart {
@@ -368,6 +892,15 @@ impl console::interface::Write for PL011Uart {
         self.inner.lock(|inner| inner.write_char(c));
     }
 
+    /// Writes all bytes under a single lock, so a binary log frame can't be torn apart.
+    fn write_bytes(&self, bytes: &[u8]) {
+        self.inner.lock(|inner| {
+            for &byte in bytes {
+                inner.write_char(byte as char);
+            }
+        });
+    }
+
     fn write_fmt(&self, args: core::fmt::Arguments) -> fmt::Result {
         // Fully qualified syntax for the call to `core::fmt::Write::write_fmt()` to increase
         // readability.
@@ -378,6 +911,10 @@ impl console::interface::Write for PL011Uart {
         // Spin until TX FIFO empty is set.
         self.inner.lock(|inner| inner.flush());
     }
+
+    fn poll_write_char(&self, cx: &mut Context<'_>, c: char) -> Poll<()> {
+        self.poll_write(cx, c)
+    }
 }
 
 impl console::interface::Read for PL011Uart {
@@ -394,6 +931,10 @@ impl console::interface::Read for PL011Uart {
             .is_some()
         {}
     }
+
+    fn poll_read_char(&self, cx: &mut Context<'_>) -> Poll<char> {
+        self.poll_read(cx)
+    }
 }
 
 impl console::interface::Statistics for PL011Uart {
@@ -0,0 +1,222 @@
+//! Power management (PM) block driver.
+//!
+//! Only the PM watchdog is used. It counts down and resets the whole chip when it expires, unless
+//! it is fed in time. A reboot arms it with a minimal timeout.
+//!
+//! The PM registers are not in the public datasheets. The layout used here follows the Linux
+//! `bcm2835_wdt` driver.
+
+use crate::{
+    bsp::{board::mmio, device_driver::common::MMIODerefWrapper},
+    driver, synchronization,
+    synchronization::NullLock,
+};
+use core::time::Duration;
+use tock_registers::{
+    interfaces::{ReadWriteable, Readable, Writeable},
+    register_bitfields, register_structs,
+    registers::{ReadOnly, ReadWrite},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+register_bitfields! {
+    u32,
+
+    /// Reset Control.
+    PM_RSTC [
+        /// Every write must carry this password, otherwise it is ignored.
+        PASSWD OFFSET(24) NUMBITS(8) [
+            Magic = 0x5a
+        ],
+
+        /// What happens when the watchdog expires.
+        WRCFG OFFSET(4) NUMBITS(2) [
+            Clear = 0b00,
+            FullReset = 0b10
+        ]
+    ],
+
+    /// Watchdog timer.
+    PM_WDOG [
+        /// Every write must carry this password, otherwise it is ignored.
+        PASSWD OFFSET(24) NUMBITS(8) [
+            Magic = 0x5a
+        ],
+
+        /// Remaining time until expiry, in ticks of 1/65536 s.
+        TIME OFFSET(0) NUMBITS(20) []
+    ]
+}
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        (0x00 => _reserved1),
+        (0x1C => RSTC: ReadWrite<u32, PM_RSTC::Register>),
+        (0x20 => RSTS: ReadOnly<u32>),
+        (0x24 => WDOG: ReadWrite<u32, PM_WDOG::Register>),
+        (0x28 => @END),
+    }
+}
+
+/// Abstraction for the associated MMIO registers.
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+struct PowerManagementInner {
+    registers: Registers,
+    /// Watchdog ticks the watchdog is set back to when fed.
+    timeout_ticks: u32,
+}
+
+/// Watchdog ticks until the reset is triggered.
+const RESET_TIMEOUT_TICKS: u32 = 10;
+
+const WATCHDOG_TICKS_PER_SEC: u64 = 65_536;
+
+/// Largest value of the 20-bit `PM_WDOG.TIME`, just under 16 seconds.
+const MAX_WATCHDOG_TICKS: u32 = (1 << 20) - 1;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Representation of the power management block.
+pub struct PowerManagement {
+    inner: NullLock<PowerManagementInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl PowerManagementInner {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+            timeout_ticks: 0,
+        }
+    }
+
+    /// Arm the watchdog to reset the chip after `ticks`.
+    fn start_watchdog(&mut self, ticks: u32) {
+        self.timeout_ticks = ticks;
+        self.feed_watchdog();
+        self.registers
+            .RSTC
+            .modify(PM_RSTC::PASSWD::Magic + PM_RSTC::WRCFG::FullReset);
+    }
+
+    /// Set the watchdog back to the full timeout.
+    fn feed_watchdog(&mut self) {
+        self.registers
+            .WDOG
+            .write(PM_WDOG::PASSWD::Magic + PM_WDOG::TIME.val(self.timeout_ticks));
+    }
+
+    fn stop_watchdog(&mut self) {
+        self.registers
+            .RSTC
+            .modify(PM_RSTC::PASSWD::Magic + PM_RSTC::WRCFG::Clear);
+    }
+
+    fn watchdog_running(&self) -> bool {
+        self.registers.RSTC.matches_all(PM_RSTC::WRCFG::FullReset)
+    }
+
+    /// Arm the watchdog with a short timeout and let it reset the chip.
+    fn reset(&mut self) {
+        self.start_watchdog(RESET_TIMEOUT_TICKS);
+    }
+}
+
+/// Convert a timeout to watchdog ticks, rounded up.
+fn watchdog_ticks(timeout: Duration) -> Result<u32, &'static str> {
+    let ticks = (timeout.as_nanos() * u128::from(WATCHDOG_TICKS_PER_SEC)).div_ceil(1_000_000_000);
+
+    match u32::try_from(ticks) {
+        Ok(0) => Err("Timeout too short"),
+        Ok(ticks) if ticks <= MAX_WATCHDOG_TICKS => Ok(ticks),
+        _ => Err("Timeout too long, the maximum is 16 s"),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl PowerManagement {
+    pub const COMPATIBLE: &'static str = "BCM Power Management";
+
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new() -> Self {
+        Self {
+            inner: NullLock::new(PowerManagementInner::new(mmio::PM_START)),
+        }
+    }
+
+    /// Start the watchdog, or restart it with a new timeout. The chip resets unless
+    /// [`Self::feed_watchdog()`] is called within `timeout`, which can be up to 16 s.
+    pub fn start_watchdog(&self, timeout: Duration) -> Result<(), &'static str> {
+        let ticks = watchdog_ticks(timeout)?;
+        self.inner.lock(|inner| inner.start_watchdog(ticks));
+
+        Ok(())
+    }
+
+    /// Set the watchdog back to its full timeout, if it runs.
+    pub fn feed_watchdog(&self) {
+        self.inner.lock(|inner| {
+            if inner.watchdog_running() {
+                inner.feed_watchdog();
+            }
+        })
+    }
+
+    /// Stop the watchdog.
+    pub fn stop_watchdog(&self) {
+        self.inner.lock(|inner| inner.stop_watchdog())
+    }
+
+    /// Time left until the watchdog resets the chip, if it runs.
+    pub fn watchdog_remaining(&self) -> Option<Duration> {
+        self.inner.lock(|inner| {
+            inner.watchdog_running().then(|| {
+                let ticks = u64::from(inner.registers.WDOG.read(PM_WDOG::TIME));
+
+                Duration::from_nanos(ticks * 1_000_000_000 / WATCHDOG_TICKS_PER_SEC)
+            })
+        })
+    }
+
+    /// Reset the whole chip. Does not return.
+    pub fn reset(&self) -> ! {
+        self.inner.lock(|inner| inner.reset());
+
+        loop {
+            aarch64_cpu::asm::wfe();
+        }
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+use synchronization::interface::Mutex;
+
+impl driver::interface::DeviceDriver for PowerManagement {
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+}
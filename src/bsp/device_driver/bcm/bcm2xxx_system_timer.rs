@@ -0,0 +1,260 @@
+//! System Timer driver.
+//!
+//! A free-running 64-bit counter at 1 MHz, clocked independently of the ARM cores, with four
+//! 32-bit compare channels matched against its lower half. Each channel has its own VideoCore
+//! interrupt.
+//!
+//! The GPU firmware uses channels 0 and 2, so only channels 1 and 3 are available.
+
+use crate::{
+    bsp::device_driver::common::MMIODerefWrapper,
+    driver::{self, IRQNumber},
+    exception::asynchronous::{self, interface, IRQHandlerDescriptor},
+    synchronization,
+    synchronization::IRQSafeNullLock,
+    time::clock,
+};
+use core::time::Duration;
+use tock_registers::{
+    interfaces::{Readable, Writeable},
+    register_structs,
+    registers::{ReadOnly, ReadWrite},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+register_structs! {
+    #[allow(non_snake_case)]
+    RegisterBlock {
+        /// Bit n is set once channel n matched. Writing 1 clears it, and the channel's interrupt.
+        (0x00 => CS: ReadWrite<u32>),
+        (0x04 => CLO: ReadOnly<u32>),
+        (0x08 => CHI: ReadOnly<u32>),
+        (0x0C => C: [ReadWrite<u32>; NUM_CHANNELS]),
+        (0x1C => @END),
+    }
+}
+
+/// Abstraction for the associated MMIO registers.
+type Registers = MMIODerefWrapper<RegisterBlock>;
+
+const NUM_CHANNELS: usize = 4;
+
+/// Channels left to the ARM cores by the GPU firmware.
+const FREE_CHANNELS: [usize; 2] = [1, 3];
+
+/// The counter runs at 1 MHz.
+const FREQUENCY_HZ: u32 = 1_000_000;
+
+/// Compare values are written this many ticks ahead at least, so the counter can't pass them
+/// before the write lands. A passed value would only match after the counter wrapped, 71 minutes
+/// later.
+const MIN_COMPARE_TICKS: u32 = 2;
+
+struct SystemTimerInner {
+    registers: Registers,
+    callbacks: [Option<CompareCallback>; NUM_CHANNELS],
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Called in interrupt context when a compare channel matches, with the channel number.
+pub type CompareCallback = fn(usize);
+
+/// Representation of the System Timer.
+pub struct SystemTimer {
+    inner: IRQSafeNullLock<SystemTimerInner>,
+    /// Interrupt of channel 0. The other channels follow.
+    irq_number: IRQNumber,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl SystemTimerInner {
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: usize) -> Self {
+        Self {
+            registers: Registers::new(mmio_start_addr),
+            callbacks: [None; NUM_CHANNELS],
+        }
+    }
+
+    /// Read the 64-bit counter, which takes two accesses.
+    fn ticks(&self) -> u64 {
+        loop {
+            let hi = self.registers.CHI.get();
+            let lo = self.registers.CLO.get();
+
+            // Read again if the lower half wrapped in between.
+            if self.registers.CHI.get() == hi {
+                return u64::from(hi) << 32 | u64::from(lo);
+            }
+        }
+    }
+
+    fn set_compare(&mut self, channel: usize, ticks: u32, callback: CompareCallback) {
+        let target = self
+            .registers
+            .CLO
+            .get()
+            .wrapping_add(ticks.max(MIN_COMPARE_TICKS));
+
+        self.callbacks[channel] = Some(callback);
+        // Drop a match that is still pending from before.
+        self.registers.CS.set(1 << channel);
+        self.registers.C[channel].set(target);
+    }
+
+    /// Acknowledge the matched channels and take their callbacks.
+    fn take_matched(&mut self) -> [Option<CompareCallback>; NUM_CHANNELS] {
+        let matched = self.registers.CS.get();
+        let mut due = [None; NUM_CHANNELS];
+
+        for channel in FREE_CHANNELS {
+            if matched & 1 << channel != 0 {
+                self.registers.CS.set(1 << channel);
+                due[channel] = self.callbacks[channel].take();
+            }
+        }
+
+        due
+    }
+}
+
+fn check_channel(channel: usize) -> Result<(), &'static str> {
+    if channel >= NUM_CHANNELS {
+        return Err("No such channel");
+    }
+    if !FREE_CHANNELS.contains(&channel) {
+        return Err("Channel used by the GPU firmware");
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl SystemTimer {
+    pub const COMPATIBLE: &'static str = "BCM System Timer";
+
+    /// Create an instance.
+    ///
+    /// # Safety
+    ///
+    /// - The user must ensure to provide a correct MMIO start address.
+    pub const unsafe fn new(mmio_start_addr: usize, irq_number: IRQNumber) -> Self {
+        Self {
+            inner: IRQSafeNullLock::new(SystemTimerInner::new(mmio_start_addr)),
+            irq_number,
+        }
+    }
+
+    /// Register and enable the interrupts of the free compare channels.
+    pub fn register_irq_handlers(&'static self) -> Result<(), &'static str> {
+        let irq_manager = asynchronous::irq_manager();
+
+        for channel in FREE_CHANNELS {
+            let irq_number = self.irq_number + channel;
+
+            irq_manager.register_handler(IRQHandlerDescriptor::new(
+                irq_number,
+                Self::COMPATIBLE,
+                self,
+            ))?;
+            irq_manager.enable(irq_number)?;
+        }
+
+        Ok(())
+    }
+
+    /// Call `callback` once, in interrupt context, when channel `channel` matches after `after`.
+    ///
+    /// Replaces whatever the channel was set to. Delays are rounded down to whole microseconds,
+    /// and must be shorter than the 71 minutes it takes the lower half of the counter to wrap.
+    pub fn set_compare(
+        &self,
+        channel: usize,
+        after: Duration,
+        callback: CompareCallback,
+    ) -> Result<(), &'static str> {
+        check_channel(channel)?;
+        let ticks = u32::try_from(after.as_micros()).map_err(|_| "Delay too long")?;
+
+        self.inner
+            .lock(|inner| inner.set_compare(channel, ticks, callback));
+
+        Ok(())
+    }
+
+    /// Stop a channel from calling back. Returns whether it was still set.
+    pub fn cancel_compare(&self, channel: usize) -> Result<bool, &'static str> {
+        check_channel(channel)?;
+
+        Ok(self
+            .inner
+            .lock(|inner| inner.callbacks[channel].take().is_some()))
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+use synchronization::interface::Mutex;
+
+impl driver::interface::DeviceDriver for SystemTimer {
+    fn compatible(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+
+    fn power_on_self_test(&self) -> Result<(), &'static str> {
+        let start = self.inner.lock(|inner| inner.ticks());
+        crate::time::spin_for(Duration::from_micros(10));
+
+        if self.inner.lock(|inner| inner.ticks()) == start {
+            return Err("Counter doesn't run");
+        }
+
+        Ok(())
+    }
+}
+
+impl clock::interface::Clock for SystemTimer {
+    fn name(&self) -> &'static str {
+        Self::COMPATIBLE
+    }
+
+    fn frequency(&self) -> u32 {
+        FREQUENCY_HZ
+    }
+
+    fn ticks(&self) -> u64 {
+        self.inner.lock(|inner| inner.ticks())
+    }
+}
+
+impl interface::IRQHandler for SystemTimer {
+    fn handle(&self) -> Result<(), &'static str> {
+        let due = self.inner.lock(|inner| inner.take_matched());
+
+        // Called without the lock, so callbacks can set the channel again.
+        for (channel, callback) in due.iter().enumerate() {
+            if let Some(callback) = callback {
+                callback(channel);
+            }
+        }
+
+        Ok(())
+    }
+}
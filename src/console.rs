@@ -4,28 +4,81 @@
 
 //! System console.
 
+mod multi_console;
 mod null_console;
+#[cfg(feature = "console_semihosting")]
+mod semihosting_console;
+#[cfg(feature = "console_soft_uart")]
+mod soft_uart_console;
 
-use crate::synchronization::{self, NullLock};
+use crate::{dmesg, print::LevelFilter};
+use core::{
+    future::poll_fn,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+pub use multi_console::MultiConsole;
 
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
 //--------------------------------------------------------------------------------------------------
 
+/// TX and RX pin of the bit-banged UART console. Free with every other feature combination.
+#[cfg(feature = "console_soft_uart")]
+pub const SOFT_UART_CONSOLE_PINS: (u8, u8) = (20, 21);
+
+/// Baud rate of the bit-banged UART console, 8N1.
+#[cfg(feature = "console_soft_uart")]
+pub const SOFT_UART_CONSOLE_BAUD_RATE: u32 = 19200;
+
 /// Console interfaces.
 pub mod interface {
-    use core::fmt;
+    use core::{
+        fmt,
+        task::{Context, Poll},
+    };
 
     /// Console write functions.
     pub trait Write {
         /// Write a single character.
         fn write_char(&self, c: char);
 
+        /// Write raw bytes, like a binary log frame, without any character encoding.
+        ///
+        /// Implementations must write the bytes as a whole, so that nothing printed meanwhile, e.g.
+        /// from an interrupt handler, ends up in between. The default writes every byte as a
+        /// `char`, which is only good enough for consoles without any other writers.
+        fn write_bytes(&self, bytes: &[u8]) {
+            for &byte in bytes {
+                self.write_char(byte as char);
+            }
+        }
+
         /// Write a Rust format string.
         fn write_fmt(&self, args: fmt::Arguments) -> fmt::Result;
 
+        /// Write a log message of the given level.
+        ///
+        /// Consoles that filter by level override this, everyone else just writes.
+        fn write_fmt_level(
+            &self,
+            _level: crate::print::Level,
+            args: fmt::Arguments,
+        ) -> fmt::Result {
+            self.write_fmt(args)
+        }
+
         /// Block until the last buffered character has been physically put on the TX wire.
         fn flush(&self);
+
+        /// Write a single character if there is room for it, for async writers.
+        ///
+        /// Consoles that can't be waited on without blocking keep the default, which writes
+        /// blocking.
+        fn poll_write_char(&self, _cx: &mut Context<'_>, c: char) -> Poll<()> {
+            self.write_char(c);
+            Poll::Ready(())
+        }
     }
 
     /// Console read functions.
@@ -37,6 +90,15 @@ pub mod interface {
 
         /// Clear RX buffers, if any.
         fn clear_rx(&self);
+
+        /// Read a single character if one arrived, for async readers.
+        ///
+        /// Returns `Pending` after arranging for the waker of `cx` to be woken once another poll
+        /// is worth it. Consoles that can't be waited on without blocking keep the default, which
+        /// reads blocking.
+        fn poll_read_char(&self, _cx: &mut Context<'_>) -> Poll<char> {
+            Poll::Ready(self.read_char())
+        }
     }
 
     /// Console statistics.
@@ -60,22 +122,120 @@ pub mod interface {
 // Global instances
 //--------------------------------------------------------------------------------------------------
 
-static CUR_CONSOLE: NullLock<&'static (dyn interface::All + Sync)> =
-    NullLock::new(&null_console::NULL_CONSOLE);
+/// The system console. Starts out with the kernel message buffer as its only sink.
+static MULTI_CONSOLE: MultiConsole =
+    MultiConsole::with_sink("dmesg", &dmesg::DMESG_CONSOLE, LevelFilter::Trace);
+
+/// Whether the kernel message buffer was already replayed to a real console.
+static REPLAYED: AtomicBool = AtomicBool::new(false);
 
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
-use synchronization::interface::Mutex;
 
-/// Register a new console.
-pub fn register_console(new_console: &'static (dyn interface::All + Sync)) {
-    CUR_CONSOLE.lock(|con| *con = new_console);
+/// Register a console as an additional sink of the system console.
+///
+/// The first console registered becomes the primary, which input is read from, and gets
+/// everything printed so far replayed from the kernel message buffer.
+pub fn register_console(
+    name: &'static str,
+    new_console: &'static (dyn interface::All + Sync),
+) -> Result<usize, &'static str> {
+    let index = MULTI_CONSOLE.add_sink(name, new_console, LevelFilter::Trace)?;
+
+    if MULTI_CONSOLE.primary_index().is_none() {
+        MULTI_CONSOLE.set_primary(index)?;
+    }
+
+    if !REPLAYED.swap(true, Ordering::Relaxed) {
+        dmesg::dump_to(new_console, false);
+    }
+
+    Ok(index)
+}
+
+/// Register the semihosting console, see [`semihosting_console`].
+#[cfg(feature = "console_semihosting")]
+pub fn register_semihosting_console() -> Result<usize, &'static str> {
+    register_console("semihosting", &semihosting_console::SEMIHOSTING_CONSOLE)
 }
 
-/// Return a reference to the currently registered console.
+/// Register the bit-banged UART console on [`SOFT_UART_CONSOLE_PINS`].
+///
+/// As it has a receiver, it becomes the primary and takes over input from any console registered
+/// before.
+///
+/// # Safety
+///
+/// - Nothing else must use the pins.
+#[cfg(feature = "console_soft_uart")]
+pub unsafe fn register_soft_uart_console() -> Result<usize, &'static str> {
+    use crate::{
+        bitbang::uart::{ParityMode, SoftUartReceiver, SoftUartTransmitter, StopBitsOption},
+        gpio::dynpin::{DynDisabled, DynInput, DynPin, DynPinId, DynPinMode},
+    };
+    use embedded_hal::digital::v2::OutputPin;
+
+    let (tx_num, rx_num) = SOFT_UART_CONSOLE_PINS;
+
+    // Idle high before the first start bit.
+    let mut tx_pin = DynPin::new(
+        DynPinId { num: tx_num },
+        DynPinMode::Disabled(DynDisabled::Floating),
+    );
+    tx_pin.into_push_pull_output();
+    tx_pin
+        .set_high()
+        .map_err(|_| "Cannot drive soft UART TX pin")?;
+
+    // Pulled up, so an unconnected RX doesn't read as a stream of start bits.
+    let mut rx_pin = DynPin::new(
+        DynPinId { num: rx_num },
+        DynPinMode::Disabled(DynDisabled::Floating),
+    );
+    rx_pin.into_mode(DynPinMode::Input(DynInput::PullUp));
+
+    soft_uart_console::SOFT_UART_CONSOLE.attach(
+        SoftUartTransmitter::new(
+            tx_pin,
+            SOFT_UART_CONSOLE_BAUD_RATE,
+            StopBitsOption::One,
+            ParityMode::None,
+        ),
+        Some(SoftUartReceiver::new(
+            rx_pin,
+            SOFT_UART_CONSOLE_BAUD_RATE,
+            StopBitsOption::One,
+            ParityMode::None,
+        )),
+    )?;
+
+    let index = register_console("Soft UART", &soft_uart_console::SOFT_UART_CONSOLE)?;
+    MULTI_CONSOLE.set_primary(index)?;
+
+    Ok(index)
+}
+
+/// Return a reference to the system console, for managing its sinks.
+pub fn multi_console() -> &'static MultiConsole {
+    &MULTI_CONSOLE
+}
+
+/// Return a reference to the system console.
 ///
 /// This is the global console used by all printing macros.
 pub fn console() -> &'static dyn interface::All {
-    CUR_CONSOLE.lock(|con| *con)
+    &MULTI_CONSOLE
+}
+
+/// Read a single character from `console`, letting other tasks run while there is none.
+pub async fn read_char_async(console: &(impl interface::Read + ?Sized)) -> char {
+    poll_fn(|cx| console.poll_read_char(cx)).await
+}
+
+/// Write a string to `console`, letting other tasks run while it has no room.
+pub async fn write_str_async(console: &(impl interface::Write + ?Sized), s: &str) {
+    for c in s.chars() {
+        poll_fn(|cx| console.poll_write_char(cx, c)).await;
+    }
 }
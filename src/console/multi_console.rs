@@ -0,0 +1,296 @@
+//! Console fanning out to several sinks.
+//!
+//! Output goes to every registered sink. Log messages are additionally filtered by each sink's
+//! level, while plain `print!` output always goes everywhere. Input is read from a single primary
+//! sink.
+
+use super::{interface, null_console};
+use crate::{
+    print::{Level, LevelFilter},
+    synchronization::{interface::Mutex, IRQSafeNullLock},
+};
+use core::{
+    fmt,
+    task::{Context, Poll},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const NUM_SINKS: usize = 6;
+
+type Console = &'static (dyn interface::All + Sync);
+
+struct MultiConsoleInner {
+    sinks: [Option<Sink>; NUM_SINKS],
+    primary: Option<usize>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A console registered with the [`MultiConsole`].
+#[derive(Copy, Clone)]
+pub struct Sink {
+    name: &'static str,
+    console: Console,
+    level: LevelFilter,
+}
+
+/// Console forwarding to all registered sinks.
+///
+/// Printing from interrupt handlers goes through it as well, so its sink table is locked with IRQs
+/// masked. Sinks are copied out of the lock before being written to.
+pub struct MultiConsole {
+    inner: IRQSafeNullLock<MultiConsoleInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl MultiConsoleInner {
+    const fn new() -> Self {
+        Self {
+            sinks: [None; NUM_SINKS],
+            primary: None,
+        }
+    }
+}
+
+impl MultiConsole {
+    /// Copy the sinks out of the lock, so they can be written to without holding it.
+    fn sinks(&self) -> [Option<Sink>; NUM_SINKS] {
+        self.inner.lock(|inner| inner.sinks)
+    }
+
+    fn for_each(&self, f: impl FnMut(&Sink)) {
+        self.sinks().iter().filter_map(|x| x.as_ref()).for_each(f)
+    }
+
+    fn primary(&self) -> Console {
+        self.inner.lock(|inner| {
+            inner
+                .primary
+                .and_then(|i| inner.sinks[i])
+                .map_or(&null_console::NULL_CONSOLE as Console, |sink| sink.console)
+        })
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Sink {
+    /// Return the name given at registration.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Return the console itself.
+    pub fn console(&self) -> &'static (dyn interface::All + Sync) {
+        self.console
+    }
+
+    /// Return the most verbose log level written to this sink.
+    pub fn level(&self) -> LevelFilter {
+        self.level
+    }
+}
+
+impl MultiConsole {
+    /// Create an instance without any sinks.
+    pub const fn new() -> Self {
+        Self {
+            inner: IRQSafeNullLock::new(MultiConsoleInner::new()),
+        }
+    }
+
+    /// Create an instance with a single sink, which is not used for input.
+    pub const fn with_sink(name: &'static str, console: Console, level: LevelFilter) -> Self {
+        let mut inner = MultiConsoleInner::new();
+        inner.sinks[0] = Some(Sink {
+            name,
+            console,
+            level,
+        });
+
+        Self {
+            inner: IRQSafeNullLock::new(inner),
+        }
+    }
+
+    /// Add a sink. Returns its index.
+    pub fn add_sink(
+        &self,
+        name: &'static str,
+        console: Console,
+        level: LevelFilter,
+    ) -> Result<usize, &'static str> {
+        self.inner.lock(|inner| {
+            let index = inner
+                .sinks
+                .iter()
+                .position(|x| x.is_none())
+                .ok_or("Too many console sinks")?;
+
+            inner.sinks[index] = Some(Sink {
+                name,
+                console,
+                level,
+            });
+
+            Ok(index)
+        })
+    }
+
+    /// Remove a sink. If it was the primary, there is no input until another primary is chosen.
+    pub fn remove_sink(&self, index: usize) -> Result<(), &'static str> {
+        self.inner.lock(|inner| {
+            inner
+                .sinks
+                .get_mut(index)
+                .and_then(|x| x.take())
+                .ok_or("No such console sink")?;
+
+            if inner.primary == Some(index) {
+                inner.primary = None;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Set the most verbose log level written to a sink.
+    pub fn set_level(&self, index: usize, level: LevelFilter) -> Result<(), &'static str> {
+        self.inner.lock(|inner| {
+            let sink = inner
+                .sinks
+                .get_mut(index)
+                .and_then(|x| x.as_mut())
+                .ok_or("No such console sink")?;
+            sink.level = level;
+
+            Ok(())
+        })
+    }
+
+    /// Select the sink input is read from.
+    pub fn set_primary(&self, index: usize) -> Result<(), &'static str> {
+        self.inner.lock(|inner| {
+            if inner.sinks.get(index).map_or(true, |x| x.is_none()) {
+                return Err("No such console sink");
+            }
+            inner.primary = Some(index);
+
+            Ok(())
+        })
+    }
+
+    /// Return the index of the sink input is read from.
+    pub fn primary_index(&self) -> Option<usize> {
+        self.inner.lock(|inner| inner.primary)
+    }
+
+    /// Call `f` with the index and description of every sink.
+    pub fn for_each_sink(&self, mut f: impl FnMut(usize, &Sink)) {
+        self.sinks()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, x)| x.as_ref().map(|x| (i, x)))
+            .for_each(|(i, x)| f(i, x))
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl interface::Write for MultiConsole {
+    fn write_char(&self, c: char) {
+        self.for_each(|sink| sink.console.write_char(c));
+    }
+
+    fn write_bytes(&self, bytes: &[u8]) {
+        self.for_each(|sink| sink.console.write_bytes(bytes));
+    }
+
+    fn write_fmt(&self, args: fmt::Arguments) -> fmt::Result {
+        let mut result = Ok(());
+        self.for_each(|sink| {
+            if sink.console.write_fmt(args).is_err() {
+                result = Err(fmt::Error);
+            }
+        });
+
+        result
+    }
+
+    fn write_fmt_level(&self, level: Level, args: fmt::Arguments) -> fmt::Result {
+        let mut result = Ok(());
+        self.for_each(|sink| {
+            if level as u8 <= sink.level as u8 && sink.console.write_fmt(args).is_err() {
+                result = Err(fmt::Error);
+            }
+        });
+
+        result
+    }
+
+    fn flush(&self) {
+        self.for_each(|sink| sink.console.flush());
+    }
+
+    /// Waits for room at the primary sink only. The others are written to blocking once it took
+    /// the character.
+    fn poll_write_char(&self, cx: &mut Context<'_>, c: char) -> Poll<()> {
+        let primary = self.primary_index();
+        let sinks = self.sinks();
+
+        if let Some(sink) = primary.and_then(|i| sinks[i]) {
+            if sink.console.poll_write_char(cx, c).is_pending() {
+                return Poll::Pending;
+            }
+        }
+
+        sinks
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| Some(*i) != primary)
+            .filter_map(|(_, x)| x.as_ref())
+            .for_each(|sink| sink.console.write_char(c));
+
+        Poll::Ready(())
+    }
+}
+
+/// Input comes from the primary sink only.
+impl interface::Read for MultiConsole {
+    fn read_char(&self) -> char {
+        self.primary().read_char()
+    }
+
+    fn clear_rx(&self) {
+        self.primary().clear_rx()
+    }
+
+    fn poll_read_char(&self, cx: &mut Context<'_>) -> Poll<char> {
+        self.primary().poll_read_char(cx)
+    }
+}
+
+/// Statistics are those of the primary sink.
+impl interface::Statistics for MultiConsole {
+    fn chars_written(&self) -> usize {
+        self.primary().chars_written()
+    }
+
+    fn chars_read(&self) -> usize {
+        self.primary().chars_read()
+    }
+}
+
+impl interface::All for MultiConsole {}
@@ -5,7 +5,10 @@
 //! Null console.
 
 use super::interface;
-use core::fmt;
+use core::{
+    fmt,
+    task::{Context, Poll},
+};
 
 //--------------------------------------------------------------------------------------------------
 // Public Definitions
@@ -35,6 +38,11 @@ impl interface::Write for NullConsole {
 
 impl interface::Read for NullConsole {
     fn clear_rx(&self) {}
+
+    /// There is never any input.
+    fn poll_read_char(&self, _cx: &mut Context<'_>) -> Poll<char> {
+        Poll::Pending
+    }
 }
 
 impl interface::Statistics for NullConsole {}
@@ -0,0 +1,112 @@
+//! Semihosting console.
+//!
+//! Talks to a debugger or emulator through ARM semihosting calls (`HLT #0xF000`). QEMU supports
+//! this with `-semihosting`, OpenOCD with `arm semihosting enable`.
+//!
+//! Without a debugger or emulator attached, the first call raises an undefined instruction
+//! exception, and the kernel panics. Hence this console is only built with the
+//! `console_semihosting` feature.
+
+use super::interface;
+use crate::exception::asynchronous;
+use core::{arch::asm, fmt};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Write the byte pointed to by the parameter.
+const SYS_WRITEC: u64 = 0x03;
+
+/// Read a byte from the debug console. Takes no parameter.
+const SYS_READC: u64 = 0x07;
+
+/// Used for `core::fmt::Write`, which needs a `&mut self`.
+struct SemihostingWriter;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+pub struct SemihostingConsole;
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+pub static SEMIHOSTING_CONSOLE: SemihostingConsole = SemihostingConsole {};
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Issue a semihosting call.
+///
+/// # Safety
+///
+/// - A debugger or emulator must be attached to handle the call.
+/// - `parameter` must be valid for `operation`.
+unsafe fn semihosting_call(operation: u64, parameter: u64) -> u64 {
+    let result;
+    asm!(
+        "hlt #0xf000",
+        inout("x0") operation => result,
+        in("x1") parameter,
+        options(nostack)
+    );
+
+    result
+}
+
+fn write_byte(byte: u8) {
+    unsafe { semihosting_call(SYS_WRITEC, &byte as *const u8 as u64) };
+}
+
+impl fmt::Write for SemihostingWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        s.bytes().for_each(write_byte);
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl interface::Write for SemihostingConsole {
+    fn write_char(&self, c: char) {
+        let mut buf = [0; 4];
+        c.encode_utf8(&mut buf).bytes().for_each(write_byte);
+    }
+
+    /// One call per byte, so IRQs are masked to keep other output from getting in between.
+    fn write_bytes(&self, bytes: &[u8]) {
+        asynchronous::exec_with_irq_masked(|| bytes.iter().copied().for_each(write_byte));
+    }
+
+    fn write_fmt(&self, args: fmt::Arguments) -> fmt::Result {
+        fmt::Write::write_fmt(&mut SemihostingWriter, args)
+    }
+
+    /// The host side is synchronous, nothing to wait for.
+    fn flush(&self) {}
+}
+
+impl interface::Read for SemihostingConsole {
+    fn read_char(&self) -> char {
+        let c = unsafe { semihosting_call(SYS_READC, 0) } as u8 as char;
+
+        // Convert carrige return to newline, like the UARTs do.
+        if c == '\r' {
+            '\n'
+        } else {
+            c
+        }
+    }
+
+    fn clear_rx(&self) {}
+}
+
+impl interface::Statistics for SemihostingConsole {}
+impl interface::All for SemihostingConsole {}
@@ -0,0 +1,545 @@
+//! Console on a bit-banged UART.
+//!
+//! Puts the console on any pair of GPIO pins, for boards where the PL011 pins are taken.
+//!
+//! Output goes through a small buffer and is shifted out by a periodic software timer running at
+//! the baud rate, so writers only wait when the buffer is full, and then sleep until the bit clock
+//! makes room. With IRQs masked, e.g. in a panic or a timer callback, there is no bit clock and
+//! output is sent right away, spinning for every bit.
+//!
+//! Input is received the same way: the falling edge of a start bit, seen by the GPIO interrupt,
+//! starts the receiver, and a one-shot software timer samples every bit in its middle. Characters
+//! are buffered until read. With IRQs masked, `read_char()` polls the line instead, spinning for
+//! every bit.
+
+use super::interface;
+use crate::{
+    bitbang::uart::{SoftUartReceiver, SoftUartTransmitter},
+    bsp::{self, Edge},
+    exception::asynchronous,
+    gpio::dynpin::DynPin,
+    synchronization::{interface::Mutex, IRQSafeNullLock},
+    time::{
+        self,
+        timer::{self, TimerHandle},
+        Instant,
+    },
+};
+use core::{
+    fmt,
+    task::{Context, Poll, Waker},
+};
+use embedded_hal::serial::Read;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Characters buffered for the bit clock. Fits a whole binary log frame, see `queue_bytes`.
+const TX_BUFFER_SIZE: usize = 256;
+
+/// Characters received, but not read yet.
+const RX_BUFFER_SIZE: usize = 16;
+
+/// The transmit side, shared with the bit clock's timer callback.
+struct SoftUartConsoleTx {
+    tx: SoftUartTransmitter<DynPin>,
+    buffer: [u8; TX_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+    /// The periodic timer shifting out bits, while there is anything to send.
+    bit_clock: Option<TimerHandle>,
+    /// Woken by the bit clock once there is room in the buffer.
+    waker: Option<Waker>,
+    chars_written: usize,
+}
+
+/// The receive side, shared with the RX pin's edge callback.
+struct SoftUartConsoleRx {
+    rx: SoftUartReceiver<DynPin>,
+    buffer: [u8; RX_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+    /// The one-shot timer sampling the next bit, while a character is being received.
+    sample_timer: Option<TimerHandle>,
+    /// Woken once a character is received.
+    waker: Option<Waker>,
+    chars_read: usize,
+}
+
+/// Writes through [`interface::Write::write_char`], so formatting doesn't hold the lock.
+struct CharWriter<'a>(&'a SoftUartConsole);
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Adapter turning a [`SoftUartTransmitter`], and optionally a [`SoftUartReceiver`], into a
+/// console.
+///
+/// Until pins are attached, it behaves like the null console.
+pub struct SoftUartConsole {
+    tx: IRQSafeNullLock<Option<SoftUartConsoleTx>>,
+    rx: IRQSafeNullLock<Option<SoftUartConsoleRx>>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+pub static SOFT_UART_CONSOLE: SoftUartConsole = SoftUartConsole::new();
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Timer callback of the bit clock.
+fn bit_clock_tick(_: TimerHandle) {
+    SOFT_UART_CONSOLE.tx.lock(|tx| {
+        if let Some(tx) = tx {
+            tx.tick()
+        }
+    });
+}
+
+/// Edge callback of the RX pin, at the falling edge of a start bit.
+fn rx_start_bit(_: usize, timestamp: Instant) {
+    SOFT_UART_CONSOLE.rx.lock(|rx| {
+        if let Some(rx) = rx {
+            rx.start(timestamp)
+        }
+    });
+}
+
+/// Timer callback sampling the bits of a character.
+fn rx_sample(_: TimerHandle) {
+    SOFT_UART_CONSOLE.rx.lock(|rx| {
+        if let Some(rx) = rx {
+            rx.sample_timer = None;
+            rx.tick()
+        }
+    });
+}
+
+impl SoftUartConsoleTx {
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let byte = self.buffer[self.head];
+        self.head = (self.head + 1) % TX_BUFFER_SIZE;
+        self.len -= 1;
+
+        Some(byte)
+    }
+
+    fn is_busy(&self) -> bool {
+        self.len > 0 || self.tx.is_busy()
+    }
+
+    /// Put the next bit on the wire. Stops the bit clock once everything is sent.
+    fn tick(&mut self) {
+        // A pin that can't be driven has nowhere to report to. Drop the character.
+        if let Ok(true) = self.tx.tick() {
+            return;
+        }
+
+        match self.pop() {
+            Some(byte) => {
+                self.tx.start(byte);
+                let _ = self.tx.tick();
+
+                if let Some(waker) = self.waker.take() {
+                    waker.wake();
+                }
+            }
+            None => {
+                if let Some(bit_clock) = self.bit_clock.take() {
+                    timer::timer_service().cancel(bit_clock);
+                }
+            }
+        }
+    }
+
+    /// Send everything right away, spinning for every bit.
+    fn drain(&mut self) {
+        if let Some(bit_clock) = self.bit_clock.take() {
+            timer::timer_service().cancel(bit_clock);
+
+            // The bit on the wire has just started.
+            time::spin_for(self.tx.bit_time());
+        }
+
+        loop {
+            while let Ok(true) = self.tx.tick() {
+                time::spin_for(self.tx.bit_time());
+            }
+
+            match self.pop() {
+                Some(byte) => self.tx.start(byte),
+                None => break,
+            }
+        }
+    }
+
+    /// Queue a byte and make sure it gets sent. Returns `false` if the buffer is full and the
+    /// caller has to wait.
+    ///
+    /// `irq_masked` tells whether the caller runs with IRQs masked, i.e. without a bit clock.
+    fn write_byte(&mut self, byte: u8, irq_masked: bool) -> bool {
+        if self.len == TX_BUFFER_SIZE {
+            if !irq_masked {
+                return false;
+            }
+            self.drain();
+        }
+
+        self.buffer[(self.head + self.len) % TX_BUFFER_SIZE] = byte;
+        self.len += 1;
+
+        if irq_masked {
+            self.drain();
+        } else if self.bit_clock.is_none() {
+            match timer::timer_service().schedule_periodic(self.tx.bit_time(), bit_clock_tick) {
+                Ok(bit_clock) => self.bit_clock = Some(bit_clock),
+                Err(_) => self.drain(),
+            }
+        }
+
+        true
+    }
+}
+
+impl SoftUartConsoleRx {
+    /// Start receiving the character whose start bit's falling edge was seen at `timestamp`.
+    fn start(&mut self, timestamp: Instant) {
+        // An edge within the character being received.
+        if self.rx.is_busy() {
+            return;
+        }
+
+        self.rx.begin(timestamp);
+        self.schedule_sample();
+    }
+
+    /// Sample the next bit, and buffer the character once it is complete.
+    fn tick(&mut self) {
+        match self.rx.tick() {
+            Ok(byte) => self.push(byte),
+            Err(nb::Error::WouldBlock) => self.schedule_sample(),
+            // Framing and parity errors are line noise, as good as nothing received.
+            Err(nb::Error::Other(_)) => (),
+        }
+    }
+
+    /// Arm the timer for the next sample, unless the character is done.
+    fn schedule_sample(&mut self) {
+        let Some(at) = self.rx.next_sample() else {
+            return;
+        };
+
+        match timer::timer_service().schedule_once(at.duration_since(Instant::now()), rx_sample) {
+            Ok(handle) => self.sample_timer = Some(handle),
+            // Without a timer, the character is lost.
+            Err(_) => self.rx.cancel(),
+        }
+    }
+
+    /// Receive a character spinning, if its start bit is on the wire. For when there are no
+    /// interrupts to do it.
+    fn poll(&mut self) {
+        if let Some(handle) = self.sample_timer.take() {
+            timer::timer_service().cancel(handle);
+        }
+
+        if let Ok(byte) = self.rx.read() {
+            self.push(byte);
+        }
+    }
+
+    /// Buffer a received character, and wake the task waiting for it.
+    fn push(&mut self, byte: u8) {
+        // Without room, the character is lost.
+        if self.len < RX_BUFFER_SIZE {
+            self.buffer[(self.head + self.len) % RX_BUFFER_SIZE] = byte;
+            self.len += 1;
+        }
+
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+
+    /// Take the oldest character received.
+    fn pop(&mut self) -> Option<char> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let mut ret = self.buffer[self.head] as char;
+        self.head = (self.head + 1) % RX_BUFFER_SIZE;
+        self.len -= 1;
+
+        // Convert carrige return to newline.
+        if ret == '\r' {
+            ret = '\n'
+        }
+
+        self.chars_read += 1;
+
+        Some(ret)
+    }
+}
+
+impl SoftUartConsole {
+    /// Queue bytes, waiting for room in the buffer, and count them as `chars` characters written.
+    fn queue_bytes(&self, bytes: &[u8], chars: usize) {
+        // Taken before locking, which masks IRQs itself.
+        let irq_masked = asynchronous::is_local_irq_masked();
+
+        // Queued all at once when there is room for them, so nothing printed from an interrupt
+        // handler gets in between. With IRQs masked, `write_byte` drains the buffer itself.
+        for chunk in bytes.chunks(TX_BUFFER_SIZE) {
+            // Without room, sleep until the bit clock's next interrupt. IRQs stay masked between
+            // the check and `wfi`, so that interrupt can't be missed.
+            while !self.tx.lock(|tx| match tx {
+                None => true,
+                Some(tx) => {
+                    let queued = irq_masked || TX_BUFFER_SIZE - tx.len >= chunk.len();
+                    if queued {
+                        for &byte in chunk {
+                            tx.write_byte(byte, irq_masked);
+                        }
+                    } else {
+                        aarch64_cpu::asm::wfi();
+                    }
+                    queued
+                }
+            }) {}
+        }
+
+        self.tx.lock(|tx| {
+            if let Some(tx) = tx {
+                tx.chars_written += chars;
+            }
+        });
+    }
+}
+
+impl fmt::Write for CharWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            interface::Write::write_char(self.0, c);
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl SoftUartConsole {
+    /// Create an instance without pins.
+    pub const fn new() -> Self {
+        Self {
+            tx: IRQSafeNullLock::new(None),
+            rx: IRQSafeNullLock::new(None),
+        }
+    }
+
+    /// Attach the transmitter and the optional receiver. Replaces any previously attached ones.
+    ///
+    /// The TX pin must already be an output idling high, the RX pin an input. Start bits are
+    /// detected on the RX pin, which takes over its edge callback.
+    pub fn attach(
+        &self,
+        tx: SoftUartTransmitter<DynPin>,
+        rx: Option<SoftUartReceiver<DynPin>>,
+    ) -> Result<(), &'static str> {
+        self.tx.lock(|inner| {
+            if let Some(old) = inner {
+                old.drain();
+            }
+
+            *inner = Some(SoftUartConsoleTx {
+                tx,
+                buffer: [0; TX_BUFFER_SIZE],
+                head: 0,
+                len: 0,
+                bit_clock: None,
+                waker: None,
+                chars_written: 0,
+            })
+        });
+
+        let old_pin = self.rx.lock(|inner| {
+            let old = inner.take()?;
+            if let Some(handle) = old.sample_timer {
+                timer::timer_service().cancel(handle);
+            }
+
+            Some(old.rx.rx_pin().id().num)
+        });
+        if let Some(num) = old_pin {
+            bsp::GPIO.clear_edge_callback(num as usize)?;
+        }
+
+        let Some(rx) = rx else {
+            return Ok(());
+        };
+        let num = rx.rx_pin().id().num;
+
+        self.rx.lock(|inner| {
+            *inner = Some(SoftUartConsoleRx {
+                rx,
+                buffer: [0; RX_BUFFER_SIZE],
+                head: 0,
+                len: 0,
+                sample_timer: None,
+                waker: None,
+                chars_read: 0,
+            })
+        });
+        bsp::GPIO.set_edge_callback(num as usize, Edge::Falling, rx_start_bit)
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl interface::Write for SoftUartConsole {
+    fn write_char(&self, c: char) {
+        let mut buf = [0; 4];
+        self.queue_bytes(c.encode_utf8(&mut buf).as_bytes(), 1);
+    }
+
+    fn write_bytes(&self, bytes: &[u8]) {
+        self.queue_bytes(bytes, bytes.len());
+    }
+
+    fn write_fmt(&self, args: fmt::Arguments) -> fmt::Result {
+        fmt::Write::write_fmt(&mut CharWriter(self), args)
+    }
+
+    /// Waits until the buffer is empty and the last character is on the wire.
+    fn flush(&self) {
+        let irq_masked = asynchronous::is_local_irq_masked();
+
+        while self.tx.lock(|tx| match tx {
+            Some(tx) if tx.is_busy() => {
+                if irq_masked {
+                    tx.drain();
+                } else {
+                    aarch64_cpu::asm::wfi();
+                }
+                true
+            }
+            _ => false,
+        }) {}
+    }
+
+    /// Returns `Pending` while the buffer has no room for the whole character.
+    fn poll_write_char(&self, cx: &mut Context<'_>, c: char) -> Poll<()> {
+        // Without a bit clock, nothing would ever make room.
+        if asynchronous::is_local_irq_masked() {
+            self.write_char(c);
+            return Poll::Ready(());
+        }
+
+        let mut buf = [0; 4];
+        let bytes = c.encode_utf8(&mut buf).as_bytes();
+
+        self.tx.lock(|tx| {
+            let Some(tx) = tx else {
+                return Poll::Ready(());
+            };
+
+            if TX_BUFFER_SIZE - tx.len < bytes.len() {
+                tx.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+
+            for &byte in bytes {
+                tx.write_byte(byte, false);
+            }
+            tx.chars_written += 1;
+
+            Poll::Ready(())
+        })
+    }
+}
+
+impl interface::Read for SoftUartConsole {
+    /// Without a receiver, returns a space like the null console.
+    fn read_char(&self) -> char {
+        // Taken before locking, which masks IRQs itself.
+        let irq_masked = asynchronous::is_local_irq_masked();
+
+        loop {
+            // Without a character, sleep until the next interrupt. IRQs stay masked between the
+            // check and `wfi`, so a start bit's interrupt can't be missed.
+            let c = self.rx.lock(|rx| {
+                let Some(rx) = rx else {
+                    return Some(' ');
+                };
+
+                // There are no interrupts, poll the line.
+                if irq_masked {
+                    rx.poll();
+                }
+
+                let c = rx.pop();
+                if c.is_none() && !irq_masked {
+                    aarch64_cpu::asm::wfi();
+                }
+                c
+            });
+
+            if let Some(c) = c {
+                return c;
+            }
+        }
+    }
+
+    fn clear_rx(&self) {
+        self.rx.lock(|rx| {
+            if let Some(rx) = rx {
+                rx.len = 0;
+            }
+        });
+    }
+
+    fn poll_read_char(&self, cx: &mut Context<'_>) -> Poll<char> {
+        self.rx.lock(|rx| {
+            // Without a receiver, there is never any input.
+            let Some(rx) = rx else {
+                return Poll::Pending;
+            };
+
+            match rx.pop() {
+                Some(c) => Poll::Ready(c),
+                None => {
+                    rx.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+    }
+}
+
+impl interface::Statistics for SoftUartConsole {
+    fn chars_written(&self) -> usize {
+        self.tx
+            .lock(|tx| tx.as_ref().map_or(0, |tx| tx.chars_written))
+    }
+
+    fn chars_read(&self) -> usize {
+        self.rx.lock(|rx| rx.as_ref().map_or(0, |rx| rx.chars_read))
+    }
+}
+
+impl interface::All for SoftUartConsole {}
@@ -0,0 +1,303 @@
+//! Kernel message buffer.
+//!
+//! The buffer is a sink of the system console, [`DMESG_CONSOLE`], so everything that goes through
+//! `print!` and the log macros is recorded here, together with the uptime at which it was
+//! printed. The buffer is a fixed-size ring. Once it is full, the oldest records are dropped to
+//! make room.
+//!
+//! Messages printed before the first real console is registered would otherwise be lost. They are
+//! replayed to that console as soon as it is registered, see [`crate::console::register_console`].
+//!
+//! Single characters, like the shell's echo, are not recorded. Neither is anything printed while
+//! the buffer is being dumped.
+
+use crate::{
+    console,
+    synchronization::{interface::Mutex, IRQSafeNullLock},
+    time,
+};
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Size of the ring in bytes, including the record headers.
+const DMESG_LEN: usize = 16 * 1024;
+
+/// Uptime in nanoseconds as `u64`, followed by the text length as `u16`, both little endian.
+const HEADER_LEN: usize = 8 + 2;
+
+/// Bytes of text copied out of the ring at a time while dumping.
+const DUMP_CHUNK_LEN: usize = 64;
+
+struct DmesgInner {
+    buf: [u8; DMESG_LEN],
+    /// Where the next byte goes.
+    head: usize,
+    /// Start of the oldest record.
+    tail: usize,
+    /// Bytes in use.
+    used: usize,
+    /// Number of records in the buffer, including one that is being recorded.
+    records: usize,
+    /// Number of records dropped because the buffer was full.
+    dropped: usize,
+    /// Start and text length of the record being recorded.
+    current_start: usize,
+    current_len: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Console sink recording into the buffer.
+pub struct DmesgConsole;
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+/// Records are also made from interrupt handlers, so IRQs are masked while it is locked.
+static DMESG: IRQSafeNullLock<DmesgInner> = IRQSafeNullLock::new(DmesgInner::new());
+
+/// Set while dumping, so the dump doesn't feed back into the buffer it is reading.
+static DUMPING: AtomicBool = AtomicBool::new(false);
+
+pub static DMESG_CONSOLE: DmesgConsole = DmesgConsole;
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl DmesgInner {
+    const fn new() -> Self {
+        Self {
+            buf: [0; DMESG_LEN],
+            head: 0,
+            tail: 0,
+            used: 0,
+            records: 0,
+            dropped: 0,
+            current_start: 0,
+            current_len: 0,
+        }
+    }
+
+    fn byte_at(&self, offset: usize) -> u8 {
+        self.buf[offset % DMESG_LEN]
+    }
+
+    fn header_at(&self, start: usize) -> (u64, usize) {
+        let mut nanos = [0; 8];
+        for (i, byte) in nanos.iter_mut().enumerate() {
+            *byte = self.byte_at(start + i);
+        }
+        let len = u16::from_le_bytes([self.byte_at(start + 8), self.byte_at(start + 9)]);
+
+        (u64::from_le_bytes(nanos), len as usize)
+    }
+
+    fn drop_oldest(&mut self) {
+        let (_, len) = self.header_at(self.tail);
+
+        self.tail = (self.tail + HEADER_LEN + len) % DMESG_LEN;
+        self.used -= HEADER_LEN + len;
+        self.records -= 1;
+        self.dropped += 1;
+    }
+
+    /// Append a byte to the current record. Returns false if the record has grown as big as the
+    /// whole buffer.
+    fn push(&mut self, byte: u8) -> bool {
+        if self.used == DMESG_LEN {
+            // Never drop the record being recorded.
+            if self.records == 1 {
+                return false;
+            }
+            self.drop_oldest();
+        }
+
+        self.buf[self.head] = byte;
+        self.head = (self.head + 1) % DMESG_LEN;
+        self.used += 1;
+
+        true
+    }
+
+    fn begin_record(&mut self, timestamp: Duration) {
+        self.current_start = self.head;
+        self.current_len = 0;
+        self.records += 1;
+
+        for byte in (timestamp.as_nanos() as u64).to_le_bytes() {
+            self.push(byte);
+        }
+        // Length, filled in by end_record().
+        self.push(0);
+        self.push(0);
+    }
+
+    fn end_record(&mut self) {
+        let len = (self.current_len as u16).to_le_bytes();
+
+        self.buf[(self.current_start + 8) % DMESG_LEN] = len[0];
+        self.buf[(self.current_start + 9) % DMESG_LEN] = len[1];
+    }
+
+    /// Copy the bytes starting at `offset` into `buf`.
+    fn copy_out(&self, offset: usize, buf: &mut [u8]) {
+        for (i, byte) in buf.iter_mut().enumerate() {
+            *byte = self.byte_at(offset + i);
+        }
+    }
+
+    /// Append bytes to the text of the current record.
+    fn push_text(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if self.current_len == u16::MAX as usize || !self.push(byte) {
+                // Truncate, the rest of the record is lost.
+                break;
+            }
+            self.current_len += 1;
+        }
+    }
+}
+
+impl fmt::Write for DmesgInner {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_text(s.as_bytes());
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Record a message.
+pub fn record(args: fmt::Arguments) {
+    if DUMPING.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let timestamp = time::uptime();
+
+    DMESG.lock(|inner| {
+        inner.begin_record(timestamp);
+        // DmesgInner::write_str can't fail, it truncates instead.
+        let _ = fmt::Write::write_fmt(inner, args);
+        inner.end_record();
+    })
+}
+
+/// Record raw bytes, like a binary log frame, as a message of its own.
+pub fn record_bytes(bytes: &[u8]) {
+    if DUMPING.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let timestamp = time::uptime();
+
+    DMESG.lock(|inner| {
+        inner.begin_record(timestamp);
+        inner.push_text(bytes);
+        inner.end_record();
+    })
+}
+
+/// Write the whole buffer to `console`. With `timestamps`, every record is prefixed by the uptime
+/// at which it was recorded.
+pub fn dump_to(console: &dyn console::interface::All, timestamps: bool) {
+    DUMPING.store(true, Ordering::Relaxed);
+
+    // The buffer is only locked while copying a piece out, so IRQs aren't masked for the whole
+    // dump. Nothing is recorded meanwhile, so the records stay where they are.
+    let (mut start, records) = DMESG.lock(|inner| (inner.tail, inner.records));
+
+    for _ in 0..records {
+        let (nanos, len) = DMESG.lock(|inner| inner.header_at(start));
+
+        if timestamps {
+            let timestamp = Duration::from_nanos(nanos);
+            let _ = console.write_fmt(format_args!(
+                "<{:>3}.{:06}> ",
+                timestamp.as_secs(),
+                timestamp.subsec_micros()
+            ));
+        }
+
+        // Written as is, as the text may be a binary frame, or a character may wrap around the
+        // end of the ring.
+        let mut text = start + HEADER_LEN;
+        let end = text + len;
+        while text < end {
+            let mut chunk = [0; DUMP_CHUNK_LEN];
+            let chunk = &mut chunk[..usize::min(DUMP_CHUNK_LEN, end - text)];
+
+            DMESG.lock(|inner| inner.copy_out(text, chunk));
+            console.write_bytes(chunk);
+
+            text += chunk.len();
+        }
+
+        start = end % DMESG_LEN;
+    }
+
+    DUMPING.store(false, Ordering::Relaxed);
+}
+
+/// Write the whole buffer to the current console.
+pub fn dump(timestamps: bool) {
+    dump_to(console::console(), timestamps)
+}
+
+/// Empty the buffer.
+pub fn clear() {
+    DMESG.lock(|inner| *inner = DmesgInner::new())
+}
+
+/// Return the number of records in the buffer and the number of records dropped so far.
+pub fn stats() -> (usize, usize) {
+    DMESG.lock(|inner| (inner.records, inner.dropped))
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl console::interface::Write for DmesgConsole {
+    fn write_char(&self, _c: char) {}
+
+    fn write_bytes(&self, bytes: &[u8]) {
+        record_bytes(bytes);
+    }
+
+    fn write_fmt(&self, args: fmt::Arguments) -> fmt::Result {
+        record(args);
+
+        Ok(())
+    }
+
+    fn flush(&self) {}
+}
+
+/// The buffer has no input.
+impl console::interface::Read for DmesgConsole {
+    fn clear_rx(&self) {}
+
+    /// There is never any input.
+    fn poll_read_char(&self, _cx: &mut Context<'_>) -> Poll<char> {
+        Poll::Pending
+    }
+}
+
+impl console::interface::Statistics for DmesgConsole {}
+impl console::interface::All for DmesgConsole {}
@@ -5,19 +5,23 @@
 //! Driver support.
 
 use crate::{
-    info,
+    error, info,
+    profile::Stopwatch,
     synchronization::{interface::Mutex, NullLock},
 };
+use core::time::Duration;
 
 //--------------------------------------------------------------------------------------------------
 // Private Definitions
 //--------------------------------------------------------------------------------------------------
 
-const NUM_DRIVERS: usize = 5;
+const NUM_DRIVERS: usize = 16;
 
 struct DriverManagerInner {
     next_index: usize,
     descriptors: [Option<DeviceDriverDescriptor>; NUM_DRIVERS],
+    /// Time each driver's init and post-init callback took, by index.
+    init_times: [Duration; NUM_DRIVERS],
 }
 
 //--------------------------------------------------------------------------------------------------
@@ -39,9 +43,19 @@ pub mod interface {
         unsafe fn init(&self) -> Result<(), &'static str> {
             Ok(())
         }
+
+        /// Called by the kernel to check that an initialized device actually works.
+        ///
+        /// Drivers without a meaningful self-test keep the default, which always passes.
+        fn power_on_self_test(&self) -> Result<(), &'static str> {
+            Ok(())
+        }
     }
 }
 
+/// Interrupt number, as defined by the board's interrupt controller.
+pub type IRQNumber = usize;
+
 /// Tpye to be used as an optional callback after a driver's init() has run.
 pub type DeviceDriverPostInitCallback = unsafe fn() -> Result<(), &'static str>;
 
@@ -73,6 +87,7 @@ impl DriverManagerInner {
         Self {
             next_index: 0,
             descriptors: [None; NUM_DRIVERS],
+            init_times: [Duration::ZERO; NUM_DRIVERS],
         }
     }
 }
@@ -128,11 +143,18 @@ impl DriverManager {
 
     /// Fully initialize all drivers.
     ///
+    /// Every driver is timed as a profiler section named after its compatible string.
+    ///
     /// # Safety
     ///
     /// - During init, drivers might do stuff with system-wide impact.
     pub unsafe fn init_drivers(&self) {
+        let mut init_times = [Duration::ZERO; NUM_DRIVERS];
+        let mut i = 0;
+
         self.for_each_descriptor(|descriptor| {
+            let stopwatch = Stopwatch::start(descriptor.device_driver.compatible());
+
             // 1. Initialize driver.
             if let Err(x) = descriptor.device_driver.init() {
                 panic!(
@@ -152,14 +174,45 @@ impl DriverManager {
                     );
                 }
             }
+
+            init_times[i] = stopwatch.stop();
+            i += 1;
+        });
+
+        self.inner.lock(|inner| inner.init_times = init_times);
+    }
+
+    /// Run the power-on self-test of all drivers.
+    ///
+    /// Must be called after [`DriverManager::init_drivers`]. Returns the number of failed tests.
+    pub fn run_power_on_self_tests(&self) -> usize {
+        let mut failures = 0;
+        self.for_each_descriptor(|descriptor| {
+            if let Err(x) = descriptor.device_driver.power_on_self_test() {
+                error!(
+                    "Power-on self-test failed: {}: {}",
+                    descriptor.device_driver.compatible(),
+                    x
+                );
+                failures += 1;
+            }
         });
+
+        failures
     }
 
-    /// Enumerate all registered device drivers.
+    /// Enumerate all registered device drivers, with the time their init took.
     pub fn enumerate(&self) {
+        let init_times = self.inner.lock(|inner| inner.init_times);
+
         let mut i: usize = 1;
         self.for_each_descriptor(|descriptor| {
-            info!("      {}. {}", i, descriptor.device_driver.compatible());
+            info!(
+                "      {}. {} ({} us)",
+                i,
+                descriptor.device_driver.compatible(),
+                init_times[i - 1].as_micros()
+            );
 
             i += 1;
         });
@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: MIT OR Apache-2.0
+//
+// Copyright (c) 2022 Andre Richter <andre.o.richter@gmail.com>
+
+//! Asynchronous exception handling, i.e. interrupts.
+//!
+//! Drivers and services implement [`interface::IRQHandler`] and register it for their interrupt
+//! number with the board's interrupt controller, see [`irq_manager()`]. Handlers run in interrupt
+//! context with IRQs masked on the executing core.
+
+use crate::{bsp, driver::IRQNumber};
+use aarch64_cpu::registers::*;
+use tock_registers::interfaces::{ReadWriteable, Readable, Writeable};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Interrupt interfaces.
+pub mod interface {
+    use super::IRQHandlerDescriptor;
+    use crate::driver::IRQNumber;
+
+    /// Implemented by anything that handles an interrupt.
+    pub trait IRQHandler {
+        /// Called when the corresponding interrupt is asserted.
+        fn handle(&self) -> Result<(), &'static str>;
+    }
+
+    /// IRQ management functions.
+    ///
+    /// The interrupt controller driver of the board implements these.
+    pub trait IRQManager {
+        /// Register a handler.
+        fn register_handler(&self, descriptor: IRQHandlerDescriptor) -> Result<(), &'static str>;
+
+        /// Let an IRQ through at the controller.
+        fn enable(&self, irq_number: IRQNumber) -> Result<(), &'static str>;
+
+        /// Handle all pending interrupts.
+        ///
+        /// Called from the IRQ exception vector only, with IRQs masked on the executing core.
+        fn handle_pending_irqs(&self);
+
+        /// Print the registered handlers.
+        fn enumerate(&self);
+    }
+}
+
+/// Interrupt descriptor.
+#[derive(Copy, Clone)]
+pub struct IRQHandlerDescriptor {
+    /// The IRQ number.
+    number: IRQNumber,
+
+    /// Descriptive name.
+    name: &'static str,
+
+    /// Reference to handler trait object.
+    handler: &'static (dyn interface::IRQHandler + Sync),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl IRQHandlerDescriptor {
+    /// Create an instance.
+    pub const fn new(
+        number: IRQNumber,
+        name: &'static str,
+        handler: &'static (dyn interface::IRQHandler + Sync),
+    ) -> Self {
+        Self {
+            number,
+            name,
+            handler,
+        }
+    }
+
+    /// Return the number.
+    pub const fn number(&self) -> IRQNumber {
+        self.number
+    }
+
+    /// Return the name.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Return the handler.
+    pub const fn handler(&self) -> &'static (dyn interface::IRQHandler + Sync) {
+        self.handler
+    }
+}
+
+/// Returns whether IRQs are masked on the executing core.
+pub fn is_local_irq_masked() -> bool {
+    DAIF.is_set(DAIF::I)
+}
+
+/// Unmask IRQs on the executing core.
+///
+/// # Safety
+///
+/// - The exception vectors must be installed, see [`crate::exception::handling_init`].
+pub unsafe fn local_irq_unmask() {
+    DAIF.modify(DAIF::I::Unmasked);
+}
+
+/// Mask IRQs on the executing core.
+pub fn local_irq_mask() {
+    DAIF.modify(DAIF::I::Masked);
+}
+
+/// Executes the provided closure while IRQs are masked on the executing core.
+///
+/// While the function temporarily changes the HW state of the executing core, it restores it to
+/// the previous state before returning, so this is deemed safe.
+pub fn exec_with_irq_masked<T>(f: impl FnOnce() -> T) -> T {
+    let saved = DAIF.get();
+    local_irq_mask();
+
+    let ret = f();
+
+    DAIF.set(saved);
+
+    ret
+}
+
+/// Return a reference to the IRQ manager of the board's interrupt controller.
+pub fn irq_manager() -> &'static (dyn interface::IRQManager + Sync) {
+    bsp::irq_manager()
+}
@@ -0,0 +1,190 @@
+//! Single-core async executor.
+//!
+//! Tasks are `async` blocks [`spawn()`]ed into a fixed number of statically allocated slots. Once
+//! [`run()`] takes over the core, it polls the tasks that were woken, and idles in `WFI` while none
+//! are. Wakers only mark their task as ready, so interrupt handlers can wake tasks.
+//!
+//! Tasks are polled one after the other. A task that doesn't `.await` keeps all others from
+//! running, so anything that takes a while should await a [`Timer`], or [`yield_now()`].
+
+mod timer;
+
+use crate::{
+    exception::asynchronous,
+    synchronization::{interface::Mutex, IRQSafeNullLock},
+};
+use aarch64_cpu::asm;
+use core::{
+    cell::{Cell, UnsafeCell},
+    future::{poll_fn, Future},
+    mem::{self, MaybeUninit},
+    pin::Pin,
+    ptr::{self, NonNull},
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+pub use timer::Timer;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Number of tasks that can exist at the same time. At most 32, one bit each in [`READY`].
+const NUM_TASKS: usize = 8;
+
+/// Bytes a task's future may take, i.e. what its `async` block keeps across `.await`s.
+const TASK_SIZE: usize = 4096;
+
+/// Alignment of the task storage.
+const TASK_ALIGN: usize = 16;
+
+#[repr(C, align(16))]
+struct TaskStorage(MaybeUninit<[u8; TASK_SIZE]>);
+
+struct Task {
+    storage: UnsafeCell<TaskStorage>,
+    /// The future in `storage`, if the slot is taken.
+    future: Cell<Option<NonNull<dyn Future<Output = ()>>>>,
+}
+
+struct TaskTable([Task; NUM_TASKS]);
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+/// Tasks are only touched by [`spawn()`] and [`run()`], which must not be called from interrupt
+/// handlers, and the kernel runs on a single core.
+unsafe impl Sync for TaskTable {}
+
+static TASKS: TaskTable = TaskTable([Task::EMPTY; NUM_TASKS]);
+
+/// One bit per task slot, set when the task was woken.
+static READY: IRQSafeNullLock<u32> = IRQSafeNullLock::new(0);
+
+static WAKER_VTABLE: RawWakerVTable =
+    RawWakerVTable::new(clone_waker, wake_task, wake_task, drop_waker);
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl Task {
+    const EMPTY: Self = Self {
+        storage: UnsafeCell::new(TaskStorage(MaybeUninit::uninit())),
+        future: Cell::new(None),
+    };
+
+    /// Poll the task, and drop it once it completed. Stale wake-ups of a free slot are ignored.
+    fn poll(&self, slot: usize) {
+        let Some(future) = self.future.get() else {
+            return;
+        };
+
+        let waker = waker(slot);
+        let mut cx = Context::from_waker(&waker);
+
+        // The future stays in place until it is dropped below, and is only ever polled from here.
+        let pinned = unsafe { Pin::new_unchecked(&mut *future.as_ptr()) };
+        if pinned.poll(&mut cx).is_ready() {
+            self.future.set(None);
+            unsafe { ptr::drop_in_place(future.as_ptr()) };
+        }
+    }
+}
+
+// The waker data is the task's slot number, so wakers need neither allocation nor reference
+// counting. A waker that outlives its task at worst wakes the task next spawned into the slot,
+// which futures must cope with anyway.
+
+fn waker(slot: usize) -> Waker {
+    unsafe { Waker::from_raw(RawWaker::new(slot as *const (), &WAKER_VTABLE)) }
+}
+
+unsafe fn clone_waker(data: *const ()) -> RawWaker {
+    RawWaker::new(data, &WAKER_VTABLE)
+}
+
+unsafe fn wake_task(data: *const ()) {
+    READY.lock(|ready| *ready |= 1 << data as usize);
+}
+
+unsafe fn drop_waker(_: *const ()) {}
+
+/// Take the set of woken tasks. If there is none, idle until the next interrupt first.
+fn take_ready() -> u32 {
+    // IRQs stay masked between the check and `wfi`, so a wake-up from an interrupt can't be
+    // missed. A pending interrupt still ends `wfi`, and is taken once IRQs are unmasked again.
+    asynchronous::exec_with_irq_masked(|| {
+        let ready = READY.lock(mem::take);
+        if ready == 0 {
+            asm::wfi();
+        }
+
+        ready
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Add a task, which is first polled once [`run()`] gets to it.
+///
+/// Must not be called from interrupt handlers.
+pub fn spawn<F>(future: F) -> Result<(), &'static str>
+where
+    F: Future<Output = ()> + 'static,
+{
+    if mem::size_of::<F>() > TASK_SIZE || mem::align_of::<F>() > TASK_ALIGN {
+        return Err("Task too big");
+    }
+
+    let (slot, task) = TASKS
+        .0
+        .iter()
+        .enumerate()
+        .find(|(_, task)| task.future.get().is_none())
+        .ok_or("No free task slot")?;
+
+    let storage = task.storage.get().cast::<F>();
+    unsafe { storage.write(future) };
+    task.future
+        .set(NonNull::new(storage as *mut dyn Future<Output = ()>));
+
+    waker(slot).wake();
+
+    Ok(())
+}
+
+/// Run the spawned tasks, for good.
+///
+/// Must be called once only, with IRQs unmasked, and not from a task.
+pub fn run() -> ! {
+    loop {
+        let mut ready = take_ready();
+
+        while ready != 0 {
+            let slot = ready.trailing_zeros() as usize;
+            ready &= ready - 1;
+
+            TASKS.0[slot].poll(slot);
+        }
+    }
+}
+
+/// Let the other ready tasks run before continuing.
+pub async fn yield_now() {
+    let mut yielded = false;
+
+    poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
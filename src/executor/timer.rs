@@ -0,0 +1,151 @@
+//! Futures that complete at a point in time, woken by the software timer service.
+
+use crate::{
+    synchronization::{interface::Mutex, IRQSafeNullLock},
+    time::{
+        timer::{self, TimerHandle},
+        Instant,
+    },
+};
+use core::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Timers that can be pending at the same time. As many as the timer service has.
+const NUM_WAKERS: usize = 16;
+
+type WakerEntry = Option<(TimerHandle, Waker)>;
+
+const NO_WAKER: WakerEntry = None;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Future that completes once its deadline has passed.
+///
+/// While pending, it holds a one-shot software timer that wakes its task. Should none be left, the
+/// task is polled again right away instead, which wastes time but still completes in time.
+pub struct Timer {
+    deadline: Instant,
+    handle: Option<TimerHandle>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+/// The wakers of the pending timers, by the handle of their software timer.
+static WAKERS: IRQSafeNullLock<[WakerEntry; NUM_WAKERS]> =
+    IRQSafeNullLock::new([NO_WAKER; NUM_WAKERS]);
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Timer callback, in interrupt context.
+fn wake(handle: TimerHandle) {
+    let waker = WAKERS.lock(|wakers| {
+        wakers
+            .iter_mut()
+            .find(|entry| matches!(entry, Some((h, _)) if *h == handle))
+            .and_then(|entry| entry.take())
+    });
+
+    if let Some((_, waker)) = waker {
+        waker.wake();
+    }
+}
+
+impl Timer {
+    /// Make sure the task is woken at the deadline, with the waker of its latest poll.
+    fn arm(&mut self, waker: &Waker) -> Result<(), &'static str> {
+        WAKERS.lock(|wakers| {
+            if let Some(handle) = self.handle {
+                if let Some((_, w)) = wakers.iter_mut().flatten().find(|(h, _)| *h == handle) {
+                    w.clone_from(waker);
+                    return Ok(());
+                }
+            }
+
+            // Not armed yet, or woken by something else after the timer fired.
+            let free = wakers
+                .iter_mut()
+                .find(|entry| entry.is_none())
+                .ok_or("No free timer waker")?;
+
+            let handle = timer::timer_service()
+                .schedule_once(self.deadline.duration_since(Instant::now()), wake)?;
+            *free = Some((handle, waker.clone()));
+            self.handle = Some(handle);
+
+            Ok(())
+        })
+    }
+
+    fn disarm(&mut self) {
+        let Some(handle) = self.handle.take() else {
+            return;
+        };
+
+        timer::timer_service().cancel(handle);
+        WAKERS.lock(|wakers| {
+            for entry in wakers.iter_mut() {
+                if matches!(entry, Some((h, _)) if *h == handle) {
+                    *entry = None;
+                }
+            }
+        });
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Timer {
+    /// Complete after the given time.
+    pub fn after(duration: Duration) -> Self {
+        Self::at(Instant::now().checked_add(duration).unwrap_or(Instant::MAX))
+    }
+
+    /// Complete once `deadline` has passed.
+    pub fn at(deadline: Instant) -> Self {
+        Self {
+            deadline,
+            handle: None,
+        }
+    }
+}
+
+impl Future for Timer {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+
+        if Instant::now() >= this.deadline {
+            this.disarm();
+            return Poll::Ready(());
+        }
+
+        if this.arm(cx.waker()).is_err() {
+            cx.waker().wake_by_ref();
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.disarm();
+    }
+}
@@ -1,3 +1,7 @@
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+use super::reg::RegisterInterface;
+
 /// Value-level `enum` for disabled configurations
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[allow(missing_docs)]
@@ -70,3 +74,128 @@ impl DynPinId {
         self.num / 15
     }
 }
+
+/// Value-level `struct` implementing the [`RegisterInterface`] for a [`DynPinId`]
+struct DynRegisters {
+    id: DynPinId,
+}
+
+unsafe impl RegisterInterface for DynRegisters {
+    #[inline]
+    fn id(&self) -> DynPinId {
+        self.id
+    }
+}
+
+/// Error type for [`DynPin`] operations
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Error {
+    /// The pin is not in a mode that supports the requested operation
+    InvalidPinType,
+}
+
+/// A value-level pin, parameterized by [`DynPinId`] and [`DynPinMode`]
+///
+/// Unlike the type-level [`Pin`], the pin number and mode are only known at runtime. This is
+/// what board-dependent pins (see [`Board`]) have to use.
+///
+/// [`Pin`]: super::pin::Pin
+/// [`Board`]: crate::bsp::board::Board
+pub struct DynPin {
+    regs: DynRegisters,
+    mode: DynPinMode,
+}
+
+impl DynPin {
+    /// Create a new [`DynPin`]
+    ///
+    /// # Safety
+    ///
+    /// Each [`DynPin`] must be a singleton. For a given [`DynPinId`], there must be at most one
+    /// corresponding [`DynPin`] (or [`Pin`](super::pin::Pin)) in existence at any given time.
+    /// `mode` must be the mode the pin is currently configured in.
+    #[inline]
+    pub unsafe fn new(id: DynPinId, mode: DynPinMode) -> Self {
+        DynPin {
+            regs: DynRegisters { id },
+            mode,
+        }
+    }
+
+    /// Return the pin's [`DynPinId`]
+    #[inline]
+    pub fn id(&self) -> DynPinId {
+        self.regs.id
+    }
+
+    /// Return the pin's current [`DynPinMode`]
+    #[inline]
+    pub fn mode(&self) -> DynPinMode {
+        self.mode
+    }
+
+    /// Convert the pin to the requested [`DynPinMode`]
+    #[inline]
+    pub fn into_mode(&mut self, mode: DynPinMode) {
+        if mode != self.mode {
+            self.regs.change_mode(mode);
+            self.mode = mode;
+        }
+    }
+
+    /// Configure the pin as a push-pull output
+    #[inline]
+    pub fn into_push_pull_output(&mut self) {
+        self.into_mode(DynPinMode::Output(DynOutput::PushPull));
+    }
+
+    /// Configure the pin as a floating input
+    #[inline]
+    pub fn into_floating_input(&mut self) {
+        self.into_mode(DynPinMode::Input(DynInput::Floating));
+    }
+
+    #[inline]
+    fn _read(&self) -> Result<bool, Error> {
+        match self.mode {
+            DynPinMode::Input(_) | DynPinMode::Output(_) => Ok(self.regs.read_pin()),
+            _ => Err(Error::InvalidPinType),
+        }
+    }
+
+    #[inline]
+    fn _write(&mut self, bit: bool) -> Result<(), Error> {
+        match self.mode {
+            DynPinMode::Output(_) => {
+                self.regs.write_pin(bit);
+                Ok(())
+            }
+            _ => Err(Error::InvalidPinType),
+        }
+    }
+}
+
+/// [`embedded_hal`] traits
+impl OutputPin for DynPin {
+    type Error = Error;
+    #[inline]
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self._write(true)
+    }
+    #[inline]
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self._write(false)
+    }
+}
+
+impl InputPin for DynPin {
+    type Error = Error;
+    #[inline]
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        self._read()
+    }
+    #[inline]
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        self._read().map(|v| !v)
+    }
+}
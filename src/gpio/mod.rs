@@ -1,3 +1,3 @@
-mod dynpin;
+pub mod dynpin;
 pub mod pin;
 mod reg;
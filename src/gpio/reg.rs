@@ -1,8 +1,32 @@
-use bcm2711_pac::GPIO;
-use crate::info;
+use crate::bsp::{board::mmio, MMIODerefWrapper};
+use tock_registers::{
+    interfaces::{Readable, Writeable},
+    register_structs,
+    registers::{ReadOnly, ReadWrite, WriteOnly},
+};
 
 use super::dynpin::{DynPinId, DynPinMode};
 
+register_structs! {
+    RegisterBlock {
+        (0x00 => gpfsel: [ReadWrite<u32>; 6]),
+        (0x18 => _reserved1),
+        (0x1C => gpset: [WriteOnly<u32>; 2]),
+        (0x24 => _reserved2),
+        (0x28 => gpclr: [WriteOnly<u32>; 2]),
+        (0x30 => _reserved3),
+        (0x34 => gplev: [ReadOnly<u32>; 2]),
+        (0x3C => @END),
+    }
+}
+
+/// GPIO registers of the current board.
+///
+/// Shares the MMIO block with the `bsp` GPIO driver, but only ever touches the bits belonging to
+/// the pin it is accessed for.
+const REGISTERS: MMIODerefWrapper<RegisterBlock> =
+    unsafe { MMIODerefWrapper::new(mmio::GPIO_START) };
+
 #[allow(dead_code)]
 enum FunctionSelect {
     Input,
@@ -70,72 +94,27 @@ pub(super) unsafe trait RegisterInterface {
 
     // TODO Output embedded_hal `PinSate`
     fn read_pin(&self) -> bool {
-        let mask = self.mask();
-        (match self.id().group() {
-            0 => unsafe { &(*GPIO::ptr()) }.gplev0.read().bits(),
-            1 => unsafe { &(*GPIO::ptr()) }.gplev1.read().bits(),
-            _ => unreachable!(),
-        }) & mask
-            != 0
+        REGISTERS.gplev[self.id().group() as usize].get() & self.mask() != 0
     }
 
     // TODO Receive embedded_hal `PinSate` as argument
     fn write_pin(&mut self, bit: bool) {
-        let mask = self.mask();
-
-        unsafe {
-            match self.id().group() {
-                0 => {
-                    if bit {
-                        (*GPIO::ptr()).gpset0.write_with_zero(|w| w.bits(mask));
-                    } else {
-                        (*GPIO::ptr()).gpclr0.write_with_zero(|w| w.bits(mask));
-                    }
-                }
-                1 => {
-                    if bit {
-                        (*GPIO::ptr()).gpset1.write_with_zero(|w| w.bits(mask));
-                    } else {
-                        (*GPIO::ptr()).gpclr1.write_with_zero(|w| w.bits(mask));
-                    }
-                }
-                _ => unreachable!(),
-            }
-        };
+        let group = self.id().group() as usize;
+
+        if bit {
+            REGISTERS.gpset[group].set(self.mask());
+        } else {
+            REGISTERS.gpclr[group].set(self.mask());
+        }
     }
 
     fn change_mode(&mut self, mode: DynPinMode) {
         let fields: ModeFields = mode.into();
         let fsel_offset = (self.id().num % 10) * 3;
         let fsel = u32::from(fields.fsel) << fsel_offset;
-        unsafe {
-            match self.id().fsel_group() {
-                0 => (*GPIO::ptr())
-                    .gpfsel0
-                    .write_with_zero(|w| w.bits(fsel)),
-                1 => (*GPIO::ptr())
-                    .gpfsel1
-                    .write_with_zero(|w| w.bits(fsel)),
-                2 => (*GPIO::ptr())
-                    .gpfsel2
-                    .write_with_zero(|w| w.bits(fsel)),
-                3 => (*GPIO::ptr())
-                    .gpfsel3
-                    .write_with_zero(|w| w.bits(fsel)),
-                4 => (*GPIO::ptr())
-                    .gpfsel4
-                    .write_with_zero(|w| w.bits(fsel)),
-                5 => (*GPIO::ptr())
-                    .gpfsel5
-                    .write_with_zero(|w| w.bits(fsel)),
-                _ => unreachable!(),
-            }
-        }
+        let gpfsel = &REGISTERS.gpfsel[self.id().fsel_group() as usize];
+
+        // Read-modify-write, so the other nine pins of the register keep their function.
+        gpfsel.set((gpfsel.get() & !(0b111 << fsel_offset)) | fsel);
     }
 }
-
-// #[inline]
-// fn gpio_change_mode<M: PinMode>(num: usize, mode: M) {
-//     let fields: ModeFields = mode.into();
-//     (*pac::GPIO::ptr()).gpafen0
-// }
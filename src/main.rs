@@ -13,8 +13,17 @@ use embedded_hal::{digital::v2::OutputPin, serial::Write};
 
 use crate::bitbang::uart::*;
 use crate::{
-    gpio::pin::{Gpio0, Gpio42, Pin, PinId, PushPullOutput},
-    time::spin_for,
+    bsp::board::{Board, CurrentBoard},
+    executor::Timer,
+    gpio::{
+        dynpin::{DynInput, DynPin, DynPinId, DynPinMode},
+        pin::{Gpio0, Pin, PinId, PushPullOutput},
+    },
+    time::{Instant, Iso8601},
+};
+use core::{
+    fmt::{self, Write as _},
+    time::Duration,
 };
 
 // Real entrypoint
@@ -23,12 +32,102 @@ mod boot;
 mod bitbang;
 mod bsp;
 mod console;
+mod dmesg;
 mod driver;
+mod exception;
+mod executor;
 mod gpio;
 mod panic_wait;
 mod print;
+mod profile;
+mod shell;
 mod synchronization;
 mod time;
+mod watchdog;
+
+/// Time the ACT LED stays on and off while blinking.
+const HEARTBEAT_PERIOD: Duration = Duration::from_millis(500);
+
+/// Time between the timestamps sent on the bit-banged UART.
+const TIMESTAMP_PERIOD: Duration = Duration::from_secs(1);
+
+const TIMESTAMP_LEN: usize = 64;
+
+/// Pin the timestamps are sent on.
+type TimestampPin = Gpio0;
+
+/// A timestamp formatted on the stack, as the UART takes bytes rather than format strings.
+struct TimestampBuffer {
+    buf: [u8; TIMESTAMP_LEN],
+    len: usize,
+}
+
+impl TimestampBuffer {
+    const fn new() -> Self {
+        Self {
+            buf: [0; TIMESTAMP_LEN],
+            len: 0,
+        }
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl fmt::Write for TimestampBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let buf = self
+            .buf
+            .get_mut(self.len..self.len + s.len())
+            .ok_or(fmt::Error)?;
+        buf.copy_from_slice(s.as_bytes());
+        self.len += s.len();
+
+        Ok(())
+    }
+}
+
+/// Blink the ACT LED, as a sign of life.
+async fn blink(mut pin: DynPin) {
+    let mut on = false;
+
+    loop {
+        on = !on;
+        // The pin is a push-pull output, setting it can't fail.
+        let _ = pin.set_state(on.into());
+
+        Timer::after(HEARTBEAT_PERIOD).await;
+    }
+}
+
+/// Send the time on the bit-banged UART every [`TIMESTAMP_PERIOD`]. The wall clock once it is
+/// set, uptime until then.
+async fn send_timestamps(mut uart: SoftUartTransmitter<Pin<TimestampPin, PushPullOutput>>) {
+    let mut next = Instant::now();
+
+    loop {
+        let mut timestamp = TimestampBuffer::new();
+        let _ = match time::wall_clock() {
+            Some(now) => write!(timestamp, "{}\r\n", Iso8601(now)),
+            None => {
+                let uptime = time::uptime();
+                write!(
+                    timestamp,
+                    "{}.{:06}\r\n",
+                    uptime.as_secs(),
+                    uptime.subsec_micros()
+                )
+            }
+        };
+        // The pin is a push-pull output, writing can't fail.
+        let _ = uart.write_async(timestamp.as_bytes()).await;
+
+        // Scheduled from the previous deadline, so the period doesn't drift.
+        next = next + TIMESTAMP_PERIOD;
+        Timer::at(next).await;
+    }
+}
 
 /// Early init code.
 ///
@@ -37,6 +136,9 @@ mod time;
 /// - Only a single core must be active and running this function.
 /// - The init calls in this function must appear in the correct order.
 pub unsafe fn kernel_init() -> ! {
+    exception::handling_init();
+    time::cycles::init();
+
     // Initialize the BSP driver subsystem.
     if let Err(x) = bsp::init() {
         panic!("Error initializing BSP driver subsystem: {}", x);
@@ -46,35 +148,63 @@ pub unsafe fn kernel_init() -> ! {
     driver::driver_manager().init_drivers();
     // println! is usable from here on.
 
+    if let Err(x) = time::timer::timer_service().init() {
+        panic!("Error initializing the timer service: {}", x);
+    }
+    exception::asynchronous::local_irq_unmask();
+    // Software timers fire from here on.
+
+    #[cfg(feature = "console_semihosting")]
+    if let Err(x) = console::register_semihosting_console() {
+        warn!("Semihosting console not registered: {}", x);
+    }
+
+    #[cfg(feature = "console_soft_uart")]
+    if let Err(x) = console::register_soft_uart_console() {
+        warn!("Soft UART console not registered: {}", x);
+    }
+
+    #[cfg(feature = "power_on_self_test")]
+    if driver::driver_manager().run_power_on_self_tests() == 0 {
+        info!("Power-on self-tests passed");
+    }
+
     // Transition from unsafe to safe.
     kernel_main()
 }
 
 /// The main function running after the early init.
 fn kernel_main() -> ! {
-    use core::time::Duration;
-
     info!(
         "{} version {}",
         env!("CARGO_PKG_NAME"),
         env!("CARGO_PKG_VERSION")
     );
+    info!("Booting on: {}", bsp::board::board_name());
 
     info!(
         "Architectural timer resolution: {} ns",
         time::resolution().as_nanos()
     );
 
+    let (_, privilege_level) = exception::current_privilege_level();
+    info!("Current privilege level: {}", privilege_level);
+
     info!("Drivers loaded:");
     driver::driver_manager().enumerate();
 
-    // Test a failing timer case.
-    time::spin_for(Duration::from_nanos(1));
+    info!("Registered IRQ handlers:");
+    exception::asynchronous::irq_manager().enumerate();
 
-    let led_pin: Pin<Gpio42, <Gpio42 as PinId>::Reset> = unsafe { Pin::new() };
-    let mut led_pin: Pin<_, PushPullOutput> = led_pin.into_mode();
+    // Boards without a directly wired ACT LED just go without the heartbeat.
+    let led_pin = CurrentBoard::ACT_LED_PIN.map(|num| {
+        let mut pin =
+            unsafe { DynPin::new(DynPinId { num }, DynPinMode::Input(DynInput::PullDown)) };
+        pin.into_push_pull_output();
+        pin
+    });
 
-    let uart_pin: Pin<Gpio0, <Gpio0 as PinId>::Reset> = unsafe { Pin::new() };
+    let uart_pin: Pin<TimestampPin, <TimestampPin as PinId>::Reset> = unsafe { Pin::new() };
     let uart_pin: Pin<_, PushPullOutput> = uart_pin.into_mode();
 
     let mut uart = SoftUartTransmitter::<Pin<_, PushPullOutput>>::new(
@@ -84,12 +214,8 @@ fn kernel_main() -> ! {
         ParityMode::Even,
     );
 
-    loop {
-        led_pin.set_high().unwrap();
-        spin_for(Duration::from_millis(200));
-
-        uart.write(
-            "
+    uart.write(
+        "
 \r  _____  _____  _____    _____       _  _       _____  _____  _____
 \r |  _  ||     ||   __|  |  _  | ___ | ||_| ___ |  |  ||   __||  _  |
 \r |   __||   --||__   |  |   __|| . || || ||___||  |  ||__   ||   __|
@@ -99,11 +225,25 @@ fn kernel_main() -> ! {
 \r |  _  ||     ||   __||_  ||_  ||_  ||_  |   _____   |  |    ___ | |_   |  _  | ___  ___  ___
 \r |   __||   --||__   ||_  |  | ||_  ||  _|  |_____|  |  |__ | .'|| . |  |   __||  _|| . ||  _|
 \r |__|   |_____||_____||___|  |_||___||___|  |_____|  |_____||__,||___|  |__|   |_|  |___||___|
-        ",
-        )
-        .unwrap();
+    ",
+    )
+    .unwrap();
+
+    if let Some(led_pin) = led_pin {
+        if let Err(x) = executor::spawn(blink(led_pin)) {
+            warn!("No heartbeat: {}", x);
+        }
+    }
 
-        led_pin.set_low().unwrap();
-        spin_for(Duration::from_millis(1000));
+    if let Err(x) = executor::spawn(send_timestamps(uart)) {
+        warn!("No timestamps: {}", x);
     }
+
+    // Without the executor, the shell still runs on its own.
+    if let Err(x) = executor::spawn(shell::run_async()) {
+        warn!("Shell not spawned: {}", x);
+        shell::run()
+    }
+
+    executor::run()
 }
@@ -2,9 +2,12 @@
 //
 // Copyright (c) 2018-2022 Andre Richter <andre.o.richter@gmail.com>
 
-//! A panic handler that infinitely waits.
+//! A panic handler that infinitely waits, or reboots, see [`crate::watchdog::PanicPolicy`].
 
-use crate::println;
+use crate::{
+    println,
+    watchdog::{self, PanicPolicy},
+};
 use core::panic::PanicInfo;
 
 //--------------------------------------------------------------------------------------------------
@@ -60,6 +63,11 @@ fn panic(info: &PanicInfo) -> ! {
         info.message().unwrap_or(&format_args!("")),
     );
 
+    if watchdog::panic_policy() == PanicPolicy::Reboot {
+        println!("\nRebooting...");
+        watchdog::reboot();
+    }
+
     loop {
         aarch64_cpu::asm::wfe();
     }
@@ -3,14 +3,437 @@
 // Copyright (c) 2018-2022 Andre Richter <andre.o.richter@gmail.com>
 
 //! Printing.
+//!
+//! Besides the plain `print!`/`println!`, there are leveled, timestamped log macros: `error!`,
+//! `warn!`, `info!`, `debug!` and `trace!`.
+//!
+//! Log output is filtered twice:
+//!
+//! - At compile time by the `max_level_*` cargo features. Levels above the maximum compile to
+//!   nothing. Without any of these features, all levels are compiled in.
+//! - At runtime by a global level, which defaults to [`LevelFilter::Info`], and per-module
+//!   overrides matched against `module_path!()`. Both can be changed from the shell with the `log`
+//!   command.
+//!
+//! Every text log line starts with a timestamp in the [`TimestampFormat`] selected at runtime, and
+//! optionally a sequence number counting all log messages, so host tools can tell when lines were
+//! lost.
+//!
+//! With the `binary_log` feature, the log macros send compact binary frames instead of text. See
+//! [`binary`].
 
-use crate::console;
-use core::fmt;
+#[cfg(feature = "binary_log")]
+pub mod binary;
+
+use crate::{
+    console,
+    synchronization::{interface::Mutex, IRQSafeNullLock},
+};
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, AtomicU8, Ordering},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const NUM_MODULE_LEVELS: usize = 8;
+
+/// Longest module path a per-module level can be set for.
+const MODULE_PATH_LEN: usize = 64;
+
+#[derive(Copy, Clone)]
+struct ModuleLevel {
+    path: [u8; MODULE_PATH_LEN],
+    path_len: usize,
+    level: LevelFilter,
+}
+
+struct ModuleLevelsInner {
+    levels: [Option<ModuleLevel>; NUM_MODULE_LEVELS],
+}
+
+/// Timestamp of a log message, rendered in the given format.
+#[cfg(not(feature = "binary_log"))]
+struct Timestamp {
+    format: TimestampFormat,
+    ticks: u64,
+    previous_ticks: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Severity of a log message.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[repr(u8)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// Most verbose level that is let through. `Off` disables logging altogether.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[repr(u8)]
+pub enum LevelFilter {
+    Off = 0,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// How the timestamp of a log message is shown.
+///
+/// Only applies to text output. Binary frames always carry the raw counter value.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum TimestampFormat {
+    /// Seconds since power-on with microseconds, e.g. `  1.234567`.
+    Uptime = 0,
+    /// Raw value of the physical counter.
+    Ticks,
+    /// Nanoseconds since power-on.
+    Nanos,
+    /// Seconds with microseconds since the previous log message, e.g. `+  0.000123`.
+    Delta,
+    /// ISO 8601 UTC, see [`crate::time::Iso8601`]. Uptime until the wall clock is set.
+    WallClock,
+}
+
+/// Maximum level compiled into the kernel, selected by the `max_level_*` features. If several
+/// are enabled, the most restrictive one wins.
+pub const STATIC_MAX_LEVEL: LevelFilter = if cfg!(feature = "max_level_off") {
+    LevelFilter::Off
+} else if cfg!(feature = "max_level_error") {
+    LevelFilter::Error
+} else if cfg!(feature = "max_level_warn") {
+    LevelFilter::Warn
+} else if cfg!(feature = "max_level_info") {
+    LevelFilter::Info
+} else if cfg!(feature = "max_level_debug") {
+    LevelFilter::Debug
+} else {
+    LevelFilter::Trace
+};
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(LevelFilter::Info as u8);
+
+/// Whether any per-module level is set. Spares the table lookup for the common case.
+static HAS_MODULE_LEVELS: AtomicBool = AtomicBool::new(false);
+
+/// Locked from log calls in interrupt handlers too, hence masking IRQs.
+static MODULE_LEVELS: IRQSafeNullLock<ModuleLevelsInner> =
+    IRQSafeNullLock::new(ModuleLevelsInner::new());
+
+static TIMESTAMP_FORMAT: AtomicU8 = AtomicU8::new(TimestampFormat::Uptime as u8);
+
+static SEQUENCE_NUMBERS: AtomicBool = AtomicBool::new(false);
+
+/// Sequence number of the next log message.
+#[cfg(not(feature = "binary_log"))]
+static SEQUENCE: core::sync::atomic::AtomicU32 = core::sync::atomic::AtomicU32::new(0);
+
+/// Counter value at the previous log message, for [`TimestampFormat::Delta`].
+#[cfg(not(feature = "binary_log"))]
+static PREVIOUS_TICKS: core::sync::atomic::AtomicU64 = core::sync::atomic::AtomicU64::new(0);
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl ModuleLevel {
+    fn path(&self) -> &str {
+        core::str::from_utf8(&self.path[..self.path_len]).unwrap_or("")
+    }
+
+    /// Return whether `module_path` is this module or one of its children.
+    fn matches(&self, module_path: &str) -> bool {
+        let path = self.path();
+
+        match module_path.strip_prefix(path) {
+            Some(rest) => rest.is_empty() || rest.starts_with("::"),
+            None => false,
+        }
+    }
+}
+
+impl ModuleLevelsInner {
+    const fn new() -> Self {
+        Self {
+            levels: [None; NUM_MODULE_LEVELS],
+        }
+    }
+
+    /// Return the level of the most specific entry matching `module_path`.
+    fn lookup(&self, module_path: &str) -> Option<LevelFilter> {
+        self.levels
+            .iter()
+            .filter_map(|x| x.as_ref())
+            .filter(|x| x.matches(module_path))
+            .max_by_key(|x| x.path_len)
+            .map(|x| x.level)
+    }
+}
+
+#[cfg(not(feature = "binary_log"))]
+impl Level {
+    /// Prefix put in front of the timestamp.
+    fn prefix(&self) -> &'static str {
+        match self {
+            Level::Error => "E ",
+            Level::Warn => "W ",
+            Level::Info => "  ",
+            Level::Debug => "D ",
+            Level::Trace => "T ",
+        }
+    }
+}
+
+impl LevelFilter {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => LevelFilter::Off,
+            1 => LevelFilter::Error,
+            2 => LevelFilter::Warn,
+            3 => LevelFilter::Info,
+            4 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    }
+}
+
+impl TimestampFormat {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            1 => TimestampFormat::Ticks,
+            2 => TimestampFormat::Nanos,
+            3 => TimestampFormat::Delta,
+            4 => TimestampFormat::WallClock,
+            _ => TimestampFormat::Uptime,
+        }
+    }
+}
+
+#[cfg(not(feature = "binary_log"))]
+impl Timestamp {
+    /// Take the timestamp of a new log message.
+    fn now() -> Self {
+        let ticks = crate::time::counter_ticks();
+
+        Self {
+            format: timestamp_format(),
+            ticks,
+            previous_ticks: PREVIOUS_TICKS.swap(ticks, Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(not(feature = "binary_log"))]
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use crate::time;
+
+        let uptime = time::ticks_to_duration(self.ticks);
+
+        match (self.format, time::wall_clock_offset()) {
+            (TimestampFormat::Ticks, _) => write!(f, "{:>12}", self.ticks),
+            (TimestampFormat::Nanos, _) => write!(f, "{:>13}", uptime.as_nanos()),
+            (TimestampFormat::Delta, _) => {
+                let delta = time::ticks_to_duration(self.ticks.wrapping_sub(self.previous_ticks));
+                write!(f, "+{:>3}.{:06}", delta.as_secs(), delta.subsec_micros())
+            }
+            (TimestampFormat::WallClock, Some(offset)) => {
+                write!(f, "{}", time::Iso8601(offset + uptime))
+            }
+            (TimestampFormat::Uptime | TimestampFormat::WallClock, _) => {
+                write!(f, "{:>3}.{:06}", uptime.as_secs(), uptime.subsec_micros())
+            }
+        }
+    }
+}
+
+/// Return whether a message of `level` from `module_path` passes the runtime filters.
+fn enabled(level: Level, module_path: &str) -> bool {
+    let filter = if HAS_MODULE_LEVELS.load(Ordering::Relaxed) {
+        MODULE_LEVELS.lock(|inner| inner.lookup(module_path))
+    } else {
+        None
+    };
+
+    let filter = filter.unwrap_or_else(max_level);
+
+    level as u8 <= filter as u8
+}
 
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
 
+impl LevelFilter {
+    /// Parse a level name as used by the `log` shell command, e.g. `debug`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(LevelFilter::Off),
+            "error" => Some(LevelFilter::Error),
+            "warn" => Some(LevelFilter::Warn),
+            "info" => Some(LevelFilter::Info),
+            "debug" => Some(LevelFilter::Debug),
+            "trace" => Some(LevelFilter::Trace),
+            _ => None,
+        }
+    }
+
+    /// Return the name of the level, as understood by [`LevelFilter::parse()`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            LevelFilter::Off => "off",
+            LevelFilter::Error => "error",
+            LevelFilter::Warn => "warn",
+            LevelFilter::Info => "info",
+            LevelFilter::Debug => "debug",
+            LevelFilter::Trace => "trace",
+        }
+    }
+}
+
+impl TimestampFormat {
+    /// Parse a format name as used by the `log` shell command, e.g. `delta`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "uptime" => Some(TimestampFormat::Uptime),
+            "ticks" => Some(TimestampFormat::Ticks),
+            "nanos" => Some(TimestampFormat::Nanos),
+            "delta" => Some(TimestampFormat::Delta),
+            "wallclock" => Some(TimestampFormat::WallClock),
+            _ => None,
+        }
+    }
+
+    /// Return the name of the format, as understood by [`TimestampFormat::parse()`].
+    pub fn name(&self) -> &'static str {
+        match self {
+            TimestampFormat::Uptime => "uptime",
+            TimestampFormat::Ticks => "ticks",
+            TimestampFormat::Nanos => "nanos",
+            TimestampFormat::Delta => "delta",
+            TimestampFormat::WallClock => "wallclock",
+        }
+    }
+}
+
+/// Return the format of log timestamps.
+pub fn timestamp_format() -> TimestampFormat {
+    TimestampFormat::from_u8(TIMESTAMP_FORMAT.load(Ordering::Relaxed))
+}
+
+/// Set the format of log timestamps.
+pub fn set_timestamp_format(format: TimestampFormat) {
+    TIMESTAMP_FORMAT.store(format as u8, Ordering::Relaxed);
+}
+
+/// Return whether log lines carry a sequence number.
+pub fn sequence_numbers() -> bool {
+    SEQUENCE_NUMBERS.load(Ordering::Relaxed)
+}
+
+/// Enable or disable sequence numbers on log lines.
+///
+/// The numbers count every log message that passed the filters, whether shown or not, so a gap
+/// always means lost lines.
+pub fn set_sequence_numbers(enable: bool) {
+    SEQUENCE_NUMBERS.store(enable, Ordering::Relaxed);
+}
+
+/// Return the global runtime log level.
+pub fn max_level() -> LevelFilter {
+    LevelFilter::from_u8(MAX_LEVEL.load(Ordering::Relaxed))
+}
+
+/// Set the global runtime log level.
+///
+/// Levels above [`STATIC_MAX_LEVEL`] stay disabled regardless.
+pub fn set_max_level(level: LevelFilter) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Override the log level of a module and its children, e.g. `kernel::shell`.
+///
+/// The most specific override wins over the global level, in either direction.
+pub fn set_module_level(module_path: &str, level: LevelFilter) -> Result<(), &'static str> {
+    if module_path.is_empty() || module_path.len() > MODULE_PATH_LEN {
+        return Err("Invalid module path length");
+    }
+
+    MODULE_LEVELS.lock(|inner| {
+        let slot = match inner
+            .levels
+            .iter()
+            .position(|x| x.map_or(false, |x| x.path() == module_path))
+        {
+            Some(i) => i,
+            None => inner
+                .levels
+                .iter()
+                .position(|x| x.is_none())
+                .ok_or("Too many module levels")?,
+        };
+
+        let mut entry = ModuleLevel {
+            path: [0; MODULE_PATH_LEN],
+            path_len: module_path.len(),
+            level,
+        };
+        entry.path[..module_path.len()].copy_from_slice(module_path.as_bytes());
+        inner.levels[slot] = Some(entry);
+
+        Ok(())
+    })?;
+
+    HAS_MODULE_LEVELS.store(true, Ordering::Relaxed);
+
+    Ok(())
+}
+
+/// Remove the override of a module. Returns whether there was one.
+pub fn clear_module_level(module_path: &str) -> bool {
+    MODULE_LEVELS.lock(|inner| {
+        let Some(i) = inner
+            .levels
+            .iter()
+            .position(|x| x.map_or(false, |x| x.path() == module_path))
+        else {
+            return false;
+        };
+
+        inner.levels[i] = None;
+        HAS_MODULE_LEVELS.store(inner.levels.iter().any(|x| x.is_some()), Ordering::Relaxed);
+
+        true
+    })
+}
+
+/// Call `f` for every per-module override.
+pub fn for_each_module_level(mut f: impl FnMut(&str, LevelFilter)) {
+    // Work on a copy, so `f` may log.
+    let levels = MODULE_LEVELS.lock(|inner| inner.levels);
+
+    levels
+        .iter()
+        .filter_map(|x| x.as_ref())
+        .for_each(|x| f(x.path(), x.level));
+}
+
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     console::console().write_fmt(args).unwrap();
@@ -35,50 +458,113 @@ macro_rules! println {
     })
 }
 
-/// Prints an info, with a newline.
+#[cfg(not(feature = "binary_log"))]
+#[doc(hidden)]
+pub fn _log(level: Level, module_path: &'static str, args: fmt::Arguments) {
+    if !enabled(level, module_path) {
+        return;
+    }
+
+    let timestamp = Timestamp::now();
+    let sequence = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+
+    let console = console::console();
+
+    if sequence_numbers() {
+        console.write_fmt_level(
+            level,
+            format_args!(
+                "[{}{} #{}] {}\r\n",
+                level.prefix(),
+                timestamp,
+                sequence,
+                args
+            ),
+        )
+    } else {
+        console.write_fmt_level(
+            level,
+            format_args!("[{}{}] {}\r\n", level.prefix(), timestamp, args),
+        )
+    }
+    .unwrap();
+}
+
+/// Logs a message at the given level, with a newline.
+///
+/// Compiles to nothing if `level` is above [`STATIC_MAX_LEVEL`].
+#[cfg(not(feature = "binary_log"))]
 #[macro_export]
-macro_rules! info {
-    ($string:expr) => ({
-        let timestamp = $crate::time::uptime();
-
-        $crate::print::_print(format_args!(
-            concat!("[  {:>3}.{:06}] ", $string, "\r\n"),
-            timestamp.as_secs(),
-            timestamp.subsec_micros(),
-        ));
-    });
-    ($format_string:expr, $($arg:tt)*) => ({
-        let timestamp = $crate::time::uptime();
-
-        $crate::print::_print(format_args!(
-            concat!("[  {:>3}.{:06}] ", $format_string, "\r\n"),
-            timestamp.as_secs(),
-            timestamp.subsec_micros(),
-            $($arg)*
-        ));
+macro_rules! log {
+    ($level:expr, $($arg:tt)+) => ({
+        let level: $crate::print::Level = $level;
+
+        if level as u8 <= $crate::print::STATIC_MAX_LEVEL as u8 {
+            $crate::print::_log(level, module_path!(), format_args!($($arg)+));
+        }
     })
 }
 
-/// Prints a warning, with a newline.
+/// Logs a message at the given level, with a newline.
+///
+/// Compiles to nothing if `level` is above [`STATIC_MAX_LEVEL`]. Sends a binary frame, see
+/// [`binary`].
+#[cfg(feature = "binary_log")]
 #[macro_export]
-macro_rules! warn {
-    ($string:expr) => ({
-        let timestamp = $crate::time::uptime();
-
-        $crate::print::_print(format_args!(
-            concat!("[W {:>3}.{:06}] ", $string, "\r\n"),
-            timestamp.as_secs(),
-            timestamp.subsec_micros(),
-        ));
-    });
-    ($format_string:expr, $($arg:tt)*) => ({
-        let timestamp = $crate::time::uptime();
-
-        $crate::print::_print(format_args!(
-            concat!("[W {:>3}.{:06}] ", $format_string, "\r\n"),
-            timestamp.as_secs(),
-            timestamp.subsec_micros(),
-            $($arg)*
-        ));
+macro_rules! log {
+    ($level:expr, $fmt:literal $(, $arg:expr)* $(,)?) => ({
+        const LEVEL: $crate::print::Level = $level;
+
+        if LEVEL as u8 <= $crate::print::STATIC_MAX_LEVEL as u8 {
+            const LEN: usize = $crate::print::binary::entry_len(module_path!(), $fmt);
+
+            #[link_section = ".log_strings"]
+            #[used]
+            static ENTRY: [u8; LEN] = $crate::print::binary::entry(LEVEL, module_path!(), $fmt);
+
+            // Never executed. Lets the compiler check the format string against the arguments.
+            if false {
+                let _ = format_args!($fmt $(, $arg)*);
+            }
+
+            $crate::print::binary::_log(
+                LEVEL,
+                module_path!(),
+                core::ptr::addr_of!(ENTRY) as usize,
+                |_frame| {
+                    $($crate::print::binary::Arg::encode(&$arg, _frame);)*
+                },
+            );
+        }
     })
 }
+
+/// Logs an error, with a newline.
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)+) => ($crate::log!($crate::print::Level::Error, $($arg)+))
+}
+
+/// Logs a warning, with a newline.
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)+) => ($crate::log!($crate::print::Level::Warn, $($arg)+))
+}
+
+/// Logs an info, with a newline.
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)+) => ($crate::log!($crate::print::Level::Info, $($arg)+))
+}
+
+/// Logs debug output, with a newline.
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)+) => ($crate::log!($crate::print::Level::Debug, $($arg)+))
+}
+
+/// Logs trace output, with a newline.
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)+) => ($crate::log!($crate::print::Level::Trace, $($arg)+))
+}
@@ -0,0 +1,358 @@
+//! Binary log output with deferred formatting.
+//!
+//! Enabled by the `binary_log` feature. Instead of formatting on the target, every log call site
+//! places its level, module path and format string into the `.log_strings` ELF section, and only
+//! sends the offset of that entry, the raw counter value and the arguments. The `logdecode` host
+//! tool looks the entries up in the kernel ELF and does the formatting.
+//!
+//! # Entries
+//!
+//! `.log_strings` is not loaded (see `linker.ld`) and starts at address 0, so the address of an
+//! entry is its offset into the section. An entry is the level (`1` error to `5` trace), the
+//! module path, a NUL, the format string and another NUL.
+//!
+//! # Frames
+//!
+//! A frame is a zero byte, the COBS encoded payload and another zero byte. Text printed with
+//! `print!` never contains zero bytes, so the two can be freely mixed on one stream. The payload
+//! is:
+//!
+//! | Field     | Encoding                                             |
+//! |-----------|------------------------------------------------------|
+//! | index     | `u32`, little endian                                 |
+//! | timestamp | `u64` `CNTPCT_EL0` ticks, little endian              |
+//! | arguments | one tag byte each, followed by the value, see below  |
+//! | checksum  | CRC-16/CCITT-FALSE over the above, little endian     |
+//!
+//! | Tag | Value                                                    |
+//! |-----|----------------------------------------------------------|
+//! | 0   | unsigned integer, LEB128                                 |
+//! | 1   | signed integer, zigzag LEB128                            |
+//! | 2   | `f32`, little endian                                     |
+//! | 3   | `f64`, little endian                                     |
+//! | 4   | `bool`, one byte                                         |
+//! | 5   | `char`, LEB128                                           |
+//! | 6   | string, `u16` little endian length followed by UTF-8     |
+//!
+//! Before the first log frame, a metadata frame with index [`META_INDEX`] is sent. Its only
+//! argument is the counter frequency in Hz.
+//!
+//! # Limitations
+//!
+//! Format strings must be literals and arguments positional. Arguments must implement [`Arg`],
+//! which covers integers, floats, `bool`, `char` and `str`. Anything else can be wrapped in
+//! [`Display`], which formats it on the target and sends the resulting string.
+
+use super::Level;
+use crate::{console, time};
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Maximum payload size. Longer frames are cut off, which the decoder reports.
+const FRAME_LEN: usize = 250;
+
+/// A frame on the wire: COBS adds a byte per started 254 bytes, plus possibly an empty last
+/// chunk, and the frame is delimited by a zero on either side.
+const ENCODED_LEN: usize = FRAME_LEN + FRAME_LEN / 254 + 2 + 2;
+
+const TAG_UNSIGNED: u8 = 0;
+const TAG_SIGNED: u8 = 1;
+const TAG_F32: u8 = 2;
+const TAG_F64: u8 = 3;
+const TAG_BOOL: u8 = 4;
+const TAG_CHAR: u8 = 5;
+const TAG_STR: u8 = 6;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Index of the metadata frame.
+pub const META_INDEX: u32 = u32::MAX;
+
+/// Payload of a frame under construction.
+pub struct Frame {
+    buf: [u8; FRAME_LEN],
+    len: usize,
+}
+
+/// A value that can be sent as a log argument.
+pub trait Arg {
+    /// Append the tag and value to `frame`.
+    fn encode(&self, frame: &mut Frame);
+}
+
+/// Wrapper sending any [`fmt::Display`] type as a string, formatted on the target.
+pub struct Display<T>(pub T);
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static META_SENT: AtomicBool = AtomicBool::new(false);
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl Frame {
+    const fn new() -> Self {
+        Self {
+            buf: [0; FRAME_LEN],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.len < FRAME_LEN {
+            self.buf[self.len] = byte;
+            self.len += 1;
+        }
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.push(*byte);
+        }
+    }
+
+    fn push_leb128(&mut self, mut value: u128) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+
+            if value == 0 {
+                self.push(byte);
+                return;
+            }
+            self.push(byte | 0x80);
+        }
+    }
+
+    fn push_unsigned(&mut self, value: u128) {
+        self.push(TAG_UNSIGNED);
+        self.push_leb128(value);
+    }
+
+    fn push_signed(&mut self, value: i128) {
+        self.push(TAG_SIGNED);
+        self.push_leb128(((value << 1) ^ (value >> 127)) as u128);
+    }
+
+    fn push_str(&mut self, s: &str) {
+        let len = usize::min(s.len(), u16::MAX as usize);
+
+        self.push(TAG_STR);
+        self.push_bytes(&(len as u16).to_le_bytes());
+        self.push_bytes(&s.as_bytes()[..len]);
+    }
+
+    /// Append the checksum and write the frame to the console.
+    fn send(mut self) {
+        // The checksum always fits, at the expense of the arguments of an oversized frame.
+        self.len = usize::min(self.len, FRAME_LEN - 2);
+        let crc = crc16(&self.buf[..self.len]);
+        self.push_bytes(&crc.to_le_bytes());
+
+        // Encoded in one go, so the frame reaches the console as a single write.
+        let mut encoded = [0; ENCODED_LEN];
+        let mut len = 1;
+        cobs_encode(&self.buf[..self.len], |byte| {
+            encoded[len] = byte;
+            len += 1;
+        });
+        len += 1;
+
+        console::console().write_bytes(&encoded[..len]);
+    }
+}
+
+impl fmt::Write for Frame {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_bytes(s.as_bytes());
+
+        Ok(())
+    }
+}
+
+/// CRC-16/CCITT-FALSE.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xffff;
+
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+/// Consistent Overhead Byte Stuffing. Emits `data` without any zero bytes.
+fn cobs_encode(data: &[u8], mut emit: impl FnMut(u8)) {
+    for block in data.split(|byte| *byte == 0) {
+        // Blocks longer than 254 bytes are split, without an implied zero in between.
+        let mut chunks = block.chunks(254).peekable();
+        if chunks.peek().is_none() {
+            emit(1);
+        }
+
+        while let Some(chunk) = chunks.next() {
+            emit(chunk.len() as u8 + 1);
+            chunk.iter().for_each(|byte| emit(*byte));
+
+            // A full chunk at the very end of a block needs an empty chunk to carry the zero.
+            if chunk.len() == 254 && chunks.peek().is_none() {
+                emit(1);
+            }
+        }
+    }
+}
+
+fn send_meta() {
+    let mut frame = Frame::new();
+    frame.push_bytes(&META_INDEX.to_le_bytes());
+    frame.push_bytes(&time::counter_ticks().to_le_bytes());
+    frame.push_unsigned(time::counter_frequency() as u128);
+
+    frame.send();
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Size of the `.log_strings` entry for `module_path` and `fmt`.
+pub const fn entry_len(module_path: &str, fmt: &str) -> usize {
+    1 + module_path.len() + 1 + fmt.len() + 1
+}
+
+/// Build the `.log_strings` entry for a call site. `N` must be [`entry_len()`].
+pub const fn entry<const N: usize>(level: Level, module_path: &str, fmt: &str) -> [u8; N] {
+    let mut entry = [0; N];
+    entry[0] = level as u8;
+
+    let mut i = 0;
+    while i < module_path.len() {
+        entry[1 + i] = module_path.as_bytes()[i];
+        i += 1;
+    }
+
+    let fmt_start = 1 + module_path.len() + 1;
+    let mut i = 0;
+    while i < fmt.len() {
+        entry[fmt_start + i] = fmt.as_bytes()[i];
+        i += 1;
+    }
+
+    entry
+}
+
+#[doc(hidden)]
+pub fn _log(level: Level, module_path: &'static str, index: usize, args: impl FnOnce(&mut Frame)) {
+    if !super::enabled(level, module_path) {
+        return;
+    }
+
+    if !META_SENT.swap(true, Ordering::Relaxed) {
+        send_meta();
+    }
+
+    let mut frame = Frame::new();
+    frame.push_bytes(&(index as u32).to_le_bytes());
+    frame.push_bytes(&time::counter_ticks().to_le_bytes());
+    args(&mut frame);
+
+    frame.send();
+}
+
+impl<T: Arg + ?Sized> Arg for &T {
+    fn encode(&self, frame: &mut Frame) {
+        (**self).encode(frame)
+    }
+}
+
+macro_rules! impl_arg_unsigned {
+    ($($t:ty),*) => {
+        $(impl Arg for $t {
+            fn encode(&self, frame: &mut Frame) {
+                frame.push_unsigned(*self as u128)
+            }
+        })*
+    };
+}
+
+macro_rules! impl_arg_signed {
+    ($($t:ty),*) => {
+        $(impl Arg for $t {
+            fn encode(&self, frame: &mut Frame) {
+                frame.push_signed(*self as i128)
+            }
+        })*
+    };
+}
+
+impl_arg_unsigned!(u8, u16, u32, u64, u128, usize);
+impl_arg_signed!(i8, i16, i32, i64, i128, isize);
+
+impl Arg for f32 {
+    fn encode(&self, frame: &mut Frame) {
+        frame.push(TAG_F32);
+        frame.push_bytes(&self.to_le_bytes());
+    }
+}
+
+impl Arg for f64 {
+    fn encode(&self, frame: &mut Frame) {
+        frame.push(TAG_F64);
+        frame.push_bytes(&self.to_le_bytes());
+    }
+}
+
+impl Arg for bool {
+    fn encode(&self, frame: &mut Frame) {
+        frame.push(TAG_BOOL);
+        frame.push(*self as u8);
+    }
+}
+
+impl Arg for char {
+    fn encode(&self, frame: &mut Frame) {
+        frame.push(TAG_CHAR);
+        frame.push_leb128(*self as u128);
+    }
+}
+
+impl Arg for str {
+    fn encode(&self, frame: &mut Frame) {
+        frame.push_str(self)
+    }
+}
+
+impl<T: fmt::Display> Arg for Display<T> {
+    fn encode(&self, frame: &mut Frame) {
+        frame.push(TAG_STR);
+        let len_pos = frame.len;
+        frame.push_bytes(&[0, 0]);
+
+        let start = frame.len;
+        // Frame::write_str can't fail, it truncates instead.
+        let _ = fmt::Write::write_fmt(frame, format_args!("{}", self.0));
+
+        let len = ((frame.len - start) as u16).to_le_bytes();
+        if len_pos + 2 <= FRAME_LEN {
+            frame.buf[len_pos..len_pos + 2].copy_from_slice(&len);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! Code section profiler.
+//!
+//! Times named sections of code on the architectural counter and keeps statistics per name: number
+//! of runs, minimum, mean and maximum, and a histogram with power-of-two buckets. Everything lives
+//! in static storage, so sections can be timed from anywhere, including interrupt context.
+//!
+//! ```ignore
+//! let word = profile!("parse", { parse(line) });
+//!
+//! let stopwatch = Stopwatch::start("send");
+//! send(word);
+//! let elapsed = stopwatch.stop();
+//! ```
+
+use crate::{
+    println,
+    synchronization::{interface::Mutex, IRQSafeNullLock},
+    time::Instant,
+};
+use core::{fmt, time::Duration};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Number of distinct section names that can be tracked.
+const NUM_SECTIONS: usize = 32;
+
+/// Bucket `n` counts runs of 2^n to 2^(n+1) nanoseconds. The last one also takes everything
+/// longer, i.e. from about 18 minutes on.
+const NUM_BUCKETS: usize = 41;
+
+#[derive(Copy, Clone)]
+struct Section {
+    name: &'static str,
+    stats: SectionStats,
+}
+
+struct ProfilerInner {
+    sections: [Option<Section>; NUM_SECTIONS],
+    /// Runs not recorded because all sections were taken.
+    dropped: usize,
+}
+
+/// Displays a power of two nanoseconds with a readable unit, e.g. `512ns` or `16us`.
+struct Pow2Nanos(usize);
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Statistics of a section.
+#[derive(Copy, Clone)]
+pub struct SectionStats {
+    pub count: u64,
+    pub total: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    /// Number of runs per power-of-two bucket of nanoseconds.
+    pub histogram: [u32; NUM_BUCKETS],
+}
+
+/// Times a section of code, and records it when stopped or dropped.
+///
+/// Being recorded on drop, a section left early, e.g. with `?`, is still accounted for.
+pub struct Stopwatch {
+    name: &'static str,
+    start: Instant,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static PROFILER: IRQSafeNullLock<ProfilerInner> = IRQSafeNullLock::new(ProfilerInner::new());
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl ProfilerInner {
+    const fn new() -> Self {
+        Self {
+            sections: [None; NUM_SECTIONS],
+            dropped: 0,
+        }
+    }
+
+    fn record(&mut self, name: &'static str, elapsed: Duration) {
+        let slot = match self
+            .sections
+            .iter()
+            .position(|section| section.map_or(false, |section| section.name == name))
+        {
+            Some(slot) => slot,
+            None => match self.sections.iter().position(|section| section.is_none()) {
+                Some(slot) => slot,
+                None => {
+                    self.dropped += 1;
+                    return;
+                }
+            },
+        };
+
+        self.sections[slot]
+            .get_or_insert(Section {
+                name,
+                stats: SectionStats::new(),
+            })
+            .stats
+            .add(elapsed);
+    }
+}
+
+impl SectionStats {
+    const fn new() -> Self {
+        Self {
+            count: 0,
+            total: Duration::ZERO,
+            min: Duration::MAX,
+            max: Duration::ZERO,
+            histogram: [0; NUM_BUCKETS],
+        }
+    }
+
+    fn add(&mut self, elapsed: Duration) {
+        self.count += 1;
+        self.total = self.total.saturating_add(elapsed);
+        self.min = self.min.min(elapsed);
+        self.max = self.max.max(elapsed);
+
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+        let bucket = nanos.checked_ilog2().unwrap_or(0) as usize;
+        let bucket = &mut self.histogram[bucket.min(NUM_BUCKETS - 1)];
+        *bucket = bucket.saturating_add(1);
+    }
+
+    /// Mean time of a run.
+    pub fn mean(&self) -> Duration {
+        match self.count {
+            0 => Duration::ZERO,
+            count => Duration::from_nanos((self.total.as_nanos() / u128::from(count)) as u64),
+        }
+    }
+}
+
+impl fmt::Display for Pow2Nanos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let nanos = 1u64 << self.0;
+
+        match nanos {
+            0..=999 => write!(f, "{}ns", nanos),
+            1_000..=999_999 => write!(f, "{}us", nanos / 1_000),
+            1_000_000..=999_999_999 => write!(f, "{}ms", nanos / 1_000_000),
+            _ => write!(f, "{}s", nanos / 1_000_000_000),
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Stopwatch {
+    /// Start timing a section.
+    pub fn start(name: &'static str) -> Self {
+        Self {
+            name,
+            start: Instant::now(),
+        }
+    }
+
+    /// Time since the start.
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Stop and record the section. Returns how long it took.
+    pub fn stop(self) -> Duration {
+        let elapsed = self.elapsed();
+        record(self.name, elapsed);
+
+        // Already recorded.
+        core::mem::forget(self);
+
+        elapsed
+    }
+}
+
+impl Drop for Stopwatch {
+    fn drop(&mut self) {
+        record(self.name, self.elapsed());
+    }
+}
+
+/// Record a run of a section that was timed otherwise.
+pub fn record(name: &'static str, elapsed: Duration) {
+    PROFILER.lock(|inner| inner.record(name, elapsed));
+}
+
+/// Forget all sections.
+pub fn reset() {
+    PROFILER.lock(|inner| *inner = ProfilerInner::new());
+}
+
+/// Print the statistics of all sections.
+pub fn dump() {
+    // A copy, so printing doesn't mask IRQs.
+    let (sections, dropped) = PROFILER.lock(|inner| (inner.sections, inner.dropped));
+
+    println!(
+        "{: <24} {: >8} {: >10} {: >10} {: >10}",
+        "section", "count", "min [us]", "mean [us]", "max [us]"
+    );
+    for section in sections.iter().flatten() {
+        let stats = &section.stats;
+        println!(
+            "{: <24} {: >8} {: >10} {: >10} {: >10}",
+            section.name,
+            stats.count,
+            stats.min.as_micros(),
+            stats.mean().as_micros(),
+            stats.max.as_micros()
+        );
+
+        for (bucket, count) in stats.histogram.iter().enumerate() {
+            if *count != 0 {
+                println!("    >= {}: {}", Pow2Nanos(bucket), count);
+            }
+        }
+    }
+
+    if dropped != 0 {
+        println!("{} runs dropped, no free section", dropped);
+    }
+}
+
+/// Time a block as the named section of the profiler, and evaluate to the block's value.
+#[macro_export]
+macro_rules! profile {
+    ($name:expr, $body:block) => {{
+        let _stopwatch = $crate::profile::Stopwatch::start($name);
+        $body
+    }};
+}
@@ -0,0 +1,246 @@
+//! Interactive command shell on the system console.
+//!
+//! Commands are kept in a static registry. The built-ins are registered by [`run()`] or
+//! [`run_async()`], and any subsystem can add its own with [`command_registry()`]:
+//!
+//! ```ignore
+//! fn cmd_hello(args: &[&str]) -> Result<(), &'static str> {
+//!     println!("Hello {:?}", args);
+//!     Ok(())
+//! }
+//!
+//! shell::command_registry().register_command(shell::Command::new(
+//!     "hello",
+//!     "hello [args...] - Greet back",
+//!     cmd_hello,
+//! ))?;
+//! ```
+//!
+//! The handler gets the arguments without the command name itself.
+
+mod commands;
+mod line_editor;
+
+use crate::{
+    console, debug, print, println,
+    synchronization::{interface::Mutex, NullLock},
+};
+use line_editor::{LineEditor, LineEvent};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const NUM_COMMANDS: usize = 32;
+
+/// Maximum number of whitespace separated words of a command line, including the command name.
+const MAX_ARGS: usize = 16;
+
+const PROMPT: &str = "> ";
+
+struct CommandRegistryInner {
+    next_index: usize,
+    commands: [Option<Command>; NUM_COMMANDS],
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Function executing a command. Receives the arguments following the command name.
+pub type CommandHandler = fn(args: &[&str]) -> Result<(), &'static str>;
+
+/// A shell command.
+#[derive(Copy, Clone)]
+pub struct Command {
+    name: &'static str,
+    help: &'static str,
+    handler: CommandHandler,
+}
+
+/// Holds all commands known to the shell.
+pub struct CommandRegistry {
+    inner: NullLock<CommandRegistryInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static COMMAND_REGISTRY: CommandRegistry = CommandRegistry::new();
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl CommandRegistryInner {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        Self {
+            next_index: 0,
+            commands: [None; NUM_COMMANDS],
+        }
+    }
+}
+
+/// Split a line into words and run the matching command.
+fn execute(line: &str) {
+    let mut words = [""; MAX_ARGS];
+    let mut num_words = 0;
+    for word in line.split_whitespace() {
+        if num_words == MAX_ARGS {
+            println!("Too many arguments");
+            return;
+        }
+        words[num_words] = word;
+        num_words += 1;
+    }
+
+    if num_words == 0 {
+        return;
+    }
+
+    debug!("Executing '{}'", line);
+
+    // Copy the command out of the registry, so the handler is free to use it too.
+    let Some(command) = command_registry().find(words[0]) else {
+        println!("Unknown command: {}. Try 'help'.", words[0]);
+        return;
+    };
+
+    if let Err(x) = (command.handler)(&words[1..num_words]) {
+        println!("{}: {}", command.name, x);
+        println!("Usage: {}", command.help);
+    }
+}
+
+/// Register the built-in commands and print the first prompt.
+fn start() -> LineEditor {
+    commands::register_builtins();
+
+    let editor = LineEditor::new(PROMPT);
+
+    console::console().clear_rx();
+    print!("{}", PROMPT);
+
+    editor
+}
+
+/// Feed a character to the line editor, and execute the line once it is complete.
+fn handle_char(editor: &mut LineEditor, c: char) {
+    match editor.handle_char(c) {
+        LineEvent::None => (),
+        LineEvent::Submit => {
+            execute(editor.line());
+            editor.clear();
+            print!("{}", PROMPT);
+        }
+        LineEvent::Cancel => print!("{}", PROMPT),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Command {
+    /// Create an instance.
+    ///
+    /// `help` is shown by the `help` command and on errors. By convention, it starts with the
+    /// usage, followed by ` - ` and a short description.
+    pub const fn new(name: &'static str, help: &'static str, handler: CommandHandler) -> Self {
+        Self {
+            name,
+            help,
+            handler,
+        }
+    }
+
+    /// Return the name of the command.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Return the help text of the command.
+    pub fn help(&self) -> &'static str {
+        self.help
+    }
+}
+
+/// Return a reference to the global CommandRegistry.
+pub fn command_registry() -> &'static CommandRegistry {
+    &COMMAND_REGISTRY
+}
+
+impl CommandRegistry {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        Self {
+            inner: NullLock::new(CommandRegistryInner::new()),
+        }
+    }
+
+    /// Register a command with the shell.
+    pub fn register_command(&self, command: Command) -> Result<(), &'static str> {
+        if self.find(command.name).is_some() {
+            return Err("Command already registered");
+        }
+
+        self.inner.lock(|inner| {
+            if inner.next_index == NUM_COMMANDS {
+                return Err("Command registry full");
+            }
+
+            inner.commands[inner.next_index] = Some(command);
+            inner.next_index += 1;
+
+            Ok(())
+        })
+    }
+
+    /// Look up a command by its name.
+    pub fn find(&self, name: &str) -> Option<Command> {
+        self.inner.lock(|inner| {
+            inner
+                .commands
+                .iter()
+                .filter_map(|x| x.as_ref())
+                .find(|command| command.name == name)
+                .copied()
+        })
+    }
+
+    /// Call `f` for every registered command, in registration order.
+    pub fn for_each(&self, mut f: impl FnMut(&Command)) {
+        // Work on a copy, so `f` may print or even register commands.
+        let commands = self.inner.lock(|inner| inner.commands);
+
+        commands
+            .iter()
+            .filter_map(|x| x.as_ref())
+            .for_each(|x| f(x));
+    }
+}
+
+/// Register the built-in commands and run the shell on the system console.
+pub fn run() -> ! {
+    let console = console::console();
+    let mut editor = start();
+
+    loop {
+        handle_char(&mut editor, console.read_char());
+    }
+}
+
+/// Like [`run()`], as a task of the [`executor`](crate::executor).
+///
+/// Other tasks run while waiting for input. A command still runs to completion before anything
+/// else does.
+pub async fn run_async() {
+    let console = console::console();
+    let mut editor = start();
+
+    loop {
+        handle_char(&mut editor, console::read_char_async(console).await);
+    }
+}
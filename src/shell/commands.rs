@@ -0,0 +1,705 @@
+//! Built-in shell commands.
+
+use super::{command_registry, Command};
+use crate::{
+    bitbang::uart::{ParityMode, SoftUartTransmitter, StopBitsOption},
+    bsp::{
+        self,
+        board::{Board, CurrentBoard},
+    },
+    console::{self, interface::Statistics},
+    dmesg, driver,
+    gpio::{
+        dynpin::{DynDisabled, DynInput, DynOutput, DynPin, DynPinId, DynPinMode},
+        pin::PinId,
+    },
+    print::{self, LevelFilter, TimestampFormat, STATIC_MAX_LEVEL},
+    println, profile,
+    time::{self, clock, clock::interface::Clock, cycles, pps},
+    warn,
+    watchdog::{self, PanicPolicy},
+    TimestampPin,
+};
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use embedded_hal::{
+    digital::v2::{InputPin, OutputPin},
+    serial::Write,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Pins 14 and 15 carry the console, whichever UART it is on. Reconfiguring them would cut off
+/// the shell itself.
+const CONSOLE_PINS: [u8; 2] = [14, 15];
+
+/// System Timer channel used by `clocks compare`.
+const COMPARE_CHANNEL: usize = 1;
+
+/// How long `clocks compare` waits for the interrupt beyond the requested time.
+const COMPARE_GRACE: Duration = Duration::from_millis(100);
+
+/// How long `cycles calibrate` measures.
+const CALIBRATION_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Runs per `cycles bench` case.
+const BENCH_RUNS: u32 = 1000;
+
+const BUILTINS: [Command; 18] = [
+    Command::new("help", "help - List all commands", cmd_help),
+    Command::new("uptime", "uptime - Time since boot", cmd_uptime),
+    Command::new(
+        "drivers",
+        "drivers - List the loaded device drivers",
+        cmd_drivers,
+    ),
+    Command::new(
+        "gpio",
+        "gpio get <pin> | set <pin> <0|1> | mode <pin> <in|out> - Access a GPIO pin",
+        cmd_gpio,
+    ),
+    Command::new("uart", "uart stats - Show UART statistics", cmd_uart),
+    Command::new(
+        "softuart",
+        "softuart send <pin> <baud> <text...> - Send text, 8N1, from a bit-banged UART",
+        cmd_softuart,
+    ),
+    Command::new(
+        "dmesg",
+        "dmesg [-t] [-c] - Show the kernel messages; -t with timestamps, -c clear them afterwards",
+        cmd_dmesg,
+    ),
+    Command::new(
+        "console",
+        "console [level <n> <level> | primary <n>] - Show or configure the console sinks",
+        cmd_console,
+    ),
+    Command::new(
+        "log",
+        "log [<level> | <module> <level|reset> | time <format> | seq <on|off>] - Log settings",
+        cmd_log,
+    ),
+    Command::new(
+        "date",
+        "date [<unix seconds> | <ISO 8601> | nmea <sentence>] - Show or set the wall clock",
+        cmd_date,
+    ),
+    Command::new(
+        "sleep",
+        "sleep <ms> | precision [exact|<us>] - Sleep, or show or set how late sleeps may end",
+        cmd_sleep,
+    ),
+    Command::new(
+        "clocks",
+        "clocks [drift <ms> | compare <ms>] - Show the clocks, or test the System Timer",
+        cmd_clocks,
+    ),
+    Command::new(
+        "cycles",
+        "cycles [calibrate | bench [<pin>]] - Show the cycle counter, calibrate it, time MMIO",
+        cmd_cycles,
+    ),
+    Command::new(
+        "profile",
+        "profile [reset] - Show the profiled code sections, then forget them",
+        cmd_profile,
+    ),
+    Command::new(
+        "pps",
+        "pps [start <pin> | stop] - Show the PPS lock and measured frequency, or start/stop it",
+        cmd_pps,
+    ),
+    Command::new(
+        "clocksync",
+        "clocksync - Serve tools/clocksync until it is done",
+        cmd_clocksync,
+    ),
+    Command::new(
+        "watchdog",
+        "watchdog [start <ms> | feed | stop | panic <halt|reboot>] - Control the watchdog",
+        cmd_watchdog,
+    ),
+    Command::new("reboot", "reboot - Reset the board", cmd_reboot),
+];
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+/// Counter value at which the `clocks compare` interrupt arrived. Zero while it hasn't.
+static COMPARE_MATCHED_AT: AtomicU64 = AtomicU64::new(0);
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Parse a pin number and check that the board has it.
+fn parse_pin(arg: Option<&&str>) -> Result<u8, &'static str> {
+    let num: u8 = arg
+        .ok_or("Missing pin number")?
+        .parse()
+        .map_err(|_| "Invalid pin number")?;
+
+    if num >= CurrentBoard::GPIO_COUNT {
+        return Err("No such pin");
+    }
+
+    Ok(num)
+}
+
+/// Like [`parse_pin`], but rejects the pins in use: the console's, and those driven by the tasks
+/// started at boot.
+fn parse_output_pin(arg: Option<&&str>) -> Result<u8, &'static str> {
+    let num = parse_pin(arg)?;
+
+    if CONSOLE_PINS.contains(&num) {
+        return Err("Pin is used by the console");
+    }
+
+    if num == <TimestampPin as PinId>::DYN.num {
+        return Err("Pin is used by the timestamp UART");
+    }
+
+    if CurrentBoard::ACT_LED_PIN == Some(num) {
+        return Err("Pin is used by the heartbeat");
+    }
+
+    #[cfg(feature = "console_soft_uart")]
+    if [
+        console::SOFT_UART_CONSOLE_PINS.0,
+        console::SOFT_UART_CONSOLE_PINS.1,
+    ]
+    .contains(&num)
+    {
+        return Err("Pin is used by the soft UART console");
+    }
+
+    #[cfg(feature = "extra_uarts")]
+    if bsp::is_extra_uart_pin(num.into()) {
+        return Err("Pin is used by an extra UART");
+    }
+
+    Ok(num)
+}
+
+/// Take control of a pin for reconfiguration.
+fn claim_pin(num: u8) -> DynPin {
+    // The pin mode is unknown, so claim it as disabled. That way, the first `into_mode()` always
+    // programs the function select register. The shell is the only user of arbitrary pins.
+    unsafe {
+        DynPin::new(
+            DynPinId { num },
+            DynPinMode::Disabled(DynDisabled::Floating),
+        )
+    }
+}
+
+fn cmd_help(_args: &[&str]) -> Result<(), &'static str> {
+    command_registry().for_each(|command| println!("  {}", command.help()));
+
+    Ok(())
+}
+
+fn cmd_uptime(_args: &[&str]) -> Result<(), &'static str> {
+    let uptime = time::uptime();
+    println!("{}.{:06} s", uptime.as_secs(), uptime.subsec_micros());
+
+    Ok(())
+}
+
+fn cmd_drivers(_args: &[&str]) -> Result<(), &'static str> {
+    driver::driver_manager().enumerate();
+
+    Ok(())
+}
+
+fn cmd_gpio(args: &[&str]) -> Result<(), &'static str> {
+    match args {
+        ["get", ..] => {
+            let num = parse_pin(args.get(1))?;
+            // Reading the level register works in any mode and changes nothing.
+            let pin =
+                unsafe { DynPin::new(DynPinId { num }, DynPinMode::Input(DynInput::Floating)) };
+            let high = pin.is_high().map_err(|_| "Read failed")?;
+            println!("GPIO {}: {}", num, high as u8);
+        }
+        ["set", _, level] => {
+            let num = parse_output_pin(args.get(1))?;
+            let high = match *level {
+                "0" => false,
+                "1" => true,
+                _ => return Err("Level must be 0 or 1"),
+            };
+
+            let mut pin = claim_pin(num);
+            pin.into_push_pull_output();
+            pin.set_state(high.into()).map_err(|_| "Write failed")?;
+        }
+        ["mode", _, mode] => {
+            let num = parse_output_pin(args.get(1))?;
+            let mode = match *mode {
+                "in" => DynPinMode::Input(DynInput::Floating),
+                "out" => DynPinMode::Output(DynOutput::PushPull),
+                _ => return Err("Mode must be in or out"),
+            };
+
+            claim_pin(num).into_mode(mode);
+        }
+        _ => return Err("Invalid arguments"),
+    }
+
+    Ok(())
+}
+
+fn print_uart_stats(name: &str, uart: &bsp::PL011Uart) {
+    println!(
+        "{}: {}, {} chars written, {} chars read",
+        name,
+        uart.line_config(),
+        uart.chars_written(),
+        uart.chars_read()
+    );
+}
+
+fn cmd_uart(args: &[&str]) -> Result<(), &'static str> {
+    if args != ["stats"] {
+        return Err("Invalid arguments");
+    }
+
+    let console = console::console();
+    println!(
+        "console: {} chars written, {} chars read",
+        console.chars_written(),
+        console.chars_read()
+    );
+
+    print_uart_stats("uart0", bsp::pl011_uart());
+
+    #[cfg(feature = "extra_uarts")]
+    for (name, number) in [("uart3", 3), ("uart4", 4), ("uart5", 5)] {
+        if let Some(uart) = bsp::extra_uart(number) {
+            print_uart_stats(name, uart);
+        }
+    }
+
+    Ok(())
+}
+
+fn cmd_softuart(args: &[&str]) -> Result<(), &'static str> {
+    let ["send", _, baud, text @ ..] = args else {
+        return Err("Invalid arguments");
+    };
+
+    let num = parse_output_pin(args.get(1))?;
+    let baud_rate: u32 = baud.parse().map_err(|_| "Invalid baud rate")?;
+    if baud_rate == 0 {
+        return Err("Invalid baud rate");
+    }
+
+    // Idle high before the first start bit.
+    let mut pin = claim_pin(num);
+    pin.into_push_pull_output();
+    pin.set_high().map_err(|_| "Write failed")?;
+
+    let mut uart = SoftUartTransmitter::new(pin, baud_rate, StopBitsOption::One, ParityMode::None);
+    for (i, word) in text.iter().enumerate() {
+        if i > 0 {
+            nb::block!(uart.write(b' ')).map_err(|_| "Write failed")?;
+        }
+        for c in word.bytes() {
+            nb::block!(uart.write(c)).map_err(|_| "Write failed")?;
+        }
+    }
+    println!(
+        "Latest bit edge: {} ns late",
+        uart.worst_lateness().as_nanos()
+    );
+
+    Ok(())
+}
+
+fn cmd_dmesg(args: &[&str]) -> Result<(), &'static str> {
+    let mut timestamps = false;
+    let mut clear = false;
+    for arg in args {
+        match *arg {
+            "-t" => timestamps = true,
+            "-c" => clear = true,
+            _ => return Err("Invalid arguments"),
+        }
+    }
+
+    dmesg::dump(timestamps);
+
+    let (records, dropped) = dmesg::stats();
+    if dropped > 0 {
+        println!("({} records, {} older ones dropped)", records, dropped);
+    }
+
+    if clear {
+        dmesg::clear();
+    }
+
+    Ok(())
+}
+
+fn parse_level(name: &str) -> Result<LevelFilter, &'static str> {
+    LevelFilter::parse(name).ok_or("Level must be off, error, warn, info, debug or trace")
+}
+
+fn parse_sink(arg: &str) -> Result<usize, &'static str> {
+    arg.parse().map_err(|_| "Invalid sink number")
+}
+
+fn cmd_console(args: &[&str]) -> Result<(), &'static str> {
+    let multi_console = console::multi_console();
+
+    match args {
+        [] => {
+            let primary = multi_console.primary_index();
+            multi_console.for_each_sink(|i, sink| {
+                println!(
+                    "{} {:<12} {:<5} {:>8} written {:>8} read{}",
+                    i,
+                    sink.name(),
+                    sink.level().name(),
+                    sink.console().chars_written(),
+                    sink.console().chars_read(),
+                    if primary == Some(i) { " (primary)" } else { "" }
+                )
+            });
+        }
+        ["level", sink, level] => {
+            multi_console.set_level(parse_sink(sink)?, parse_level(level)?)?
+        }
+        ["primary", sink] => multi_console.set_primary(parse_sink(sink)?)?,
+        _ => return Err("Invalid arguments"),
+    }
+
+    Ok(())
+}
+
+fn cmd_log(args: &[&str]) -> Result<(), &'static str> {
+    match args {
+        [] => {
+            println!(
+                "global: {} (compiled in: {})",
+                print::max_level().name(),
+                STATIC_MAX_LEVEL.name()
+            );
+            print::for_each_module_level(|path, level| println!("{}: {}", path, level.name()));
+            println!(
+                "timestamps: {}, sequence numbers: {}",
+                print::timestamp_format().name(),
+                if print::sequence_numbers() {
+                    "on"
+                } else {
+                    "off"
+                }
+            );
+        }
+        ["time", format] => print::set_timestamp_format(
+            TimestampFormat::parse(format)
+                .ok_or("Format must be uptime, ticks, nanos, delta or wallclock")?,
+        ),
+        ["seq", "on"] => print::set_sequence_numbers(true),
+        ["seq", "off"] => print::set_sequence_numbers(false),
+        [level] => print::set_max_level(parse_level(level)?),
+        [module_path, "reset"] => {
+            if !print::clear_module_level(module_path) {
+                return Err("No level set for this module");
+            }
+        }
+        [module_path, level] => print::set_module_level(module_path, parse_level(level)?)?,
+        _ => return Err("Invalid arguments"),
+    }
+
+    Ok(())
+}
+
+fn cmd_date(args: &[&str]) -> Result<(), &'static str> {
+    match args {
+        [] => match time::wall_clock() {
+            Some(now) => {
+                let now = time::DateTime::from_unix(now);
+                println!("{} {}", now.weekday(), now);
+            }
+            None => println!("Wall clock not set"),
+        },
+        ["nmea", sentence] => {
+            time::nmea::set_wall_clock(sentence)?;
+        }
+        [date_time] if date_time.contains('-') => {
+            time::set_wall_clock(time::DateTime::parse_iso8601(date_time)?.to_unix()?);
+        }
+        [secs] => {
+            let secs: u64 = secs.parse().map_err(|_| "Invalid time")?;
+            time::set_wall_clock(Duration::from_secs(secs));
+        }
+        _ => return Err("Invalid arguments"),
+    }
+
+    Ok(())
+}
+
+fn cmd_sleep(args: &[&str]) -> Result<(), &'static str> {
+    match args {
+        ["precision"] => match time::sleep_precision() {
+            time::SleepPrecision::Exact => println!("exact"),
+            time::SleepPrecision::Within(precision) => {
+                println!("{} us", precision.as_micros())
+            }
+        },
+        ["precision", "exact"] => time::set_sleep_precision(time::SleepPrecision::Exact),
+        ["precision", us] => {
+            let us: u64 = us.parse().map_err(|_| "Invalid precision")?;
+            time::set_sleep_precision(time::SleepPrecision::Within(Duration::from_micros(us)));
+        }
+        [ms] => {
+            let ms: u64 = ms.parse().map_err(|_| "Invalid time")?;
+            let deadline = time::Instant::now()
+                .checked_add(Duration::from_millis(ms))
+                .ok_or("Invalid time")?;
+
+            let overshoot = time::sleep_until(deadline);
+            println!("Woke up {} ns late", overshoot.duration().as_nanos());
+        }
+        _ => return Err("Invalid arguments"),
+    }
+
+    Ok(())
+}
+
+fn compare_matched(_channel: usize) {
+    COMPARE_MATCHED_AT.store(time::counter_ticks(), Ordering::Relaxed);
+}
+
+fn cmd_clocks(args: &[&str]) -> Result<(), &'static str> {
+    let system_timer = bsp::system_timer();
+
+    match args {
+        [] => {
+            let clocks: [&dyn Clock; 3] = [
+                clock::arch_timer_clock(),
+                system_timer,
+                pps::disciplined_clock(),
+            ];
+            for clock in clocks {
+                let uptime = clock.uptime();
+                println!(
+                    "{: <20} {: >10} Hz  {}.{:06} s",
+                    clock.name(),
+                    clock.frequency(),
+                    uptime.as_secs(),
+                    uptime.subsec_micros()
+                );
+            }
+        }
+        ["drift", ms] => {
+            let ms: u64 = ms.parse().map_err(|_| "Invalid time")?;
+            let drift = clock::measure_drift(
+                system_timer,
+                clock::arch_timer_clock(),
+                Duration::from_millis(ms),
+            )?;
+            println!("{}: {}", system_timer.name(), drift);
+        }
+        ["compare", ms] => {
+            let after = Duration::from_millis(ms.parse().map_err(|_| "Invalid time")?);
+
+            COMPARE_MATCHED_AT.store(0, Ordering::Relaxed);
+            let start = time::Instant::now();
+            system_timer.set_compare(COMPARE_CHANNEL, after, compare_matched)?;
+
+            let deadline = time::Deadline::after(after.saturating_add(COMPARE_GRACE));
+            while COMPARE_MATCHED_AT.load(Ordering::Relaxed) == 0 && !deadline.has_passed() {
+                time::sleep_for(Duration::from_millis(1));
+            }
+
+            match COMPARE_MATCHED_AT.load(Ordering::Relaxed) {
+                0 => {
+                    system_timer.cancel_compare(COMPARE_CHANNEL)?;
+                    return Err("No compare interrupt");
+                }
+                ticks => println!(
+                    "Compare interrupt after {} us by CNTPCT",
+                    time::Instant::from_ticks(ticks)
+                        .duration_since(start)
+                        .as_micros()
+                ),
+            }
+        }
+        _ => return Err("Invalid arguments"),
+    }
+
+    Ok(())
+}
+
+/// Time one `cycles bench` case, and print cycles and instructions per run.
+fn bench_case(name: &str, mut f: impl FnMut()) {
+    let result = cycles::bench(BENCH_RUNS, &mut f);
+    let instructions = cycles::count_event(cycles::Event::InstructionsRetired, || {
+        for _ in 0..BENCH_RUNS {
+            f();
+        }
+    });
+
+    match instructions {
+        Ok(instructions) => println!(
+            "{: <20} {}, {} instructions",
+            name,
+            result,
+            instructions / u64::from(BENCH_RUNS)
+        ),
+        Err(_) => println!("{: <20} {}", name, result),
+    }
+}
+
+fn cmd_cycles(args: &[&str]) -> Result<(), &'static str> {
+    match args {
+        [] => println!(
+            "{} cycles at {} Hz, {} ns per cycle",
+            cycles::cycles(),
+            cycles::frequency(),
+            cycles::cycles_to_duration(1000).as_nanos() as f32 / 1000.0
+        ),
+        ["calibrate"] => {
+            let nominal = CurrentBoard::CPU_CLOCK_HZ;
+            let measured = cycles::calibrate(CALIBRATION_INTERVAL)?;
+            println!("Core clock: {} Hz, nominal {} Hz", measured, nominal);
+        }
+        ["bench", pin @ ..] => {
+            bench_case("nothing", || {});
+            bench_case("CNTPCT_EL0 read", || {
+                core::hint::black_box(time::counter_ticks());
+            });
+            bench_case("System Timer read", || {
+                core::hint::black_box(bsp::system_timer().ticks());
+            });
+
+            if let [pin] = pin {
+                let num = parse_output_pin(Some(pin))?;
+                let mut pin = claim_pin(num);
+                pin.into_push_pull_output();
+
+                let mut high = false;
+                bench_case("GPIO write", || {
+                    high = !high;
+                    // The pin is a push-pull output, setting it can't fail.
+                    let _ = pin.set_state(high.into());
+                });
+                bench_case("GPIO read", || {
+                    core::hint::black_box(pin.is_high().unwrap_or_default());
+                });
+            }
+        }
+        _ => return Err("Invalid arguments"),
+    }
+
+    Ok(())
+}
+
+fn cmd_profile(args: &[&str]) -> Result<(), &'static str> {
+    match args {
+        [] => profile::dump(),
+        ["reset"] => profile::reset(),
+        _ => return Err("Invalid arguments"),
+    }
+
+    Ok(())
+}
+
+fn cmd_pps(args: &[&str]) -> Result<(), &'static str> {
+    match args {
+        [] => {
+            let status = pps::status();
+            match status.pin {
+                Some(pin) => println!(
+                    "GPIO {}: {}, {} pulses in a row, {} rejected",
+                    pin, status.lock, status.pulses, status.rejected
+                ),
+                None => println!("PPS {}", status.lock),
+            }
+
+            if let Some(measurement) = status.measurement {
+                println!("Counter: {}", measurement);
+            }
+            if status.pulses > 1 {
+                println!(
+                    "Nominal uptime off by {} ns since the first pulse",
+                    status.phase_error_ns
+                );
+            }
+        }
+        ["start", _] => {
+            let num = parse_output_pin(args.get(1))?;
+
+            claim_pin(num).into_floating_input();
+            pps::start(usize::from(num))?;
+        }
+        ["stop"] => pps::stop()?,
+        _ => return Err("Invalid arguments"),
+    }
+
+    Ok(())
+}
+
+fn cmd_clocksync(_args: &[&str]) -> Result<(), &'static str> {
+    time::sync::serve();
+
+    match time::wall_clock() {
+        Some(now) => println!("Wall clock: {}", time::Iso8601(now)),
+        None => println!("Wall clock not set"),
+    }
+
+    Ok(())
+}
+
+fn cmd_watchdog(args: &[&str]) -> Result<(), &'static str> {
+    match args {
+        [] => {
+            match watchdog::remaining() {
+                Some(remaining) => println!("Watchdog expires in {} ms", remaining.as_millis()),
+                None => println!("Watchdog stopped"),
+            }
+            println!("On panic: {}", watchdog::panic_policy());
+        }
+        ["start", ms] => {
+            let ms: u64 = ms.parse().map_err(|_| "Invalid time")?;
+            watchdog::start(Duration::from_millis(ms))?;
+        }
+        ["feed"] => watchdog::feed(),
+        ["stop"] => watchdog::stop(),
+        ["panic", policy] => watchdog::set_panic_policy(match *policy {
+            "halt" => PanicPolicy::Halt,
+            "reboot" => PanicPolicy::Reboot,
+            _ => return Err("Policy must be halt or reboot"),
+        }),
+        _ => return Err("Invalid arguments"),
+    }
+
+    Ok(())
+}
+
+fn cmd_reboot(_args: &[&str]) -> Result<(), &'static str> {
+    println!("Rebooting...");
+
+    watchdog::reboot()
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Register the built-in commands.
+pub fn register_builtins() {
+    for command in BUILTINS {
+        if let Err(x) = command_registry().register_command(command) {
+            warn!("Cannot register shell command {}: {}", command.name(), x);
+        }
+    }
+}
@@ -0,0 +1,311 @@
+//! Line editing for the shell.
+//!
+//! Understands printable ASCII, backspace/delete, Ctrl-C, tab completion of command names and
+//! the up/down arrow keys (`ESC [ A`, `ESC [ B`) for the history. Everything is echoed to the
+//! console directly.
+
+use super::command_registry;
+use crate::{console, print, println};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Maximum length of a command line.
+const LINE_LEN: usize = 128;
+
+/// Number of lines kept in the history.
+const HISTORY_LEN: usize = 8;
+
+/// Terminal bell, signals that a key had no effect.
+const BELL: char = '\x07';
+
+#[derive(Copy, Clone)]
+struct Line {
+    buf: [u8; LINE_LEN],
+    len: usize,
+}
+
+/// Ring buffer of the last submitted lines.
+struct History {
+    lines: [Line; HISTORY_LEN],
+    next: usize,
+    count: usize,
+}
+
+/// Progress through an ANSI escape sequence.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum EscapeState {
+    Normal,
+    Escape,
+    Csi,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// What happened to the line after a character was handled.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum LineEvent {
+    /// Still editing.
+    None,
+    /// Enter was pressed. The line is available through [`LineEditor::line()`] until
+    /// [`LineEditor::clear()`] is called.
+    Submit,
+    /// Ctrl-C was pressed and the line was discarded.
+    Cancel,
+}
+
+/// Line editor state.
+pub struct LineEditor {
+    prompt: &'static str,
+    line: Line,
+    history: History,
+    /// Currently shown history entry, 0 being the newest. `None` while editing a new line.
+    history_pos: Option<usize>,
+    escape: EscapeState,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl Line {
+    const fn new() -> Self {
+        Self {
+            buf: [0; LINE_LEN],
+            len: 0,
+        }
+    }
+
+    fn as_str(&self) -> &str {
+        // Only ASCII ever gets in.
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+
+    fn push(&mut self, c: u8) -> bool {
+        if self.len == LINE_LEN {
+            return false;
+        }
+
+        self.buf[self.len] = c;
+        self.len += 1;
+
+        true
+    }
+
+    fn pop(&mut self) -> bool {
+        if self.len == 0 {
+            return false;
+        }
+
+        self.len -= 1;
+
+        true
+    }
+}
+
+impl History {
+    const fn new() -> Self {
+        Self {
+            lines: [Line::new(); HISTORY_LEN],
+            next: 0,
+            count: 0,
+        }
+    }
+
+    /// Add a line, unless it is empty or repeats the newest entry.
+    fn push(&mut self, line: &Line) {
+        if line.len == 0 || self.get(0).map(|x| x.as_str()) == Some(line.as_str()) {
+            return;
+        }
+
+        self.lines[self.next] = *line;
+        self.next = (self.next + 1) % HISTORY_LEN;
+        self.count = usize::min(self.count + 1, HISTORY_LEN);
+    }
+
+    /// Return the entry `age` lines back, 0 being the newest.
+    fn get(&self, age: usize) -> Option<&Line> {
+        if age >= self.count {
+            return None;
+        }
+
+        Some(&self.lines[(self.next + HISTORY_LEN - 1 - age) % HISTORY_LEN])
+    }
+}
+
+impl LineEditor {
+    /// Reprint the prompt and the whole line, then erase whatever was left behind.
+    fn redraw(&self) {
+        print!("\r{}{}\x1b[K", self.prompt, self.line.as_str());
+    }
+
+    fn insert(&mut self, c: u8) {
+        if self.line.push(c) {
+            console::console().write_char(c as char);
+        } else {
+            console::console().write_char(BELL);
+        }
+    }
+
+    fn backspace(&mut self) {
+        if self.line.pop() {
+            print!("\x08 \x08");
+        } else {
+            console::console().write_char(BELL);
+        }
+    }
+
+    fn show_history(&mut self, pos: Option<usize>) {
+        self.line = match pos {
+            None => Line::new(),
+            Some(age) => match self.history.get(age) {
+                Some(line) => *line,
+                None => {
+                    console::console().write_char(BELL);
+                    return;
+                }
+            },
+        };
+
+        self.history_pos = pos;
+        self.redraw();
+    }
+
+    fn history_up(&mut self) {
+        self.show_history(Some(self.history_pos.map_or(0, |age| age + 1)));
+    }
+
+    fn history_down(&mut self) {
+        match self.history_pos {
+            None => console::console().write_char(BELL),
+            Some(0) => self.show_history(None),
+            Some(age) => self.show_history(Some(age - 1)),
+        }
+    }
+
+    /// Complete the command name. Arguments are not completed.
+    fn complete(&mut self) {
+        let line = self.line;
+        let prefix = line.as_str();
+        if prefix.contains(' ') {
+            console::console().write_char(BELL);
+            return;
+        }
+
+        let mut num_matches = 0;
+        let mut first_match = "";
+        let mut common_len = 0;
+        command_registry().for_each(|command| {
+            let name = command.name();
+            if !name.starts_with(prefix) {
+                return;
+            }
+
+            if num_matches == 0 {
+                first_match = name;
+                common_len = name.len();
+            } else {
+                common_len = first_match
+                    .bytes()
+                    .zip(name.bytes())
+                    .take(common_len)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+            }
+            num_matches += 1;
+        });
+
+        match num_matches {
+            0 => console::console().write_char(BELL),
+            1 => {
+                for c in first_match[prefix.len()..].bytes() {
+                    self.insert(c);
+                }
+                self.insert(b' ');
+            }
+            _ if common_len > prefix.len() => {
+                for c in first_match[prefix.len()..common_len].bytes() {
+                    self.insert(c);
+                }
+            }
+            _ => {
+                // Ambiguous, list the candidates below the line.
+                println!();
+                command_registry().for_each(|command| {
+                    if command.name().starts_with(prefix) {
+                        print!("{}  ", command.name());
+                    }
+                });
+                println!();
+                self.redraw();
+            }
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl LineEditor {
+    /// Create an instance.
+    pub const fn new(prompt: &'static str) -> Self {
+        Self {
+            prompt,
+            line: Line::new(),
+            history: History::new(),
+            history_pos: None,
+            escape: EscapeState::Normal,
+        }
+    }
+
+    /// Return the current line.
+    pub fn line(&self) -> &str {
+        self.line.as_str()
+    }
+
+    /// Start over with an empty line.
+    pub fn clear(&mut self) {
+        self.line = Line::new();
+        self.history_pos = None;
+    }
+
+    /// Feed one received character into the editor.
+    pub fn handle_char(&mut self, c: char) -> LineEvent {
+        match (self.escape, c) {
+            (EscapeState::Escape, '[') => {
+                self.escape = EscapeState::Csi;
+                return LineEvent::None;
+            }
+            (EscapeState::Csi, 'A') => self.history_up(),
+            (EscapeState::Csi, 'B') => self.history_down(),
+            // Unsupported sequences are dropped.
+            (EscapeState::Escape | EscapeState::Csi, _) => (),
+
+            (EscapeState::Normal, '\x1b') => {
+                self.escape = EscapeState::Escape;
+                return LineEvent::None;
+            }
+            (EscapeState::Normal, '\n') => {
+                println!();
+                self.history.push(&self.line);
+                return LineEvent::Submit;
+            }
+            (EscapeState::Normal, '\x03') => {
+                print!("^C\r\n");
+                self.clear();
+                return LineEvent::Cancel;
+            }
+            (EscapeState::Normal, '\x08' | '\x7f') => self.backspace(),
+            (EscapeState::Normal, '\t') => self.complete(),
+            (EscapeState::Normal, ' '..='~') => self.insert(c as u8),
+            (EscapeState::Normal, _) => (),
+        }
+
+        self.escape = EscapeState::Normal;
+        LineEvent::None
+    }
+}
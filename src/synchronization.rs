@@ -10,6 +10,7 @@
 //!   - <https://stackoverflow.com/questions/59428096/understanding-the-send-trait>
 //!   - <https://doc.rust-lang.org/std/cell/index.html>
 
+use crate::exception;
 use core::cell::UnsafeCell;
 
 //--------------------------------------------------------------------------------------------------
@@ -44,6 +45,17 @@ where
     data: UnsafeCell<T>,
 }
 
+/// A pseudo-lock that is safe to share with interrupt handlers.
+///
+/// Like [`NullLock`], but masks IRQs on the executing core for the duration of the closure, so an
+/// interrupt handler locking the same data can never preempt the holder.
+pub struct IRQSafeNullLock<T>
+where
+    T: ?Sized,
+{
+    data: UnsafeCell<T>,
+}
+
 //--------------------------------------------------------------------------------------------------
 // Public Code
 //--------------------------------------------------------------------------------------------------
@@ -60,6 +72,18 @@ impl<T> NullLock<T> {
     }
 }
 
+unsafe impl<T> Send for IRQSafeNullLock<T> where T: ?Sized + Send {}
+unsafe impl<T> Sync for IRQSafeNullLock<T> where T: ?Sized + Send {}
+
+impl<T> IRQSafeNullLock<T> {
+    /// Create an instance.
+    pub const fn new(data: T) -> Self {
+        Self {
+            data: UnsafeCell::new(data),
+        }
+    }
+}
+
 //------------------------------------------------------------------------------
 // OS Interface Code
 //------------------------------------------------------------------------------
@@ -75,3 +99,16 @@ impl<T> interface::Mutex for NullLock<T> {
         f(data)
     }
 }
+
+impl<T> interface::Mutex for IRQSafeNullLock<T> {
+    type Data = T;
+
+    fn lock<'a, R>(&'a self, f: impl FnOnce(&'a mut Self::Data) -> R) -> R {
+        // In a real lock, there would be code encapsulating this line that ensures that this
+        // mutable reference will ever only be given out once at a time.
+        let data = unsafe { &mut *self.data.get() };
+
+        // Execute the closure while IRQs are masked.
+        exception::asynchronous::exec_with_irq_masked(|| f(data))
+    }
+}
@@ -1,8 +1,28 @@
+pub mod calendar;
+pub mod clock;
+pub mod cycles;
+mod delay;
+mod instant;
+pub mod nmea;
+pub mod pps;
+mod sleep;
+pub mod sync;
+pub mod timer;
+
+pub use calendar::{DateTime, Iso8601};
+// For embedded-hal drivers, none of which is in the tree right now.
+#[allow(unused_imports)]
+pub use delay::Delay;
+pub use instant::{Deadline, Instant};
+pub use sleep::{set_sleep_precision, sleep_for, sleep_precision, sleep_until, SleepPrecision};
+
 use crate::warn;
 use aarch64_cpu::{asm::barrier, registers::*};
 use core::{
-    num::{NonZeroU128, NonZeroU32, NonZeroU64},
+    fmt,
+    num::{NonZeroU32, NonZeroU64},
     ops::{Add, Div},
+    sync::atomic::{AtomicU64, Ordering},
     time::Duration,
 };
 use tock_registers::interfaces::Readable;
@@ -16,6 +36,37 @@ const NANOSEC_PER_SEC: NonZeroU64 = NonZeroU64::new(1_000_000_000).unwrap();
 #[derive(Copy, Clone, PartialOrd, PartialEq)]
 struct GenericTimerCounterValue(u64);
 
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// How a duration that falls between two counter ticks is converted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rounding {
+    /// To the previous tick. Waits are at most the requested time.
+    Floor,
+    /// To the closest tick, halfway rounded up.
+    Nearest,
+    /// To the next tick. Waits are at least the requested time.
+    Ceil,
+}
+
+/// Errors of the fallible time functions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimeError {
+    /// The duration doesn't fit the counter.
+    TooLarge,
+    /// The duration is not zero, but rounds to zero ticks.
+    BelowResolution,
+}
+
+/// How late a wait ended, i.e. how far the counter was past the target when it was seen.
+///
+/// Zero is as punctual as it gets: the wait ended on the first counter value at or after the
+/// target. Interrupts taken while waiting, or a target that had already passed, show up here.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Overshoot(u64);
+
 //--------------------------------------------------------------------------------------------------
 // Global instances
 //--------------------------------------------------------------------------------------------------
@@ -25,6 +76,9 @@ struct GenericTimerCounterValue(u64);
 #[no_mangle]
 pub static ARCH_TIMER_COUNTER_FREQUENCY: NonZeroU32 = NonZeroU32::MIN;
 
+/// Wall-clock time at uptime zero, in nanoseconds since the Unix epoch. Zero while unset.
+static WALL_CLOCK_OFFSET: AtomicU64 = AtomicU64::new(0);
+
 //--------------------------------------------------------------------------------------------------
 // Private Code
 //--------------------------------------------------------------------------------------------------
@@ -79,29 +133,14 @@ fn max_duration() -> Duration {
     Duration::from(GenericTimerCounterValue::MAX)
 }
 
-impl TryFrom<Duration> for GenericTimerCounterValue {
-    type Error = &'static str;
-
-    fn try_from(duration: Duration) -> Result<Self, Self::Error> {
-        if duration < resolution() {
-            return Ok(GenericTimerCounterValue(0));
-        }
-
-        if duration > max_duration() {
-            return Err("Conversion error. Duration too big");
-        }
-
-        let frequency: u128 = u32::from(arch_timer_counter_frequency()) as u128;
-        let duration: u128 = duration.as_nanos();
-
-        // This is safe, because frequency can never be greater than u32::MAX, and
-        // (Duration::MAX.as_nanos() * u32::MAX) < u128::MAX.
-        let counter_value =
-            unsafe { duration.unchecked_mul(frequency) }.div(NonZeroU128::from(NANOSEC_PER_SEC));
-
-        // Since we checked above that we are <= max_duration(), just cast to u64.
-        Ok(GenericTimerCounterValue(counter_value as u64))
+fn spin_until_ticks(target: u64) -> Overshoot {
+    // Read CNTPCT_EL0 directly to avoid the ISB that is part of [`read_cntpct`].
+    let mut now = CNTPCT_EL0.get();
+    while now < target {
+        now = CNTPCT_EL0.get();
     }
+
+    Overshoot(now - target)
 }
 
 #[inline(always)]
@@ -129,21 +168,137 @@ pub fn uptime() -> Duration {
     read_cntpct().into()
 }
 
-/// Spin for a given duration.
-pub fn spin_for(duration: Duration) {
-    let curr_counter_value = read_cntpct();
+/// Raw value of the physical counter, `CNTPCT_EL0`.
+pub fn counter_ticks() -> u64 {
+    read_cntpct().0
+}
 
-    let counter_value_delta: GenericTimerCounterValue = match duration.try_into() {
-        Err(msg) => {
-            warn!("spin_for: {}. Skipping", msg);
-            return;
-        }
-        Ok(val) => val,
+/// Frequency of the physical counter in Hz.
+pub fn counter_frequency() -> u32 {
+    arch_timer_counter_frequency().get()
+}
+
+/// Convert a value of the physical counter to a duration.
+pub fn ticks_to_duration(ticks: u64) -> Duration {
+    GenericTimerCounterValue(ticks).into()
+}
+
+/// Convert a duration to counter ticks.
+pub fn duration_to_ticks(duration: Duration, rounding: Rounding) -> Result<u64, TimeError> {
+    if duration > max_duration() {
+        return Err(TimeError::TooLarge);
+    }
+
+    let frequency = u128::from(arch_timer_counter_frequency().get());
+    let nanos_per_sec = u128::from(NANOSEC_PER_SEC.get());
+
+    // Cannot overflow, because (Duration::MAX.as_nanos() * u32::MAX) < u128::MAX.
+    let scaled = duration.as_nanos() * frequency;
+    let ticks = match rounding {
+        Rounding::Floor => scaled / nanos_per_sec,
+        Rounding::Nearest => (scaled + nanos_per_sec / 2) / nanos_per_sec,
+        Rounding::Ceil => (scaled + nanos_per_sec - 1) / nanos_per_sec,
     };
-    let counter_value_target = curr_counter_value + counter_value_delta;
 
-    // Busy wait.
-    //
-    // Read CNTPCT_EL0 directly to avoid the ISB that is part of [`read_cntpct`].
-    while GenericTimerCounterValue(CNTPCT_EL0.get()) < counter_value_target {}
+    // Rounding up max_duration() can still leave the range.
+    u64::try_from(ticks).map_err(|_| TimeError::TooLarge)
+}
+
+/// Set the wall clock, as time since the Unix epoch.
+pub fn set_wall_clock(now: Duration) {
+    set_wall_clock_offset(now.saturating_sub(uptime()));
+}
+
+/// Set the wall clock by its offset from uptime, i.e. the time since the Unix epoch at uptime zero.
+pub fn set_wall_clock_offset(offset: Duration) {
+    // Zero means unset.
+    WALL_CLOCK_OFFSET.store((offset.as_nanos() as u64).max(1), Ordering::Relaxed);
+}
+
+/// Wall-clock time at uptime zero, if the wall clock was set.
+pub fn wall_clock_offset() -> Option<Duration> {
+    match WALL_CLOCK_OFFSET.load(Ordering::Relaxed) {
+        0 => None,
+        nanos => Some(Duration::from_nanos(nanos)),
+    }
+}
+
+/// Current wall-clock time since the Unix epoch, if it was set.
+pub fn wall_clock() -> Option<Duration> {
+    wall_clock_offset().map(|offset| offset + uptime())
+}
+
+impl TimeError {
+    /// A description of the error.
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            TimeError::TooLarge => "Duration too big",
+            TimeError::BelowResolution => "Duration below timer resolution",
+        }
+    }
+}
+
+impl fmt::Display for TimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Overshoot {
+    /// The overshoot in counter ticks.
+    pub const fn ticks(&self) -> u64 {
+        self.0
+    }
+
+    /// The overshoot as a duration.
+    pub fn duration(&self) -> Duration {
+        GenericTimerCounterValue(self.0).into()
+    }
+
+    /// How far the counter is past `target` now, e.g. in a timer callback meant to run at it.
+    pub fn since(target: Instant) -> Self {
+        Overshoot(Instant::now().ticks().saturating_sub(target.ticks()))
+    }
+}
+
+/// Spin until the counter reaches the given instant. Returns right away if it already has.
+pub fn spin_until(instant: Instant) -> Overshoot {
+    spin_until_ticks(instant.ticks())
+}
+
+/// Spin for a given duration, rounded up to the next counter tick.
+///
+/// See [`try_spin_for_rounded`].
+pub fn try_spin_for(duration: Duration) -> Result<Overshoot, TimeError> {
+    try_spin_for_rounded(duration, Rounding::Ceil)
+}
+
+/// Spin for a given duration, rounded to counter ticks as requested.
+///
+/// The wait is measured from the counter value read on entry, so it ends up to a tick earlier than
+/// the rounded duration if the counter was about to advance. Returns without waiting if the
+/// duration doesn't fit the counter, or rounds to zero ticks although it isn't zero.
+pub fn try_spin_for_rounded(
+    duration: Duration,
+    rounding: Rounding,
+) -> Result<Overshoot, TimeError> {
+    let start = read_cntpct().0;
+
+    let ticks = duration_to_ticks(duration, rounding)?;
+    if ticks == 0 && !duration.is_zero() {
+        return Err(TimeError::BelowResolution);
+    }
+    let target = start.checked_add(ticks).ok_or(TimeError::TooLarge)?;
+
+    Ok(spin_until_ticks(target))
+}
+
+/// Spin for a given duration, rounded down to the previous counter tick.
+///
+/// Durations below [`resolution()`] return right away. Durations that don't fit the counter are
+/// skipped with a warning. Use [`try_spin_for`] to find out about either.
+pub fn spin_for(duration: Duration) {
+    if let Err(TimeError::TooLarge) = try_spin_for_rounded(duration, Rounding::Floor) {
+        warn!("spin_for: {}. Skipping", TimeError::TooLarge.as_str());
+    }
 }
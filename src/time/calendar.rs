@@ -0,0 +1,299 @@
+//! Calendar dates and times of day, in UTC.
+//!
+//! Converts between time since the Unix epoch and the proleptic Gregorian calendar, with the
+//! algorithms from <http://howardhinnant.github.io/date_algorithms.html>. Leap seconds are not
+//! accounted for, like in Unix time.
+
+use core::{fmt, time::Duration};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// Days from 0000-03-01 to 1970-01-01.
+const UNIX_EPOCH_DAYS: u64 = 719_468;
+
+const DAYS_PER_ERA: u64 = 146_097;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A point in time broken down into calendar date and time of day.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct DateTime {
+    pub year: u64,
+    /// 1 to 12.
+    pub month: u8,
+    /// 1 to 31.
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    /// Up to 60, for a leap second.
+    pub second: u8,
+    pub nanosecond: u32,
+}
+
+/// Day of the week.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+/// Displays a time since the Unix epoch as ISO 8601 UTC, e.g. `2024-05-01T12:00:00.000000Z`.
+pub struct Iso8601(pub Duration);
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Convert days since the Unix epoch to year, month and day.
+fn civil_from_days(days: u64) -> (u64, u8, u8) {
+    let z = days + UNIX_EPOCH_DAYS;
+    let era = z / DAYS_PER_ERA;
+    let day_of_era = z - era * DAYS_PER_ERA;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u8;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u8;
+    let year = year_of_era + era * 400 + (month <= 2) as u64;
+
+    (year, month, day)
+}
+
+/// Convert a date to days since the Unix epoch. The date must be valid and not before the epoch.
+fn days_from_civil(year: u64, month: u8, day: u8) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let mp = (u64::from(month) + 9) % 12;
+    let day_of_year = (153 * mp + 2) / 5 + u64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * DAYS_PER_ERA + day_of_era - UNIX_EPOCH_DAYS
+}
+
+fn is_leap_year(year: u64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: u64, month: u8) -> u8 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Take `len` decimal digits from the front of `s`.
+fn take_digits(s: &mut &[u8], len: usize) -> Result<u64, &'static str> {
+    if s.len() < len || !s[..len].iter().all(u8::is_ascii_digit) {
+        return Err("Expected digits");
+    }
+
+    let value = s[..len]
+        .iter()
+        .fold(0, |value, digit| value * 10 + u64::from(digit - b'0'));
+    *s = &s[len..];
+
+    Ok(value)
+}
+
+/// Take `c` from the front of `s`.
+fn take_char(s: &mut &[u8], c: u8) -> Result<(), &'static str> {
+    match s.split_first() {
+        Some((first, rest)) if *first == c => {
+            *s = rest;
+            Ok(())
+        }
+        _ => Err("Unexpected character"),
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl DateTime {
+    /// Break down a time since the Unix epoch.
+    pub fn from_unix(since_epoch: Duration) -> Self {
+        let secs = since_epoch.as_secs();
+        let (year, month, day) = civil_from_days(secs / SECS_PER_DAY);
+        let secs_of_day = secs % SECS_PER_DAY;
+
+        Self {
+            year,
+            month,
+            day,
+            hour: (secs_of_day / 3600) as u8,
+            minute: (secs_of_day / 60 % 60) as u8,
+            second: (secs_of_day % 60) as u8,
+            nanosecond: since_epoch.subsec_nanos(),
+        }
+    }
+
+    /// Time since the Unix epoch. A leap second counts as the first second of the next minute.
+    pub fn to_unix(&self) -> Result<Duration, &'static str> {
+        if self.year < 1970 {
+            return Err("Date before the Unix epoch");
+        }
+        if !(1..=12).contains(&self.month) || self.day < 1 {
+            return Err("Invalid date");
+        }
+        if self.day > days_in_month(self.year, self.month) {
+            return Err("Invalid date");
+        }
+        if self.hour > 23
+            || self.minute > 59
+            || self.second > 60
+            || self.nanosecond >= 1_000_000_000
+        {
+            return Err("Invalid time of day");
+        }
+
+        let days = days_from_civil(self.year, self.month, self.day);
+        let secs = days
+            .checked_mul(SECS_PER_DAY)
+            .and_then(|secs| {
+                secs.checked_add(
+                    u64::from(self.hour) * 3600
+                        + u64::from(self.minute) * 60
+                        + u64::from(self.second),
+                )
+            })
+            .ok_or("Date too far in the future")?;
+
+        Ok(Duration::new(secs, self.nanosecond))
+    }
+
+    /// Day of the week. The date must be valid.
+    pub fn weekday(&self) -> Weekday {
+        // 1970-01-01 was a Thursday.
+        match (days_from_civil(self.year, self.month, self.day) + 3) % 7 {
+            0 => Weekday::Monday,
+            1 => Weekday::Tuesday,
+            2 => Weekday::Wednesday,
+            3 => Weekday::Thursday,
+            4 => Weekday::Friday,
+            5 => Weekday::Saturday,
+            _ => Weekday::Sunday,
+        }
+    }
+
+    /// Parse an ISO 8601 UTC date and time, `YYYY-MM-DDThh:mm:ss`, with optional fractional
+    /// seconds of up to nine digits and an optional `Z`.
+    pub fn parse_iso8601(s: &str) -> Result<Self, &'static str> {
+        let mut s = s.as_bytes();
+
+        let year = take_digits(&mut s, 4)?;
+        take_char(&mut s, b'-')?;
+        let month = take_digits(&mut s, 2)? as u8;
+        take_char(&mut s, b'-')?;
+        let day = take_digits(&mut s, 2)? as u8;
+        take_char(&mut s, b'T')?;
+        let (hour, minute, second, nanosecond) = Self::parse_time_of_day(&mut s, true)?;
+
+        if s == b"Z" {
+            s = &[];
+        }
+        if !s.is_empty() {
+            return Err("Trailing characters, only UTC is supported");
+        }
+
+        let date_time = Self {
+            year,
+            month,
+            day,
+            hour,
+            minute,
+            second,
+            nanosecond,
+        };
+        date_time.to_unix()?;
+
+        Ok(date_time)
+    }
+
+    /// Parse a time of day with optional fractional seconds from the front of `s`, either
+    /// `hh:mm:ss` or, without `separated`, `hhmmss`.
+    pub(super) fn parse_time_of_day(
+        s: &mut &[u8],
+        separated: bool,
+    ) -> Result<(u8, u8, u8, u32), &'static str> {
+        let hour = take_digits(s, 2)? as u8;
+        if separated {
+            take_char(s, b':')?;
+        }
+        let minute = take_digits(s, 2)? as u8;
+        if separated {
+            take_char(s, b':')?;
+        }
+        let second = take_digits(s, 2)? as u8;
+
+        let mut nanosecond = 0;
+        if take_char(s, b'.').is_ok() {
+            let digits = s.iter().take_while(|c| c.is_ascii_digit()).count();
+            if !(1..=9).contains(&digits) {
+                return Err("Expected one to nine fractional digits");
+            }
+            nanosecond = (take_digits(s, digits)? * 10u64.pow(9 - digits as u32)) as u32;
+        }
+
+        Ok((hour, minute, second, nanosecond))
+    }
+}
+
+impl Weekday {
+    /// The English three letter abbreviation, e.g. `Mon`.
+    pub const fn abbreviation(self) -> &'static str {
+        match self {
+            Weekday::Monday => "Mon",
+            Weekday::Tuesday => "Tue",
+            Weekday::Wednesday => "Wed",
+            Weekday::Thursday => "Thu",
+            Weekday::Friday => "Fri",
+            Weekday::Saturday => "Sat",
+            Weekday::Sunday => "Sun",
+        }
+    }
+}
+
+impl fmt::Display for DateTime {
+    /// ISO 8601 with microseconds, e.g. `2024-05-01T12:00:00.000000Z`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+            self.nanosecond / 1000
+        )
+    }
+}
+
+impl fmt::Display for Weekday {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.abbreviation())
+    }
+}
+
+impl fmt::Display for Iso8601 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        DateTime::from_unix(self.0).fmt(f)
+    }
+}
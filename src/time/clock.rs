@@ -0,0 +1,181 @@
+//! Clocks, i.e. free-running counters to tell time by.
+//!
+//! Everything in [`time`](super) runs on the architectural counter, `CNTPCT_EL0`. Other counters,
+//! like the BCM System Timer, implement [`interface::Clock`] as well, so they can be read the same
+//! way, and checked against each other with [`measure_drift()`].
+
+use super::{counter_frequency, counter_ticks, sleep_for};
+use crate::exception::asynchronous;
+use core::{fmt, time::Duration};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// The reading of a clock, bracketed by two readings of the reference.
+#[derive(Copy, Clone)]
+struct Sample {
+    reference_before: u64,
+    reference_after: u64,
+    clock: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Clock interfaces.
+pub mod interface {
+    use core::time::Duration;
+
+    /// A free-running counter.
+    pub trait Clock {
+        /// Human readable name of the clock.
+        fn name(&self) -> &'static str;
+
+        /// Frequency of the counter in Hz.
+        fn frequency(&self) -> u32;
+
+        /// Current value of the counter.
+        fn ticks(&self) -> u64;
+
+        /// Time since the counter started.
+        fn uptime(&self) -> Duration {
+            super::ticks_to_duration(self.ticks(), self.frequency())
+        }
+    }
+}
+
+/// The architectural counter, `CNTPCT_EL0`.
+pub struct ArchTimerClock;
+
+/// Result of [`measure_drift()`].
+#[derive(Copy, Clone, Debug)]
+pub struct Drift {
+    /// Time that passed on the reference.
+    pub reference_elapsed: Duration,
+    /// Time that passed on the measured clock.
+    pub clock_elapsed: Duration,
+    /// How much faster the measured clock runs, in parts per billion. Negative if it is slower.
+    pub ppb: i64,
+    /// Bound of the error of `ppb`, from the clocks' resolutions and the time taken to read them.
+    pub uncertainty_ppb: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn ticks_to_duration(ticks: u64, frequency: u32) -> Duration {
+    let frequency = u64::from(frequency);
+    let nanos = u128::from(ticks % frequency) * NANOS_PER_SEC / u128::from(frequency);
+
+    Duration::new(ticks / frequency, nanos as u32)
+}
+
+fn ticks_to_nanos(ticks: u64, frequency: u32) -> u128 {
+    u128::from(ticks) * NANOS_PER_SEC / u128::from(frequency)
+}
+
+impl Sample {
+    /// Read the clock between two readings of the reference, with IRQs masked to keep them close.
+    fn take(clock: &dyn interface::Clock, reference: &dyn interface::Clock) -> Self {
+        asynchronous::exec_with_irq_masked(|| {
+            let reference_before = reference.ticks();
+            let clock = clock.ticks();
+            let reference_after = reference.ticks();
+
+            Sample {
+                reference_before,
+                reference_after,
+                clock,
+            }
+        })
+    }
+
+    /// The reference's ticks between the two readings, within which the clock was read.
+    fn window(&self) -> u64 {
+        self.reference_after - self.reference_before
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Return the architectural counter as a clock.
+pub fn arch_timer_clock() -> &'static ArchTimerClock {
+    &ArchTimerClock
+}
+
+/// Measure how fast `clock` runs against `reference`, over at least `interval`.
+///
+/// Sleeps for the interval. The longer it is, the smaller the uncertainty.
+pub fn measure_drift(
+    clock: &dyn interface::Clock,
+    reference: &dyn interface::Clock,
+    interval: Duration,
+) -> Result<Drift, &'static str> {
+    let start = Sample::take(clock, reference);
+    sleep_for(interval);
+    let end = Sample::take(clock, reference);
+
+    // Each clock reading is placed in the middle of its window, so it is off by half the window at
+    // most. Both clocks can also be off by a tick, since the readings are truncated.
+    let reference_ticks = (end.reference_before + end.reference_after) / 2
+        - (start.reference_before + start.reference_after) / 2;
+    let clock_ticks = end.clock.wrapping_sub(start.clock);
+    if reference_ticks == 0 {
+        return Err("Interval too short");
+    }
+
+    let reference_nanos = ticks_to_nanos(reference_ticks, reference.frequency());
+    let clock_nanos = ticks_to_nanos(clock_ticks, clock.frequency());
+    let error_nanos = ticks_to_nanos(
+        (start.window() + end.window()) / 2 + 1,
+        reference.frequency(),
+    ) + ticks_to_nanos(1, clock.frequency());
+
+    let ppb = (clock_nanos as i128 - reference_nanos as i128) * NANOS_PER_SEC as i128
+        / reference_nanos as i128;
+    let uncertainty_ppb = error_nanos * NANOS_PER_SEC / reference_nanos;
+
+    Ok(Drift {
+        reference_elapsed: ticks_to_duration(reference_ticks, reference.frequency()),
+        clock_elapsed: ticks_to_duration(clock_ticks, clock.frequency()),
+        ppb: ppb as i64,
+        uncertainty_ppb: uncertainty_ppb as u64,
+    })
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:+} ppb +/- {} ppb over {} us",
+            self.ppb,
+            self.uncertainty_ppb,
+            self.reference_elapsed.as_micros()
+        )
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl interface::Clock for ArchTimerClock {
+    fn name(&self) -> &'static str {
+        "ARM generic timer"
+    }
+
+    fn frequency(&self) -> u32 {
+        counter_frequency()
+    }
+
+    fn ticks(&self) -> u64 {
+        counter_ticks()
+    }
+}
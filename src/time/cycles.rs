@@ -0,0 +1,233 @@
+//! Cycle counting on the Performance Monitors Unit (PMU).
+//!
+//! `PMCCNTR_EL0` counts CPU cycles, which resolves far below a tick of the architectural counter:
+//! a cycle is well under a nanosecond, a counter tick 18 (Pi 4) or 52 (Pi 3) nanoseconds.
+//! Additionally, one of the PMU's event counters can count an [`Event`] like retired instructions.
+//!
+//! Cycles are converted to time with the nominal core clock of the board, or with the one measured
+//! by [`calibrate()`]. The core clock is only stable with frequency scaling off, e.g. with
+//! `force_turbo=1` in `config.txt`, and the cycle counter stops while the core idles in
+//! `WFI`/`WFE`.
+//!
+//! [`bench()`] times a closure in cycles, e.g. to find out what an MMIO access costs.
+
+use super::{spin_until, Instant};
+use crate::{
+    bsp::board::{Board, CurrentBoard},
+    exception::asynchronous,
+};
+use core::{
+    arch::asm,
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+    time::Duration,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// `PMCR_EL0` bits.
+const PMCR_E: u64 = 1 << 0;
+const PMCR_P: u64 = 1 << 1;
+const PMCR_C: u64 = 1 << 2;
+const PMCR_LC: u64 = 1 << 6;
+const PMCR_N_SHIFT: u64 = 11;
+const PMCR_N_MASK: u64 = 0b1_1111;
+
+/// `PMCNTENSET_EL0` bit of the cycle counter.
+const PMCNTEN_C: u64 = 1 << 31;
+
+/// Event counter used by [`count_event()`].
+const EVENT_COUNTER: u64 = 0;
+
+/// Read a system register the `aarch64-cpu` crate has no definition for.
+macro_rules! read_sysreg {
+    ($name:literal) => {{
+        let value: u64;
+        unsafe { asm!(concat!("mrs {}, ", $name), out(reg) value, options(nomem, nostack)) };
+        value
+    }};
+}
+
+/// Write a system register the `aarch64-cpu` crate has no definition for.
+macro_rules! write_sysreg {
+    ($name:literal, $value:expr) => {{
+        let value: u64 = $value;
+        unsafe { asm!(concat!("msr ", $name, ", {}"), in(reg) value, options(nomem, nostack)) };
+    }};
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Architectural PMU events, as implemented by the Cortex-A53 and Cortex-A72.
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
+pub enum Event {
+    L1DCacheRefill = 0x03,
+    L1DCacheAccess = 0x04,
+    InstructionsRetired = 0x08,
+    ExceptionsTaken = 0x09,
+    BranchMispredicted = 0x10,
+    MemoryAccess = 0x13,
+    L2DCacheAccess = 0x16,
+    BusAccess = 0x19,
+}
+
+/// Cycles per run of a benchmarked closure, with the cost of measuring subtracted.
+#[derive(Copy, Clone, Debug)]
+pub struct BenchResult {
+    pub runs: u32,
+    pub min: u64,
+    pub mean: u64,
+    pub max: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+/// Core clock in Hz, nominal until calibrated.
+static FREQUENCY_HZ: AtomicU32 = AtomicU32::new(CurrentBoard::CPU_CLOCK_HZ);
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Number of event counters the PMU implements.
+fn num_event_counters() -> u64 {
+    read_sysreg!("pmcr_el0") >> PMCR_N_SHIFT & PMCR_N_MASK
+}
+
+/// Cycles `f` takes, with IRQs masked.
+fn measure(f: &mut impl FnMut()) -> u64 {
+    asynchronous::exec_with_irq_masked(|| {
+        let start = cycles();
+        f();
+        cycles() - start
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Enable and reset the cycle counter and the event counters.
+///
+/// Counts at EL1 and EL0. Boot code has given EL1 access to the PMU.
+pub fn init() {
+    write_sysreg!("pmccfiltr_el0", 0);
+    write_sysreg!("pmcntenset_el0", PMCNTEN_C);
+    write_sysreg!("pmcr_el0", PMCR_E | PMCR_P | PMCR_C | PMCR_LC);
+}
+
+/// Current value of the cycle counter.
+#[inline(always)]
+pub fn cycles() -> u64 {
+    // Prevent that the counter is read ahead of time due to out-of-order execution.
+    unsafe { asm!("isb", options(nomem, nostack)) };
+
+    read_sysreg!("pmccntr_el0")
+}
+
+/// Core clock in Hz used for conversions.
+pub fn frequency() -> u32 {
+    FREQUENCY_HZ.load(Ordering::Relaxed)
+}
+
+/// Convert cycles to time.
+pub fn cycles_to_duration(cycles: u64) -> Duration {
+    let nanos = u128::from(cycles) * 1_000_000_000 / u128::from(frequency());
+
+    Duration::from_nanos(nanos as u64)
+}
+
+/// Measure the core clock against the architectural counter, spinning for `interval`, and use
+/// the result for conversions from then on. Returns it in Hz.
+pub fn calibrate(interval: Duration) -> Result<u32, &'static str> {
+    let start = Instant::now();
+    let end = start.checked_add(interval).ok_or("Interval too long")?;
+
+    // Start counting on a counter tick, so both clocks cover the same span.
+    spin_until(Instant::from_ticks(start.ticks() + 1));
+    let start_cycles = cycles();
+    spin_until(end);
+    let end_cycles = cycles();
+
+    let elapsed = end.duration_since(Instant::from_ticks(start.ticks() + 1));
+    if elapsed.is_zero() {
+        return Err("Interval too short");
+    }
+
+    let hz = u128::from(end_cycles - start_cycles) * 1_000_000_000 / elapsed.as_nanos();
+    let hz = u32::try_from(hz).map_err(|_| "Implausible core clock")?;
+    FREQUENCY_HZ.store(hz, Ordering::Relaxed);
+
+    Ok(hz)
+}
+
+/// Count how often `event` occurs while running `f`, with IRQs masked.
+pub fn count_event(event: Event, f: impl FnOnce()) -> Result<u64, &'static str> {
+    if num_event_counters() <= EVENT_COUNTER {
+        return Err("No PMU event counter");
+    }
+
+    asynchronous::exec_with_irq_masked(|| {
+        write_sysreg!("pmselr_el0", EVENT_COUNTER);
+        write_sysreg!("pmxevtyper_el0", event as u64);
+        write_sysreg!("pmxevcntr_el0", 0);
+        write_sysreg!("pmcntenset_el0", 1 << EVENT_COUNTER);
+        unsafe { asm!("isb", options(nomem, nostack)) };
+
+        f();
+
+        unsafe { asm!("isb", options(nomem, nostack)) };
+        write_sysreg!("pmcntenclr_el0", 1 << EVENT_COUNTER);
+
+        Ok(read_sysreg!("pmxevcntr_el0"))
+    })
+}
+
+/// Run `f` `runs` times, each with IRQs masked, and return the cycles per run.
+///
+/// The cost of reading the counter is measured first and subtracted. Memory accesses may still be
+/// in flight when the run's end is taken, e.g. posted MMIO writes.
+pub fn bench(runs: u32, mut f: impl FnMut()) -> BenchResult {
+    let overhead = (0..runs.max(1))
+        .map(|_| measure(&mut || {}))
+        .min()
+        .unwrap_or(0);
+
+    let mut min = u64::MAX;
+    let mut max = 0;
+    let mut total: u64 = 0;
+    for _ in 0..runs {
+        let cycles = measure(&mut f).saturating_sub(overhead);
+
+        min = min.min(cycles);
+        max = max.max(cycles);
+        total = total.saturating_add(cycles);
+    }
+
+    BenchResult {
+        runs,
+        min: if runs == 0 { 0 } else { min },
+        mean: total / u64::from(runs.max(1)),
+        max,
+    }
+}
+
+impl fmt::Display for BenchResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} / {} / {} cycles (min / mean / max), mean {} ns",
+            self.min,
+            self.mean,
+            self.max,
+            cycles_to_duration(self.mean).as_nanos()
+        )
+    }
+}
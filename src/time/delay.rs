@@ -0,0 +1,61 @@
+//! Blocking delays for `embedded-hal` drivers.
+
+use super::Deadline;
+use core::time::Duration;
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Delay provider spinning on the physical counter.
+///
+/// Waits at least the requested time, rounded up to the next counter tick. Interrupts taken in
+/// between only make it longer.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Delay;
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn wait(duration: Duration) {
+    let deadline = Deadline::after(duration);
+
+    while !deadline.has_passed() {}
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Delay {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        Delay
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+macro_rules! impl_delay {
+    ($($t:ty),*) => {
+        $(
+            impl DelayUs<$t> for Delay {
+                fn delay_us(&mut self, us: $t) {
+                    wait(Duration::from_micros(u64::from(us)));
+                }
+            }
+
+            impl DelayMs<$t> for Delay {
+                fn delay_ms(&mut self, ms: $t) {
+                    wait(Duration::from_millis(u64::from(ms)));
+                }
+            }
+        )*
+    };
+}
+
+impl_delay!(u8, u16, u32, u64);
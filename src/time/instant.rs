@@ -0,0 +1,147 @@
+//! Points in time on the physical counter.
+//!
+//! An [`Instant`] is a raw `CNTPCT_EL0` value, so taking and comparing them is as cheap as reading
+//! the counter. Conversion to [`Duration`] only happens when a difference is asked for.
+//!
+//! The counter is 64 bits wide and starts at zero on power-on, so it doesn't wrap in the lifetime
+//! of the board. Arithmetic that would leave its range is reported, not wrapped.
+
+use super::{duration_to_ticks, read_cntpct, GenericTimerCounterValue, Rounding};
+use core::{
+    ops::{Add, Sub},
+    time::Duration,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// A point in time, as a value of the physical counter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant(u64);
+
+/// A point in time to wait for, or to give up at.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Deadline(Instant);
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Instant {
+    /// The latest representable point in time, i.e. never.
+    pub const MAX: Self = Instant(u64::MAX);
+
+    /// The current value of the counter.
+    pub fn now() -> Self {
+        Instant(read_cntpct().0)
+    }
+
+    /// Create an instance from a raw counter value.
+    pub const fn from_ticks(ticks: u64) -> Self {
+        Instant(ticks)
+    }
+
+    /// The raw counter value.
+    pub const fn ticks(&self) -> u64 {
+        self.0
+    }
+
+    /// Time since power-on.
+    pub fn since_boot(&self) -> Duration {
+        GenericTimerCounterValue(self.0).into()
+    }
+
+    /// Time passed since this instant. Zero if it is in the future.
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+
+    /// Time from `earlier` to this instant, or `None` if `earlier` is later.
+    pub fn checked_duration_since(&self, earlier: Instant) -> Option<Duration> {
+        self.0
+            .checked_sub(earlier.0)
+            .map(|ticks| GenericTimerCounterValue(ticks).into())
+    }
+
+    /// Time from `earlier` to this instant. Zero if `earlier` is later.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        self.checked_duration_since(earlier).unwrap_or_default()
+    }
+
+    /// This instant plus a duration, rounded up to the next tick, or `None` if the result doesn't
+    /// fit the counter.
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        let ticks = duration_to_ticks(duration, Rounding::Ceil).ok()?;
+
+        self.0.checked_add(ticks).map(Instant)
+    }
+
+    /// This instant minus a duration, rounded down to the previous tick, or `None` if the result
+    /// would be before power-on.
+    pub fn checked_sub(&self, duration: Duration) -> Option<Instant> {
+        let ticks = duration_to_ticks(duration, Rounding::Ceil).ok()?;
+
+        self.0.checked_sub(ticks).map(Instant)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    /// # Panics
+    ///
+    /// If the result doesn't fit the counter. See [`Instant::checked_add`].
+    fn add(self, duration: Duration) -> Instant {
+        self.checked_add(duration)
+            .expect("overflow when adding duration to instant")
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    /// # Panics
+    ///
+    /// If the result would be before power-on. See [`Instant::checked_sub`].
+    fn sub(self, duration: Duration) -> Instant {
+        self.checked_sub(duration)
+            .expect("overflow when subtracting duration from instant")
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    /// Saturates at zero, like [`Instant::duration_since`].
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+impl Deadline {
+    /// A deadline the given time from now. Never passes if that doesn't fit the counter.
+    pub fn after(timeout: Duration) -> Self {
+        Deadline(Instant::now().checked_add(timeout).unwrap_or(Instant::MAX))
+    }
+
+    /// A deadline at the given instant.
+    pub const fn at(instant: Instant) -> Self {
+        Deadline(instant)
+    }
+
+    /// The instant of the deadline.
+    pub const fn instant(&self) -> Instant {
+        self.0
+    }
+
+    /// Whether the deadline has passed.
+    pub fn has_passed(&self) -> bool {
+        Instant::now() >= self.0
+    }
+
+    /// Time left until the deadline. Zero once it has passed.
+    pub fn remaining(&self) -> Duration {
+        self.0.duration_since(Instant::now())
+    }
+}
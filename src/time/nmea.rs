@@ -0,0 +1,132 @@
+//! Time from the NMEA 0183 sentences of a GPS receiver.
+//!
+//! Understands `RMC` (recommended minimum data) and `ZDA` (time and date) from any talker, e.g.
+//! `$GPRMC` or `$GNZDA`. The time in a sentence is the one of the last fix, typically the start of
+//! the second in which the sentence is sent. Receiving it takes a while at the usual 9600 baud, so
+//! a wall clock set from it lags by up to a few hundred milliseconds. A PPS signal marks the second
+//! exactly.
+
+use super::DateTime;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Maximum number of fields of a sentence, including the address field.
+const MAX_FIELDS: usize = 24;
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Split `$<body>*<checksum>` and verify the checksum, the XOR of all bytes of the body.
+fn checked_body(sentence: &str) -> Result<&str, &'static str> {
+    let sentence = sentence.trim_end_matches(['\r', '\n']);
+    let sentence = sentence
+        .strip_prefix('$')
+        .ok_or("Sentence must start with '$'")?;
+    let (body, checksum) = sentence.split_once('*').ok_or("Missing checksum")?;
+
+    let checksum = u8::from_str_radix(checksum, 16).map_err(|_| "Invalid checksum")?;
+    if body.bytes().fold(0, |sum, byte| sum ^ byte) != checksum {
+        return Err("Checksum mismatch");
+    }
+
+    Ok(body)
+}
+
+/// Parse a number of exactly `len` decimal digits.
+fn parse_digits(field: &str, len: usize) -> Result<u64, &'static str> {
+    if field.len() != len || !field.bytes().all(|c| c.is_ascii_digit()) {
+        return Err("Invalid date");
+    }
+
+    field.parse().map_err(|_| "Invalid date")
+}
+
+/// Combine a date with a `hhmmss[.sss]` time field.
+fn date_time(year: u64, month: u64, day: u64, time: &str) -> Result<DateTime, &'static str> {
+    let mut time = time.as_bytes();
+    let (hour, minute, second, nanosecond) = DateTime::parse_time_of_day(&mut time, false)?;
+    if !time.is_empty() {
+        return Err("Invalid time of day");
+    }
+
+    let date_time = DateTime {
+        year,
+        month: month as u8,
+        day: day as u8,
+        hour,
+        minute,
+        second,
+        nanosecond,
+    };
+    date_time.to_unix()?;
+
+    Ok(date_time)
+}
+
+/// `$xxRMC,hhmmss.ss,A,llll.ll,a,yyyyy.yy,a,x.x,x.x,ddmmyy,...`
+fn parse_rmc(fields: &[&str]) -> Result<DateTime, &'static str> {
+    let [_, time, status, _, _, _, _, _, _, date, ..] = fields else {
+        return Err("Too few fields");
+    };
+    if *status != "A" {
+        return Err("Receiver has no valid fix");
+    }
+
+    let date = parse_digits(date, 6)?;
+    // Two-digit years. GPS time starts in 1980, so 80 to 99 are the last century.
+    let year = match date % 100 {
+        year @ 80.. => 1900 + year,
+        year => 2000 + year,
+    };
+
+    date_time(year, date / 100 % 100, date / 10_000, time)
+}
+
+/// `$xxZDA,hhmmss.ss,dd,mm,yyyy,xx,xx`
+fn parse_zda(fields: &[&str]) -> Result<DateTime, &'static str> {
+    let [_, time, day, month, year, ..] = fields else {
+        return Err("Too few fields");
+    };
+
+    date_time(
+        parse_digits(year, 4)?,
+        parse_digits(month, 2)?,
+        parse_digits(day, 2)?,
+        time,
+    )
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Parse the UTC date and time of an `RMC` or `ZDA` sentence.
+pub fn parse(sentence: &str) -> Result<DateTime, &'static str> {
+    let body = checked_body(sentence)?;
+
+    let mut fields = [""; MAX_FIELDS];
+    let mut num_fields = 0;
+    for field in body.split(',') {
+        *fields.get_mut(num_fields).ok_or("Too many fields")? = field;
+        num_fields += 1;
+    }
+    let fields = &fields[..num_fields];
+
+    // The address field is a two letter talker ID followed by the sentence type.
+    match fields[0].get(2..) {
+        Some("RMC") => parse_rmc(fields),
+        Some("ZDA") => parse_zda(fields),
+        _ => Err("Unsupported sentence"),
+    }
+}
+
+/// Set the wall clock from an `RMC` or `ZDA` sentence. Returns the time it was set to.
+pub fn set_wall_clock(sentence: &str) -> Result<DateTime, &'static str> {
+    let date_time = parse(sentence)?;
+    super::set_wall_clock(date_time.to_unix()?);
+
+    Ok(date_time)
+}
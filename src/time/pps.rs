@@ -0,0 +1,315 @@
+//! Pulse-per-second (PPS) input, e.g. from a GPS receiver, to discipline the timebase.
+//!
+//! The rising edges on a GPIO are timestamped on the architectural counter by the event detection
+//! interrupt. True time between consecutive pulses is one second, so the counter ticks in between
+//! are the counter's true frequency, where everything else in [`time`](super) assumes the nominal
+//! one from `CNTFRQ_EL0`. [`status()`] reports how far apart the two are, and the
+//! [`DisciplinedClock`] converts counter values with the measured frequency.
+//!
+//! Each timestamp carries the jitter of the interrupt latency, a few microseconds. The frequency is
+//! therefore measured across the last minute of pulses, once enough have come in a row.
+
+use super::{clock, counter_frequency, counter_ticks, Instant};
+use crate::{
+    bsp::{self, Edge},
+    synchronization::{interface::Mutex, IRQSafeNullLock},
+};
+use core::{fmt, time::Duration};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Timestamps kept, so the frequency is measured across up to one less seconds.
+const WINDOW_PULSES: usize = 61;
+
+/// Seconds of pulses in a row it takes to lock.
+const LOCK_SECONDS: u64 = 4;
+
+/// How far a pulse may be off from a nominal second after the previous one. Anything further is
+/// taken as a missed or spurious pulse, which restarts the measurement.
+const MAX_DEVIATION_PPM: u64 = 1000;
+
+/// Time without a pulse after which a lock is considered lost.
+const PULSE_TIMEOUT: Duration = Duration::from_millis(1500);
+
+struct PpsInner {
+    pin: Option<usize>,
+    /// Ring of the timestamps of the last pulses, in counter ticks.
+    edges: [u64; WINDOW_PULSES],
+    /// Index of the latest timestamp in `edges`.
+    latest: usize,
+    /// Pulses since the last missed or spurious one.
+    run_pulses: u64,
+    /// Timestamp of the first pulse of the run.
+    run_start: u64,
+    /// Pulses that were off and restarted the run.
+    rejected: u64,
+    /// Latest measurement of a locked run. Kept when the lock is lost.
+    measurement: Option<Measurement>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Lock state of the PPS input.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Lock {
+    /// Not started.
+    Off,
+    /// Waiting for enough pulses in a row.
+    Acquiring,
+    /// Measuring from the pulses.
+    Locked,
+    /// Pulses stopped or became irregular. The last measurement is still used.
+    Holdover,
+}
+
+/// The counter's frequency as measured against the PPS.
+#[derive(Copy, Clone, Debug)]
+pub struct Measurement {
+    /// Counter ticks between the first and the last pulse of the measurement.
+    pub ticks: u64,
+    /// Seconds between the first and the last pulse of the measurement.
+    pub seconds: u64,
+}
+
+/// Result of [`status()`].
+#[derive(Copy, Clone, Debug)]
+pub struct Status {
+    pub lock: Lock,
+    /// GPIO the PPS is read from.
+    pub pin: Option<usize>,
+    /// Pulses since the last missed or spurious one.
+    pub pulses: u64,
+    /// Pulses that were off from a second after their predecessor.
+    pub rejected: u64,
+    pub measurement: Option<Measurement>,
+    /// How far uptime at nominal frequency ran ahead of the pulses since the run started, in
+    /// nanoseconds. Negative if it fell behind.
+    pub phase_error_ns: i64,
+}
+
+/// The architectural counter, converted to time with the frequency measured against the PPS.
+pub struct DisciplinedClock;
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static PPS: IRQSafeNullLock<PpsInner> = IRQSafeNullLock::new(PpsInner::new());
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl PpsInner {
+    const fn new() -> Self {
+        Self {
+            pin: None,
+            edges: [0; WINDOW_PULSES],
+            latest: 0,
+            run_pulses: 0,
+            run_start: 0,
+            rejected: 0,
+            measurement: None,
+        }
+    }
+
+    fn add_pulse(&mut self, ticks: u64) {
+        if self.run_pulses != 0 {
+            let interval = ticks.saturating_sub(self.edges[self.latest]);
+            let nominal = u64::from(counter_frequency());
+
+            if interval.abs_diff(nominal) > nominal * MAX_DEVIATION_PPM / 1_000_000 {
+                self.rejected += 1;
+                self.run_pulses = 0;
+            }
+        }
+
+        if self.run_pulses == 0 {
+            self.run_start = ticks;
+        }
+        self.latest = (self.latest + 1) % WINDOW_PULSES;
+        self.edges[self.latest] = ticks;
+        self.run_pulses += 1;
+
+        if self.run_seconds() >= LOCK_SECONDS {
+            let seconds = self.run_seconds().min(WINDOW_PULSES as u64 - 1);
+            let first = (self.latest + WINDOW_PULSES - seconds as usize) % WINDOW_PULSES;
+
+            self.measurement = Some(Measurement {
+                ticks: ticks - self.edges[first],
+                seconds,
+            });
+        }
+    }
+
+    fn run_seconds(&self) -> u64 {
+        self.run_pulses.saturating_sub(1)
+    }
+
+    fn lock_state(&self) -> Lock {
+        let timed_out = || {
+            Instant::from_ticks(self.edges[self.latest])
+                .checked_add(PULSE_TIMEOUT)
+                .map_or(true, |timeout| timeout < Instant::now())
+        };
+
+        match (self.pin, self.measurement) {
+            (None, _) => Lock::Off,
+            (Some(_), None) => Lock::Acquiring,
+            (Some(_), Some(_)) if self.run_seconds() < LOCK_SECONDS || timed_out() => {
+                Lock::Holdover
+            }
+            (Some(_), Some(_)) => Lock::Locked,
+        }
+    }
+
+    fn phase_error_ns(&self) -> i64 {
+        let nominal = super::ticks_to_duration(self.edges[self.latest] - self.run_start);
+        let pulses = u128::from(self.run_seconds()) * NANOS_PER_SEC;
+
+        (nominal.as_nanos() as i128 - pulses as i128) as i64
+    }
+}
+
+/// Called by the GPIO interrupt handler on each rising edge.
+fn pulse(_pin: usize, timestamp: Instant) {
+    PPS.lock(|inner| inner.add_pulse(timestamp.ticks()));
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Start reading the PPS from a GPIO, which must be configured as input.
+///
+/// Restarts the measurement, also if already reading from another pin.
+pub fn start(pin: usize) -> Result<(), &'static str> {
+    stop()?;
+
+    bsp::GPIO.set_edge_callback(pin, Edge::Rising, pulse)?;
+    PPS.lock(|inner| inner.pin = Some(pin));
+
+    Ok(())
+}
+
+/// Stop reading the PPS. The disciplined clock falls back to the nominal frequency.
+pub fn stop() -> Result<(), &'static str> {
+    if let Some(pin) = PPS.lock(|inner| inner.pin) {
+        bsp::GPIO.clear_edge_callback(pin)?;
+    }
+    PPS.lock(|inner| *inner = PpsInner::new());
+
+    Ok(())
+}
+
+/// Current state of the PPS input.
+pub fn status() -> Status {
+    PPS.lock(|inner| Status {
+        lock: inner.lock_state(),
+        pin: inner.pin,
+        pulses: inner.run_pulses,
+        rejected: inner.rejected,
+        measurement: inner.measurement,
+        phase_error_ns: inner.phase_error_ns(),
+    })
+}
+
+/// Return the disciplined clock.
+pub fn disciplined_clock() -> &'static DisciplinedClock {
+    &DisciplinedClock
+}
+
+impl Measurement {
+    /// Measured frequency in millihertz.
+    pub fn millihertz(&self) -> u64 {
+        (u128::from(self.ticks) * 1000 / u128::from(self.seconds)) as u64
+    }
+
+    /// How much faster the counter runs than nominal, in parts per billion. Negative if it is
+    /// slower.
+    pub fn ppb(&self) -> i64 {
+        let nominal = u128::from(self.seconds) * u128::from(counter_frequency());
+        let ratio = u128::from(self.ticks) * NANOS_PER_SEC / nominal;
+
+        (ratio as i128 - NANOS_PER_SEC as i128) as i64
+    }
+
+    /// Convert counter ticks to time at the measured frequency.
+    pub fn ticks_to_duration(&self, ticks: u64) -> Duration {
+        let nanos =
+            u128::from(ticks) * NANOS_PER_SEC * u128::from(self.seconds) / u128::from(self.ticks);
+
+        Duration::new(
+            (nanos / NANOS_PER_SEC) as u64,
+            (nanos % NANOS_PER_SEC) as u32,
+        )
+    }
+}
+
+impl fmt::Display for Lock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Lock::Off => "off",
+            Lock::Acquiring => "acquiring",
+            Lock::Locked => "locked",
+            Lock::Holdover => "holdover",
+        })
+    }
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millihertz = self.millihertz();
+        let ppb = self.ppb();
+        let sign = if ppb < 0 { '-' } else { '+' };
+
+        write!(
+            f,
+            "{}.{:03} Hz, {}{}.{:03} ppm over {} s",
+            millihertz / 1000,
+            millihertz % 1000,
+            sign,
+            ppb.unsigned_abs() / 1000,
+            ppb.unsigned_abs() % 1000,
+            self.seconds
+        )
+    }
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl clock::interface::Clock for DisciplinedClock {
+    fn name(&self) -> &'static str {
+        "PPS disciplined"
+    }
+
+    /// The measured frequency, rounded to Hz. Nominal until measured.
+    fn frequency(&self) -> u32 {
+        match PPS.lock(|inner| inner.measurement) {
+            Some(measurement) => ((measurement.millihertz() + 500) / 1000) as u32,
+            None => counter_frequency(),
+        }
+    }
+
+    fn ticks(&self) -> u64 {
+        counter_ticks()
+    }
+
+    /// Uptime at the measured frequency, without the rounding of [`Self::frequency()`].
+    fn uptime(&self) -> Duration {
+        let ticks = self.ticks();
+
+        match PPS.lock(|inner| inner.measurement) {
+            Some(measurement) => measurement.ticks_to_duration(ticks),
+            None => super::ticks_to_duration(ticks),
+        }
+    }
+}
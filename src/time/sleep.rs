@@ -0,0 +1,169 @@
+//! Waiting without spinning at full power.
+//!
+//! [`sleep_for()`] waits like [`spin_for()`](super::spin_for), but lets the core idle between
+//! checks of the counter. The [`SleepPrecision`] setting trades how late a sleep may end for how
+//! often the core wakes up:
+//!
+//! - With IRQs unmasked, a one-shot software timer, with the precision as slack, wakes the core
+//!   from `WFI`. Unless other interrupts arrive, the core wakes up once.
+//! - With IRQs masked, or all software timers taken, the event stream of the counter wakes the core
+//!   from `WFE`, at the longest period that doesn't exceed the precision.
+//!
+//! Waits no longer than the precision just spin, as does everything at [`SleepPrecision::Exact`].
+
+use super::{
+    duration_to_ticks, spin_until,
+    timer::{self, Expiry, TimerHandle},
+    Instant, Overshoot, Rounding,
+};
+use crate::{exception::asynchronous, warn};
+use aarch64_cpu::{asm, registers::*};
+use core::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tock_registers::interfaces::{ReadWriteable, Readable, Writeable};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Fine enough for anything that isn't bit-banging, coarse enough to let the core idle.
+const DEFAULT_PRECISION: Duration = Duration::from_micros(100);
+
+/// Highest trigger bit of the event stream, which fires every 2^(EVNTI + 1) ticks.
+const MAX_EVNTI: u32 = 15;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// How late a sleep may end, traded against power.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SleepPrecision {
+    /// Spin on the counter. Ends on the tick, at full power.
+    Exact,
+    /// Idle, and end up to the given time late. Longer means fewer wake-ups.
+    Within(Duration),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+/// The precision in nanoseconds. Zero for exact.
+static PRECISION_NANOS: AtomicU64 = AtomicU64::new(DEFAULT_PRECISION.as_nanos() as u64);
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Timer callback that does nothing. Its interrupt ends the `WFI`.
+fn wake(_: TimerHandle) {}
+
+/// Idle in `WFI` until `deadline`, woken by a software timer at most `slack` after it.
+fn sleep_on_timer(deadline: Instant, slack: Duration) -> Result<(), &'static str> {
+    let handle = timer::timer_service().schedule(
+        Expiry::After(deadline.duration_since(Instant::now())),
+        slack,
+        wake,
+    )?;
+
+    // IRQs stay masked between the check and `wfi`, so the timer interrupt can't be missed. A
+    // pending interrupt still ends `wfi`, and is taken when IRQs are unmasked again.
+    while asynchronous::exec_with_irq_masked(|| {
+        let waiting = Instant::now() < deadline;
+        if waiting {
+            asm::wfi();
+        }
+        waiting
+    }) {}
+
+    // Another interrupt may have ended the sleep before the timer fired.
+    timer::timer_service().cancel(handle);
+
+    Ok(())
+}
+
+/// Idle in `WFE` until `deadline`, woken by the event stream at least every `max_period` ticks,
+/// which must be two or more.
+///
+/// The event stream follows the virtual counter, which boot code aligned with the physical one.
+fn sleep_on_event_stream(deadline: Instant, max_period: u64) {
+    // The longest period 2^(EVNTI + 1) not exceeding max_period.
+    let evnti = (max_period.ilog2() - 1).min(MAX_EVNTI);
+
+    let saved = CNTKCTL_EL1.get();
+    CNTKCTL_EL1.modify(
+        CNTKCTL_EL1::EVNTI.val(evnti.into())
+            + CNTKCTL_EL1::EVNTDIR::ZeroToOne
+            + CNTKCTL_EL1::EVNTEN::Enable,
+    );
+
+    // Events stay coming, so there is no wake-up to miss between the check and `wfe`.
+    while Instant::now() < deadline {
+        asm::wfe();
+    }
+
+    CNTKCTL_EL1.set(saved);
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// The precision of sleeps.
+pub fn sleep_precision() -> SleepPrecision {
+    match PRECISION_NANOS.load(Ordering::Relaxed) {
+        0 => SleepPrecision::Exact,
+        nanos => SleepPrecision::Within(Duration::from_nanos(nanos)),
+    }
+}
+
+/// Set the precision of sleeps.
+pub fn set_sleep_precision(precision: SleepPrecision) {
+    let nanos = match precision {
+        SleepPrecision::Exact => 0,
+        SleepPrecision::Within(max_lateness) => max_lateness.as_nanos() as u64,
+    };
+
+    PRECISION_NANOS.store(nanos, Ordering::Relaxed);
+}
+
+/// Sleep until the counter reaches the given instant. Returns right away if it already has.
+///
+/// Like [`spin_until()`], returns how late the sleep ended.
+pub fn sleep_until(deadline: Instant) -> Overshoot {
+    let precision = match sleep_precision() {
+        SleepPrecision::Exact => return spin_until(deadline),
+        SleepPrecision::Within(precision) => precision,
+    };
+
+    // Not worth idling for.
+    if deadline.duration_since(Instant::now()) <= precision {
+        return spin_until(deadline);
+    }
+
+    if asynchronous::is_local_irq_masked() || sleep_on_timer(deadline, precision).is_err() {
+        // Precisions below two ticks have no event stream to match, spin instead.
+        if let Ok(max_period @ 2..) = duration_to_ticks(precision, Rounding::Floor) {
+            sleep_on_event_stream(deadline, max_period);
+        }
+    }
+
+    // Returns right away, unless spinning was all that was left.
+    spin_until(deadline)
+}
+
+/// Sleep for a given duration, rounded up to the next counter tick.
+///
+/// Durations that don't fit the counter are skipped with a warning, like in
+/// [`spin_for()`](super::spin_for).
+pub fn sleep_for(duration: Duration) {
+    match Instant::now().checked_add(duration) {
+        None => warn!("sleep_for: Duration too big. Skipping"),
+        Some(deadline) => {
+            sleep_until(deadline);
+        }
+    }
+}
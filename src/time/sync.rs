@@ -0,0 +1,114 @@
+//! Clock synchronization with a host over the console.
+//!
+//! NTP-style exchange, driven by the `tools/clocksync` host tool. The host sends a request and
+//! notes its send time `t1`. The target stamps the request's reception (`t2`) and its reply's
+//! transmission (`t3`) with the raw counter. The host notes the reply's arrival `t4`, and from the
+//! four stamps computes the offset between the clocks and the round-trip delay. The offset of the
+//! exchange with the smallest delay is then sent back and applied as the wall clock.
+//!
+//! The protocol is line based, so it works over any console and the shell's text output can't be
+//! mistaken for a reply. Lines end with `\r\n` from the target, with `\r` or `\n` from the host.
+//!
+//! | Direction | Line                     | Meaning                                          |
+//! |-----------|--------------------------|--------------------------------------------------|
+//! | target    | `SYNC <hz>`              | Ready, with the counter frequency                |
+//! | host      | `REQ <n>`                | Request number `n`                               |
+//! | target    | `RSP <n> <t2> <t3>`      | Reply, with the counter at reception and reply   |
+//! | host      | `SET <ns>`               | Wall-clock time at counter zero, in Unix nanos   |
+//! | target    | `OK`                     | Applied                                          |
+//! | host      | `END`                    | Back to the shell                                |
+//! | target    | `ERR`                    | Malformed line                                   |
+//!
+//! `t2` is taken when the line end arrives, `t3` right before the reply is written. Both sides pay
+//! one line of UART transmission time, which roughly cancels out as long as requests and replies
+//! are of similar length.
+
+use crate::{console, print, time};
+use core::time::Duration;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Longest accepted request line.
+const LINE_LEN: usize = 64;
+
+struct LineBuffer {
+    buf: [u8; LINE_LEN],
+    len: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+impl LineBuffer {
+    const fn new() -> Self {
+        Self {
+            buf: [0; LINE_LEN],
+            len: 0,
+        }
+    }
+
+    /// Read a line without echo. Returns it together with the counter value at its end.
+    fn read_line(&mut self, console: &dyn console::interface::All) -> (&str, u64) {
+        self.len = 0;
+
+        let ticks = loop {
+            let c = console.read_char();
+            let ticks = time::counter_ticks();
+
+            match c {
+                // The console turns `\r` into `\n`. Empty lines are the other half of `\r\n`.
+                '\n' if self.len > 0 => break ticks,
+                '\n' => (),
+                c if c.is_ascii() && self.len < LINE_LEN => {
+                    self.buf[self.len] = c as u8;
+                    self.len += 1;
+                }
+                // Too long or garbage. Keep reading, the line will be rejected as a whole.
+                _ => self.len = LINE_LEN,
+            }
+        };
+
+        (self.as_str(), ticks)
+    }
+
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Serve synchronization requests on the system console until the host ends the session.
+pub fn serve() {
+    let console = console::console();
+    let mut line = LineBuffer::new();
+
+    console.clear_rx();
+    print!("SYNC {}\r\n", time::counter_frequency());
+
+    loop {
+        let (request, t2) = line.read_line(console);
+        let mut words = request.split_ascii_whitespace();
+
+        match (words.next(), words.next(), words.next()) {
+            (Some("REQ"), Some(n), None) => {
+                let t3 = time::counter_ticks();
+                print!("RSP {} {} {}\r\n", n, t2, t3);
+            }
+            (Some("SET"), Some(nanos), None) => match nanos.parse() {
+                Ok(nanos) => {
+                    time::set_wall_clock_offset(Duration::from_nanos(nanos));
+                    print!("OK\r\n");
+                }
+                Err(_) => print!("ERR\r\n"),
+            },
+            (Some("END"), None, None) => return,
+            _ => print!("ERR\r\n"),
+        }
+    }
+}
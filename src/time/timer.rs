@@ -0,0 +1,311 @@
+//! Software timers on the EL1 physical timer.
+//!
+//! A fixed number of one-shot and periodic timers share the single hardware timer of the core:
+//! `CNTP_TVAL_EL0` is always programmed for the most urgent one, and its interrupt fires every
+//! timer that is due.
+//!
+//! Callbacks run in interrupt context with IRQs masked, so they must be short and must not wait
+//! for anything. They may schedule and cancel timers.
+//!
+//! A timer can be given slack, i.e. allowed to fire up to that much later than its deadline.
+//! Timers whose windows overlap are then coalesced into a single interrupt, which saves interrupts
+//! when many timers run at once. A periodic timer keeps its phase: slack and interrupt latency
+//! delay single expiries, but never accumulate.
+
+use super::{duration_to_ticks, Instant, Rounding, TimeError};
+use crate::{
+    bsp::board::{Board, CurrentBoard},
+    exception::asynchronous::{self, interface, IRQHandlerDescriptor},
+    synchronization::{interface::Mutex, IRQSafeNullLock},
+};
+use aarch64_cpu::registers::*;
+use core::time::Duration;
+use tock_registers::interfaces::Writeable;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Number of timers that can be scheduled at once.
+const NUM_TIMERS: usize = 16;
+
+/// `CNTP_TVAL_EL0` is a signed 32-bit down counter. Deadlines further away are reached in steps.
+const MAX_TVAL: u64 = i32::MAX as u64;
+
+#[derive(Copy, Clone)]
+struct Timer {
+    /// Counter value at which the timer is due.
+    deadline: u64,
+    /// Period in counter ticks, for periodic timers.
+    period: Option<u64>,
+    /// How many counter ticks the timer may fire late.
+    slack: u64,
+    callback: TimerCallback,
+}
+
+struct TimerServiceInner {
+    timers: [Option<Timer>; NUM_TIMERS],
+    /// Bumped on every use of a slot, so stale handles don't match a new timer.
+    generations: [u32; NUM_TIMERS],
+    /// Expiries of periodic timers that were skipped because the previous one fired too late.
+    overruns: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Identifies a scheduled timer, for cancelling it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TimerHandle {
+    slot: usize,
+    generation: u32,
+}
+
+/// Called in interrupt context when a timer fires, with the handle of the timer.
+pub type TimerCallback = fn(TimerHandle);
+
+/// When a timer fires.
+#[derive(Copy, Clone)]
+pub enum Expiry {
+    /// Once, after the given time.
+    After(Duration),
+    /// Repeatedly, with the given period. The first time after one period.
+    Every(Duration),
+}
+
+/// The timer service.
+pub struct TimerService {
+    inner: IRQSafeNullLock<TimerServiceInner>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static TIMER_SERVICE: TimerService = TimerService::new();
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Convert a duration to counter ticks, rounding up, so timers never fire early.
+fn ticks_ceil(duration: Duration) -> Result<u64, &'static str> {
+    duration_to_ticks(duration, Rounding::Ceil).map_err(TimeError::as_str)
+}
+
+/// Program the hardware timer for the given counter value, or disable it.
+fn program_comparator(deadline: Option<u64>) {
+    match deadline {
+        None => CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::CLEAR),
+        Some(deadline) => {
+            // Zero for deadlines in the past, which fires right away.
+            let delta = deadline
+                .saturating_sub(Instant::now().ticks())
+                .min(MAX_TVAL);
+
+            CNTP_TVAL_EL0.set(delta);
+            CNTP_CTL_EL0.write(CNTP_CTL_EL0::ENABLE::SET + CNTP_CTL_EL0::IMASK::CLEAR);
+        }
+    }
+}
+
+impl TimerServiceInner {
+    const fn new() -> Self {
+        Self {
+            timers: [None; NUM_TIMERS],
+            generations: [0; NUM_TIMERS],
+            overruns: 0,
+        }
+    }
+
+    fn handle(&self, slot: usize) -> TimerHandle {
+        TimerHandle {
+            slot,
+            generation: self.generations[slot],
+        }
+    }
+
+    fn is_current(&self, handle: TimerHandle) -> bool {
+        self.timers[handle.slot].is_some() && self.generations[handle.slot] == handle.generation
+    }
+
+    fn add(&mut self, timer: Timer) -> Result<TimerHandle, &'static str> {
+        let slot = self
+            .timers
+            .iter()
+            .position(|timer| timer.is_none())
+            .ok_or("No free timer")?;
+
+        self.generations[slot] = self.generations[slot].wrapping_add(1);
+        self.timers[slot] = Some(timer);
+
+        Ok(self.handle(slot))
+    }
+
+    /// The latest counter value the hardware timer may fire at without making any timer late.
+    fn next_expiry(&self) -> Option<u64> {
+        self.timers
+            .iter()
+            .flatten()
+            .map(|timer| timer.deadline.saturating_add(timer.slack))
+            .min()
+    }
+
+    /// Collect the timers due at `now`, and rearm or remove them.
+    fn take_due(&mut self, now: u64, due: &mut [Option<(TimerHandle, TimerCallback)>]) {
+        for slot in 0..NUM_TIMERS {
+            let handle = self.handle(slot);
+            let Some(timer) = &mut self.timers[slot] else {
+                continue;
+            };
+            if timer.deadline > now {
+                continue;
+            }
+
+            due[slot] = Some((handle, timer.callback));
+
+            match timer.period {
+                None => self.timers[slot] = None,
+                Some(period) => {
+                    // Stay in phase. Expiries that already passed are skipped, not made up for.
+                    let periods = (now - timer.deadline) / period + 1;
+                    timer.deadline += periods * period;
+                    self.overruns += periods as usize - 1;
+                }
+            }
+        }
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl TimerService {
+    /// Create an instance.
+    pub const fn new() -> Self {
+        Self {
+            inner: IRQSafeNullLock::new(TimerServiceInner::new()),
+        }
+    }
+
+    /// Register and enable the timer interrupt. Timers fire once IRQs are unmasked.
+    pub fn init(&'static self) -> Result<(), &'static str> {
+        let irq_manager = asynchronous::irq_manager();
+
+        irq_manager.register_handler(IRQHandlerDescriptor::new(
+            CurrentBoard::ARCH_TIMER_IRQ,
+            "EL1 physical timer",
+            self,
+        ))?;
+        irq_manager.enable(CurrentBoard::ARCH_TIMER_IRQ)
+    }
+
+    /// Schedule a timer that may fire up to `slack` late.
+    pub fn schedule(
+        &self,
+        expiry: Expiry,
+        slack: Duration,
+        callback: TimerCallback,
+    ) -> Result<TimerHandle, &'static str> {
+        let (after, period) = match expiry {
+            Expiry::After(after) => (ticks_ceil(after)?, None),
+            Expiry::Every(period) => {
+                let period = ticks_ceil(period)?;
+                if period == 0 {
+                    return Err("Period below timer resolution");
+                }
+                (period, Some(period))
+            }
+        };
+        let slack = ticks_ceil(slack)?;
+
+        self.inner.lock(|inner| {
+            let handle = inner.add(Timer {
+                deadline: Instant::now().ticks().saturating_add(after),
+                period,
+                slack,
+                callback,
+            })?;
+            program_comparator(inner.next_expiry());
+
+            Ok(handle)
+        })
+    }
+
+    /// Schedule a timer to fire once, after the given time.
+    pub fn schedule_once(
+        &self,
+        after: Duration,
+        callback: TimerCallback,
+    ) -> Result<TimerHandle, &'static str> {
+        self.schedule(Expiry::After(after), Duration::ZERO, callback)
+    }
+
+    /// Schedule a timer to fire repeatedly, with the given period.
+    pub fn schedule_periodic(
+        &self,
+        period: Duration,
+        callback: TimerCallback,
+    ) -> Result<TimerHandle, &'static str> {
+        self.schedule(Expiry::Every(period), Duration::ZERO, callback)
+    }
+
+    /// Cancel a timer. Returns whether it was still scheduled.
+    ///
+    /// A timer that was already taken for firing by the interrupt being handled still fires that
+    /// once, even when an earlier callback of the same interrupt cancels it.
+    pub fn cancel(&self, handle: TimerHandle) -> bool {
+        self.inner.lock(|inner| {
+            if !inner.is_current(handle) {
+                return false;
+            }
+
+            inner.timers[handle.slot] = None;
+            program_comparator(inner.next_expiry());
+
+            true
+        })
+    }
+
+    /// Number of scheduled timers.
+    pub fn num_scheduled(&self) -> usize {
+        self.inner
+            .lock(|inner| inner.timers.iter().flatten().count())
+    }
+
+    /// Number of periodic expiries skipped so far, because a timer fired a period or more late.
+    pub fn overruns(&self) -> usize {
+        self.inner.lock(|inner| inner.overruns)
+    }
+}
+
+/// Return a reference to the timer service.
+pub fn timer_service() -> &'static TimerService {
+    &TIMER_SERVICE
+}
+
+//------------------------------------------------------------------------------
+// OS Interface Code
+//------------------------------------------------------------------------------
+
+impl interface::IRQHandler for TimerService {
+    fn handle(&self) -> Result<(), &'static str> {
+        let mut due = [None; NUM_TIMERS];
+
+        self.inner.lock(|inner| {
+            inner.take_due(Instant::now().ticks(), &mut due);
+
+            // Also acknowledges the interrupt, or masks it if nothing is left.
+            program_comparator(inner.next_expiry());
+        });
+
+        // Called without the lock, so callbacks can schedule and cancel timers.
+        for (handle, callback) in due.iter().flatten() {
+            callback(*handle);
+        }
+
+        Ok(())
+    }
+}
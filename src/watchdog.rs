@@ -0,0 +1,88 @@
+//! Hardware watchdog and reboot.
+//!
+//! Once [`start()`]ed, the board resets unless [`feed()`] is called within the timeout, so a kernel
+//! that hangs, e.g. in a `spin_for` loop that never ends, comes back on its own. Feeding belongs in
+//! a place that stops running when the kernel hangs, not in a timer interrupt, which may still be
+//! taken.
+//!
+//! What a panic does is set by the [`PanicPolicy`]. Halting with the watchdog running also ends in
+//! a reset, once it expires.
+
+use crate::{bsp, console};
+use core::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// What the panic handler does once the panic message is printed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Wait for events forever.
+    Halt,
+    /// Reset the board.
+    Reboot,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Global instances
+//--------------------------------------------------------------------------------------------------
+
+static PANIC_REBOOT: AtomicBool = AtomicBool::new(cfg!(feature = "panic_reboot"));
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Start the watchdog, or restart it with a new timeout of up to 16 s.
+pub fn start(timeout: Duration) -> Result<(), &'static str> {
+    bsp::power_management().start_watchdog(timeout)
+}
+
+/// Set the watchdog back to its full timeout.
+pub fn feed() {
+    bsp::power_management().feed_watchdog();
+}
+
+/// Stop the watchdog.
+pub fn stop() {
+    bsp::power_management().stop_watchdog();
+}
+
+/// Time left until the watchdog resets the board, if it runs.
+pub fn remaining() -> Option<Duration> {
+    bsp::power_management().watchdog_remaining()
+}
+
+/// Reset the board, once the console has sent what it buffered.
+pub fn reboot() -> ! {
+    console::console().flush();
+
+    bsp::reboot()
+}
+
+/// Set what a panic does. The default is to halt, or to reboot with the `panic_reboot` feature.
+pub fn set_panic_policy(policy: PanicPolicy) {
+    PANIC_REBOOT.store(policy == PanicPolicy::Reboot, Ordering::Relaxed);
+}
+
+/// What a panic does.
+pub fn panic_policy() -> PanicPolicy {
+    match PANIC_REBOOT.load(Ordering::Relaxed) {
+        true => PanicPolicy::Reboot,
+        false => PanicPolicy::Halt,
+    }
+}
+
+impl fmt::Display for PanicPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PanicPolicy::Halt => "halt",
+            PanicPolicy::Reboot => "reboot",
+        })
+    }
+}
@@ -0,0 +1,160 @@
+//! Clock synchronization with the kernel over the serial console.
+//!
+//! Starts the kernel's `clocksync` shell command, runs a number of NTP-style exchanges, and sets
+//! the kernel's wall clock from the one with the smallest round-trip delay. See
+//! `src/time/sync.rs` for the protocol.
+//!
+//! ```console
+//! $ stty -F /dev/ttyUSB0 921600 raw min 0 time 20
+//! $ cargo run -p clocksync --target x86_64-unknown-linux-gnu -- /dev/ttyUSB0
+//! ```
+//!
+//! `min 0 time 20` makes reads give up after two seconds, so a target that doesn't answer ends
+//! the run instead of hanging it.
+//!
+//! The `--target` is needed because `.cargo/config.toml` makes the kernel's target the default
+//! for the whole workspace. Use the triple of your host.
+
+mod protocol;
+
+use protocol::{Exchange, HostClock, Session};
+use std::{
+    env,
+    fs::File,
+    process::ExitCode,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const USAGE: &str = "\
+Usage: clocksync [options] <device>
+
+Options:
+  --count <n>   Number of exchanges, default 16
+  --no-start    The target is already serving, don't type the shell command
+  --dry-run     Only measure, don't set the target's wall clock";
+
+struct Options {
+    device_path: String,
+    count: u32,
+    start_command: bool,
+    dry_run: bool,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn parse_args() -> Result<Options, String> {
+    let mut args = env::args().skip(1);
+    let mut positional = Vec::new();
+    let mut options = Options {
+        device_path: String::new(),
+        count: 16,
+        start_command: true,
+        dry_run: false,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--count" => {
+                let count = args.next().ok_or("--count needs a value")?;
+                options.count = count.parse().map_err(|_| "invalid --count")?;
+            }
+            "--no-start" => options.start_command = false,
+            "--dry-run" => options.dry_run = true,
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    options.device_path = positional.next().ok_or("missing device")?;
+    if positional.next().is_some() {
+        return Err("too many arguments".to_string());
+    }
+    if options.count == 0 {
+        return Err("invalid --count".to_string());
+    }
+
+    Ok(options)
+}
+
+fn host_clock() -> HostClock {
+    Box::new(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as i128)
+    })
+}
+
+fn print_exchange(n: u32, exchange: &Exchange) {
+    println!(
+        "{:>3}  offset {:>+22} ns  delay {:>9} ns",
+        n,
+        exchange.offset(),
+        exchange.delay()
+    );
+}
+
+fn run(options: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    let port = File::options()
+        .read(true)
+        .write(true)
+        .open(&options.device_path)?;
+
+    let mut session = Session::start(port, host_clock(), options.start_command)?;
+    println!("Target counter at {} Hz", session.freq());
+
+    let mut exchanges = Vec::new();
+    for n in 0..options.count {
+        let exchange = session.exchange(n)?;
+        print_exchange(n, &exchange);
+        exchanges.push(exchange);
+    }
+
+    // Not empty, count is at least one.
+    let best = protocol::best(&exchanges).unwrap();
+    println!(
+        "Best: offset {} ns, delay {} ns, so within +/- {} ns",
+        best.offset(),
+        best.delay(),
+        best.delay() / 2
+    );
+
+    if !options.dry_run {
+        session.set_wall_clock(best.offset())?;
+        println!("Wall clock set");
+    }
+    session.end()?;
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+fn main() -> ExitCode {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(msg) => {
+            if !msg.is_empty() {
+                eprintln!("clocksync: {}", msg);
+            }
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(e) = run(&options) {
+        eprintln!("clocksync: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
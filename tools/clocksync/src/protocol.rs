@@ -0,0 +1,454 @@
+//! Host side of the kernel's clock synchronization protocol, see `src/time/sync.rs`.
+//!
+//! All times are nanoseconds. Host times count from the Unix epoch, target times from counter zero.
+
+use std::io::{self, Read, Write};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Returns the current host time.
+pub type HostClock = Box<dyn FnMut() -> i128>;
+
+/// The four timestamps of one request/reply exchange.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Exchange {
+    /// Host time the request was sent.
+    pub t1: i128,
+    /// Target time the request was received.
+    pub t2: i128,
+    /// Target time the reply was sent.
+    pub t3: i128,
+    /// Host time the reply was received.
+    pub t4: i128,
+}
+
+/// A synchronization session with a target serving the protocol.
+pub struct Session<P> {
+    port: P,
+    clock: HostClock,
+    /// Received bytes not yet returned as a line.
+    pending: Vec<u8>,
+    /// Counter frequency of the target in Hz.
+    freq: u64,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl<P: Read + Write> Session<P> {
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        self.port.write_all(line.as_bytes())?;
+        self.port.write_all(b"\r")?;
+        self.port.flush()
+    }
+
+    /// Read the next non-empty line, without its line end.
+    fn read_line(&mut self) -> io::Result<String> {
+        loop {
+            if let Some(end) = self.pending.iter().position(|b| *b == b'\n' || *b == b'\r') {
+                let line: Vec<u8> = self.pending.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line[..end]).trim().to_string();
+                if line.is_empty() {
+                    continue;
+                }
+                return Ok(line);
+            }
+
+            let mut buf = [0; 256];
+            let len = self.port.read(&mut buf)?;
+            if len == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "no answer from the target",
+                ));
+            }
+            self.pending.extend_from_slice(&buf[..len]);
+        }
+    }
+
+    /// Read lines until `f` accepts one. Everything else, like log output, is skipped.
+    fn expect<T>(&mut self, mut f: impl FnMut(&str) -> Option<T>) -> io::Result<T> {
+        loop {
+            let line = self.read_line()?;
+            if let Some(result) = f(&line) {
+                return Ok(result);
+            }
+        }
+    }
+
+    fn ticks_to_nanos(&self, ticks: &str) -> Option<i128> {
+        let ticks: i128 = ticks.parse().ok()?;
+
+        Some(ticks * 1_000_000_000 / self.freq as i128)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl Exchange {
+    /// Target time minus host time, assuming the request and reply took equally long.
+    pub fn offset(&self) -> i128 {
+        ((self.t2 - self.t1) + (self.t3 - self.t4)) / 2
+    }
+
+    /// Round-trip time, without the target's processing time.
+    pub fn delay(&self) -> i128 {
+        (self.t4 - self.t1) - (self.t3 - self.t2)
+    }
+}
+
+/// Return the exchange with the smallest delay, the one whose offset is bounded tightest.
+pub fn best(exchanges: &[Exchange]) -> Option<&Exchange> {
+    exchanges.iter().min_by_key(|e| e.delay())
+}
+
+impl<P: Read + Write> Session<P> {
+    /// Start a session on a port the kernel's shell runs on.
+    ///
+    /// With `start_command`, types the `clocksync` shell command first. Otherwise the target must
+    /// already be serving.
+    pub fn start(port: P, clock: HostClock, start_command: bool) -> io::Result<Self> {
+        let mut session = Self {
+            port,
+            clock,
+            pending: Vec::new(),
+            freq: 0,
+        };
+
+        if start_command {
+            session.send("clocksync")?;
+        }
+
+        session.freq = session.expect(|line| {
+            let freq = line.strip_prefix("SYNC ")?.trim().parse().ok()?;
+            (freq > 0).then_some(freq)
+        })?;
+
+        Ok(session)
+    }
+
+    /// Counter frequency of the target in Hz.
+    pub fn freq(&self) -> u64 {
+        self.freq
+    }
+
+    /// Run one request/reply exchange.
+    pub fn exchange(&mut self, n: u32) -> io::Result<Exchange> {
+        let t1 = (self.clock)();
+        self.send(&format!("REQ {}", n))?;
+
+        let n = n.to_string();
+        let (t2, t3) = self.expect(|line| {
+            let mut words = line.split_ascii_whitespace();
+            if words.next() != Some("RSP") || words.next() != Some(n.as_str()) {
+                return None;
+            }
+            Some((words.next()?.to_string(), words.next()?.to_string()))
+        })?;
+        let t4 = (self.clock)();
+
+        let (Some(t2), Some(t3)) = (self.ticks_to_nanos(&t2), self.ticks_to_nanos(&t3)) else {
+            return Err(invalid(format!("malformed reply to request {}", n)));
+        };
+
+        Ok(Exchange { t1, t2, t3, t4 })
+    }
+
+    /// Set the target's wall clock from the offset of an exchange.
+    pub fn set_wall_clock(&mut self, offset: i128) -> io::Result<()> {
+        // The target wants the host time at its counter zero.
+        let wall_clock_offset = -offset;
+        if wall_clock_offset < 0 || wall_clock_offset > u64::MAX as i128 {
+            return Err(invalid(format!("offset {} ns out of range", offset)));
+        }
+
+        self.send(&format!("SET {}", wall_clock_offset))?;
+
+        match self.expect(|line| match line {
+            "OK" => Some(true),
+            "ERR" => Some(false),
+            _ => None,
+        })? {
+            true => Ok(()),
+            false => Err(invalid("target rejected the wall clock".to_string())),
+        }
+    }
+
+    /// End the session, returning the target to the shell.
+    pub fn end(mut self) -> io::Result<P> {
+        self.send("END")?;
+
+        Ok(self.port)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::Cell, collections::VecDeque, rc::Rc};
+
+    const FREQ: u64 = 54_000_000;
+
+    /// Target time minus host time of the simulated targets.
+    const TRUE_OFFSET: i128 = -1_760_000_000_000_000_000;
+
+    fn ticks(target_nanos: i128) -> i128 {
+        target_nanos * FREQ as i128 / 1_000_000_000
+    }
+
+    /// Answers like the kernel, on a simulated timeline shared with the host clock. Every line
+    /// takes `uplink` to arrive and the reply `downlink`.
+    struct SimulatedTarget {
+        now: Rc<Cell<i128>>,
+        uplink: i128,
+        downlink: i128,
+        input: Vec<u8>,
+        output: VecDeque<u8>,
+        wall_clock_offset: Option<i128>,
+        ended: bool,
+    }
+
+    impl SimulatedTarget {
+        fn new(now: Rc<Cell<i128>>, uplink: i128, downlink: i128) -> Self {
+            let mut target = Self {
+                now,
+                uplink,
+                downlink,
+                input: Vec::new(),
+                output: VecDeque::new(),
+                wall_clock_offset: None,
+                ended: false,
+            };
+            // Shell noise before the session starts.
+            target.reply("> clocksync");
+            target
+        }
+
+        fn reply(&mut self, line: &str) {
+            self.output.extend(line.as_bytes());
+            self.output.extend(b"\r\n");
+        }
+
+        fn handle(&mut self, line: &str) {
+            self.now.set(self.now.get() + self.uplink);
+            let t2 = ticks(self.now.get() + TRUE_OFFSET);
+            // Processing time.
+            self.now.set(self.now.get() + 20_000);
+            let t3 = ticks(self.now.get() + TRUE_OFFSET);
+
+            let words: Vec<&str> = line.split_ascii_whitespace().collect();
+            match words.as_slice() {
+                ["clocksync"] => self.reply(&format!("SYNC {}", FREQ)),
+                ["REQ", n] => {
+                    self.reply("[    1.000000] log output in between");
+                    self.reply(&format!("RSP {} {} {}", n, t2, t3));
+                }
+                ["SET", nanos] => {
+                    self.wall_clock_offset = nanos.parse().ok();
+                    self.reply("OK");
+                }
+                ["END"] => self.ended = true,
+                _ => self.reply("ERR"),
+            }
+
+            self.now.set(self.now.get() + self.downlink);
+        }
+    }
+
+    impl Write for SimulatedTarget {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            for byte in buf {
+                if *byte == b'\r' {
+                    let line = String::from_utf8(std::mem::take(&mut self.input)).unwrap();
+                    self.handle(&line);
+                } else {
+                    self.input.push(*byte);
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for SimulatedTarget {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(self.output.len());
+            for (dst, src) in buf.iter_mut().zip(self.output.drain(..len)) {
+                *dst = src;
+            }
+            Ok(len)
+        }
+    }
+
+    fn simulated_session(uplink: i128, downlink: i128) -> Session<SimulatedTarget> {
+        let now = Rc::new(Cell::new(1_760_000_000_000_000_000));
+        let target = SimulatedTarget::new(now.clone(), uplink, downlink);
+        let clock: HostClock = Box::new(move || {
+            now.set(now.get() + 1_000);
+            now.get()
+        });
+
+        Session::start(target, clock, true).unwrap()
+    }
+
+    #[test]
+    fn offset_and_delay_of_symmetric_link() {
+        let mut session = simulated_session(500_000, 500_000);
+        assert_eq!(session.freq(), FREQ);
+
+        let exchange = session.exchange(0).unwrap();
+
+        // Off by the host clock's own steps and the counter resolution only.
+        assert!(
+            (exchange.offset() - TRUE_OFFSET).abs() < 2_000,
+            "{:?}",
+            exchange
+        );
+        assert!(
+            (exchange.delay() - 1_000_000).abs() < 2_000,
+            "{:?}",
+            exchange
+        );
+    }
+
+    #[test]
+    fn asymmetry_shows_up_as_half_the_difference() {
+        let mut session = simulated_session(800_000, 200_000);
+
+        let exchange = session.exchange(7).unwrap();
+
+        assert!((exchange.offset() - (TRUE_OFFSET + 300_000)).abs() < 2_000);
+    }
+
+    #[test]
+    fn wall_clock_is_applied_and_session_ended() {
+        let mut session = simulated_session(500_000, 500_000);
+        let exchanges: Vec<Exchange> = (0..4).map(|n| session.exchange(n).unwrap()).collect();
+        let offset = best(&exchanges).unwrap().offset();
+
+        session.set_wall_clock(offset).unwrap();
+        let target = session.end().unwrap();
+
+        assert!(target.ended);
+        assert!((target.wall_clock_offset.unwrap() + TRUE_OFFSET).abs() < 2_000);
+    }
+
+    #[test]
+    fn best_exchange_has_the_smallest_delay() {
+        let exchange = |t4| Exchange {
+            t1: 0,
+            t2: 100,
+            t3: 150,
+            t4,
+        };
+        let exchanges = [exchange(500), exchange(300), exchange(900)];
+
+        assert_eq!(best(&exchanges), Some(&exchanges[1]));
+        assert_eq!(exchanges[1].delay(), 250);
+    }
+
+    #[test]
+    fn silent_target_is_an_error() {
+        let now = Rc::new(Cell::new(0));
+        let mut target = SimulatedTarget::new(now.clone(), 0, 0);
+        target.output.clear();
+        let clock: HostClock = Box::new(move || now.get());
+
+        assert!(Session::start(target, clock, false).is_err());
+    }
+
+    /// Both sides over a real socket pair, the target on its own thread with a real clock.
+    #[cfg(unix)]
+    #[test]
+    fn loopback() {
+        use std::{
+            io::{BufRead, BufReader},
+            os::unix::net::UnixStream,
+            thread,
+            time::{Instant, SystemTime, UNIX_EPOCH},
+        };
+
+        let (host_end, target_end) = UnixStream::pair().unwrap();
+        let boot = Instant::now();
+        let boot_wall = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+        let target = thread::spawn(move || {
+            let mut writer = target_end.try_clone().unwrap();
+            let counter = || ticks(boot.elapsed().as_nanos() as i128);
+            let mut reader = BufReader::new(target_end);
+
+            writeln!(writer, "SYNC {}\r", FREQ).unwrap();
+            let mut line = String::new();
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                let t2 = counter();
+                let words: Vec<&str> = line.split_ascii_whitespace().collect();
+                match words.as_slice() {
+                    ["REQ", n] => writeln!(writer, "RSP {} {} {}\r", n, t2, counter()).unwrap(),
+                    ["SET", nanos] => {
+                        writeln!(writer, "OK\r").unwrap();
+                        return nanos.parse::<i128>().unwrap();
+                    }
+                    _ => writeln!(writer, "ERR\r").unwrap(),
+                }
+            }
+        });
+
+        let clock: HostClock = Box::new(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos() as i128
+        });
+        // The simulated target reads lines ending in `\n`.
+        let mut session = Session::start(LineFeed(host_end), clock, false).unwrap();
+        let exchanges: Vec<Exchange> = (0..8).map(|n| session.exchange(n).unwrap()).collect();
+        session
+            .set_wall_clock(best(&exchanges).unwrap().offset())
+            .unwrap();
+
+        let wall_clock_offset = target.join().unwrap();
+        let error = wall_clock_offset - boot_wall.as_nanos() as i128;
+        assert!(error.abs() < 5_000_000, "off by {} ns", error);
+    }
+
+    /// Turns the `\r` line ends into `\n`.
+    struct LineFeed<T>(T);
+
+    impl<T: Write> Write for LineFeed<T> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let buf: Vec<u8> = buf
+                .iter()
+                .map(|b| if *b == b'\r' { b'\n' } else { *b })
+                .collect();
+            self.0.write(&buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    impl<T: Read> Read for LineFeed<T> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.0.read(buf)
+        }
+    }
+}
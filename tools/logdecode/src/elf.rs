@@ -0,0 +1,118 @@
+//! Just enough ELF parsing to read a section by name.
+//!
+//! Only 64-bit little-endian files are supported, which is what the aarch64 kernel is.
+
+use std::fmt;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Debug)]
+pub enum Error {
+    NotElf,
+    Unsupported(&'static str),
+    Truncated,
+    NoSection(String),
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, Error> {
+    let bytes = data.get(offset..offset + 2).ok_or(Error::Truncated)?;
+
+    Ok(u16::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, Error> {
+    let bytes = data.get(offset..offset + 4).ok_or(Error::Truncated)?;
+
+    Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64, Error> {
+    let bytes = data.get(offset..offset + 8).ok_or(Error::Truncated)?;
+
+    Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+/// The fields of a section header we care about.
+struct SectionHeader {
+    name: u32,
+    offset: u64,
+    size: u64,
+}
+
+fn section_header(data: &[u8], offset: usize) -> Result<SectionHeader, Error> {
+    Ok(SectionHeader {
+        name: read_u32(data, offset)?,
+        offset: read_u64(data, offset + 0x18)?,
+        size: read_u64(data, offset + 0x20)?,
+    })
+}
+
+fn section_data<'a>(data: &'a [u8], header: &SectionHeader) -> Result<&'a [u8], Error> {
+    let start = header.offset as usize;
+    let end = start
+        .checked_add(header.size as usize)
+        .ok_or(Error::Truncated)?;
+
+    data.get(start..end).ok_or(Error::Truncated)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotElf => write!(f, "not an ELF file"),
+            Error::Unsupported(what) => write!(f, "unsupported ELF file: {}", what),
+            Error::Truncated => write!(f, "truncated ELF file"),
+            Error::NoSection(name) => write!(f, "no section named {}", name),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Return the contents of the section called `name`.
+pub fn find_section<'a>(data: &'a [u8], name: &str) -> Result<&'a [u8], Error> {
+    if data.get(0..4) != Some(b"\x7fELF") {
+        return Err(Error::NotElf);
+    }
+    if data.get(4) != Some(&2) {
+        return Err(Error::Unsupported("not 64-bit"));
+    }
+    if data.get(5) != Some(&1) {
+        return Err(Error::Unsupported("not little-endian"));
+    }
+
+    let shoff = read_u64(data, 0x28)? as usize;
+    let shentsize = read_u16(data, 0x3A)? as usize;
+    let shnum = read_u16(data, 0x3C)? as usize;
+    let shstrndx = read_u16(data, 0x3E)? as usize;
+
+    let strtab = section_header(data, shoff + shstrndx * shentsize)?;
+    let strtab = section_data(data, &strtab)?;
+
+    for i in 0..shnum {
+        let header = section_header(data, shoff + i * shentsize)?;
+
+        let name_start = header.name as usize;
+        let name_bytes = strtab.get(name_start..).ok_or(Error::Truncated)?;
+        let name_len = name_bytes
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or(Error::Truncated)?;
+
+        if &name_bytes[..name_len] == name.as_bytes() {
+            return section_data(data, &header);
+        }
+    }
+
+    Err(Error::NoSection(name.to_string()))
+}
@@ -0,0 +1,303 @@
+//! Rendering of Rust format strings with decoded arguments.
+//!
+//! Supports positional (`{}`, `{1}`) arguments with fill, alignment, sign, `#`, zero padding,
+//! width, precision and the `?`, `x`, `X`, `o`, `b`, `e` and `E` types. Width and precision taken
+//! from arguments (`{:1$}`, `{:.*}`) and named arguments are not supported, as the kernel can't
+//! send them either. Hex, octal and binary of negative values show all 128 bits, as the kernel
+//! doesn't send the width of the type.
+
+use crate::frame::Value;
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Default)]
+struct Spec {
+    fill: Option<char>,
+    align: Option<Align>,
+    plus: bool,
+    alternate: bool,
+    zero: bool,
+    width: Option<usize>,
+    precision: Option<usize>,
+    kind: String,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn parse_align(c: char) -> Option<Align> {
+    match c {
+        '<' => Some(Align::Left),
+        '^' => Some(Align::Center),
+        '>' => Some(Align::Right),
+        _ => None,
+    }
+}
+
+fn take_number(chars: &[char], pos: &mut usize) -> Option<usize> {
+    let start = *pos;
+    while *pos < chars.len() && chars[*pos].is_ascii_digit() {
+        *pos += 1;
+    }
+
+    chars[start..*pos].iter().collect::<String>().parse().ok()
+}
+
+/// Parse the part after the `:` of a placeholder.
+fn parse_spec(spec: &str) -> Spec {
+    let chars: Vec<char> = spec.chars().collect();
+    let mut result = Spec::default();
+    let mut pos = 0;
+
+    if chars.len() >= 2 && parse_align(chars[1]).is_some() {
+        result.fill = Some(chars[0]);
+        result.align = parse_align(chars[1]);
+        pos = 2;
+    } else if let Some(align) = chars.first().and_then(|c| parse_align(*c)) {
+        result.align = Some(align);
+        pos = 1;
+    }
+
+    if chars.get(pos) == Some(&'+') {
+        result.plus = true;
+        pos += 1;
+    } else if chars.get(pos) == Some(&'-') {
+        pos += 1;
+    }
+    if chars.get(pos) == Some(&'#') {
+        result.alternate = true;
+        pos += 1;
+    }
+    if chars.get(pos) == Some(&'0') {
+        result.zero = true;
+        pos += 1;
+    }
+
+    result.width = take_number(&chars, &mut pos);
+    if chars.get(pos) == Some(&'.') {
+        pos += 1;
+        result.precision = take_number(&chars, &mut pos);
+    }
+
+    result.kind = chars[pos..].iter().collect();
+
+    result
+}
+
+fn format_unsigned(value: u128, spec: &Spec) -> (String, String) {
+    let (prefix, digits) = match spec.kind.as_str() {
+        "x" | "x?" => ("0x", format!("{:x}", value)),
+        "X" | "X?" => ("0x", format!("{:X}", value)),
+        "o" => ("0o", format!("{:o}", value)),
+        "b" => ("0b", format!("{:b}", value)),
+        "e" => ("", format!("{:e}", value)),
+        "E" => ("", format!("{:E}", value)),
+        _ => ("", value.to_string()),
+    };
+
+    let prefix = if spec.alternate { prefix } else { "" };
+
+    (prefix.to_string(), digits)
+}
+
+/// Split the rendered value into a sign/prefix part and the rest. Zero padding goes in between.
+fn format_value(value: &Value, spec: &Spec) -> (String, String) {
+    let plus = if spec.plus { "+" } else { "" };
+    let debug = spec.kind == "?";
+
+    match value {
+        Value::Unsigned(v) => {
+            let (prefix, digits) = format_unsigned(*v, spec);
+            (format!("{}{}", plus, prefix), digits)
+        }
+        Value::Signed(v) => {
+            // Like Rust, hex and friends show the two's complement of negative values.
+            let twos_complement = matches!(spec.kind.as_str(), "x" | "X" | "x?" | "X?" | "o" | "b");
+            let (sign, (prefix, digits)) = if twos_complement {
+                (plus, format_unsigned(*v as u128, spec))
+            } else {
+                let sign = if *v < 0 { "-" } else { plus };
+                (sign, format_unsigned(v.unsigned_abs(), spec))
+            };
+            (format!("{}{}", sign, prefix), digits)
+        }
+        Value::F32(v) => format_float(*v as f64, *v < 0.0, spec, plus, |p| match (debug, p) {
+            (_, Some(p)) => format!("{:.*}", p, v.abs()),
+            (true, None) => format!("{:?}", v.abs()),
+            (false, None) => format!("{}", v.abs()),
+        }),
+        Value::F64(v) => format_float(*v, *v < 0.0, spec, plus, |p| match (debug, p) {
+            (_, Some(p)) => format!("{:.*}", p, v.abs()),
+            (true, None) => format!("{:?}", v.abs()),
+            (false, None) => format!("{}", v.abs()),
+        }),
+        Value::Bool(v) => (String::new(), v.to_string()),
+        Value::Char(v) if debug => (String::new(), format!("{:?}", v)),
+        Value::Char(v) => (String::new(), v.to_string()),
+        Value::Str(v) if debug => (String::new(), format!("{:?}", v)),
+        Value::Str(v) => match spec.precision {
+            Some(p) => (String::new(), v.chars().take(p).collect()),
+            None => (String::new(), v.clone()),
+        },
+    }
+}
+
+fn format_float(
+    value: f64,
+    negative: bool,
+    spec: &Spec,
+    plus: &str,
+    digits: impl Fn(Option<usize>) -> String,
+) -> (String, String) {
+    if spec.kind == "e" || spec.kind == "E" {
+        let s = match (spec.kind.as_str(), spec.precision) {
+            ("e", Some(p)) => format!("{:.*e}", p, value.abs()),
+            ("e", None) => format!("{:e}", value.abs()),
+            (_, Some(p)) => format!("{:.*E}", p, value.abs()),
+            (_, None) => format!("{:E}", value.abs()),
+        };
+        let sign = if negative { "-" } else { plus };
+        return (sign.to_string(), s);
+    }
+
+    let sign = if negative { "-" } else { plus };
+
+    (sign.to_string(), digits(spec.precision))
+}
+
+fn is_numeric(value: &Value) -> bool {
+    matches!(
+        value,
+        Value::Unsigned(_) | Value::Signed(_) | Value::F32(_) | Value::F64(_)
+    )
+}
+
+fn render(value: &Value, spec: &Spec) -> String {
+    let (prefix, body) = format_value(value, spec);
+    let len = prefix.chars().count() + body.chars().count();
+    let width = spec.width.unwrap_or(0);
+
+    if len >= width {
+        return prefix + &body;
+    }
+    let padding = width - len;
+
+    if spec.zero && is_numeric(value) {
+        return prefix + &"0".repeat(padding) + &body;
+    }
+
+    let default_align = if is_numeric(value) {
+        Align::Right
+    } else {
+        Align::Left
+    };
+    let fill = spec.fill.unwrap_or(' ').to_string();
+    let (left, right) = match spec.align.unwrap_or(default_align) {
+        Align::Left => (0, padding),
+        Align::Right => (padding, 0),
+        Align::Center => (padding / 2, padding - padding / 2),
+    };
+
+    fill.repeat(left) + &prefix + &body + &fill.repeat(right)
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Render `fmt` with `args`. Placeholders without a matching argument show up as `{?}`.
+pub fn format(fmt: &str, args: &[Value]) -> String {
+    let mut out = String::new();
+    let mut next_arg = 0;
+    let mut chars = fmt.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let placeholder: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                let (position, spec) = match placeholder.split_once(':') {
+                    Some((position, spec)) => (position, spec),
+                    None => (placeholder.as_str(), ""),
+                };
+
+                let index = if position.is_empty() {
+                    next_arg += 1;
+                    Some(next_arg - 1)
+                } else {
+                    position.parse().ok()
+                };
+
+                match index.and_then(|i| args.get(i)) {
+                    Some(value) => out.push_str(&render(value, &parse_spec(spec))),
+                    None => out.push_str("{?}"),
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_as_rust() {
+        let u = |v| [Value::Unsigned(v)];
+        let i = |v| [Value::Signed(v)];
+
+        assert_eq!(
+            format("{:#010x}", &u(0x3f20_1000)),
+            format!("{:#010x}", 0x3f20_1000)
+        );
+        assert_eq!(format("{:>8b}|", &u(5)), format!("{:>8b}|", 5));
+        assert_eq!(format("{:+05}", &i(-42)), format!("{:+05}", -42));
+        assert_eq!(format("{:x}", &i(-1)), format!("{:x}", -1i128));
+        assert_eq!(
+            format("{:*^7}", &[Value::Bool(true)]),
+            format!("{:*^7}", true)
+        );
+        assert_eq!(
+            format("{:.2}", &[Value::F32(1.005)]),
+            format!("{:.2}", 1.005f32)
+        );
+        assert_eq!(format("{:?}", &[Value::F64(1.0)]), format!("{:?}", 1.0f64));
+        assert_eq!(
+            format("{:.3?}", &[Value::Str("abcdef".to_string())]),
+            format!("{:.3?}", "abcdef")
+        );
+    }
+
+    #[test]
+    fn positions_escapes_and_missing_arguments() {
+        let args = [Value::Char('a'), Value::Char('b')];
+
+        assert_eq!(format("{1}{0}{}", &args), "baa");
+        assert_eq!(format("{{{}}} {} {}", &args), "{a} b {?}");
+        assert_eq!(format("{5:?}", &args), "{?}");
+    }
+}
@@ -0,0 +1,362 @@
+//! Binary log frames and `.log_strings` entries.
+//!
+//! See `src/print/binary.rs` in the kernel for the format.
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const TAG_UNSIGNED: u8 = 0;
+const TAG_SIGNED: u8 = 1;
+const TAG_F32: u8 = 2;
+const TAG_F64: u8 = 3;
+const TAG_BOOL: u8 = 4;
+const TAG_CHAR: u8 = 5;
+const TAG_STR: u8 = 6;
+
+/// Index and timestamp plus the checksum.
+const MIN_PAYLOAD_LEN: usize = 4 + 8 + 2;
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Index of the metadata frame.
+pub const META_INDEX: u32 = u32::MAX;
+
+/// A decoded log argument.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Unsigned(u128),
+    Signed(i128),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    Char(char),
+    Str(String),
+}
+
+/// A frame with valid checksum.
+#[derive(Debug)]
+pub struct Frame {
+    pub index: u32,
+    pub ticks: u64,
+    pub args: Vec<Value>,
+    /// The arguments ended in the middle of a value, usually because the target cut off an
+    /// oversized frame.
+    pub truncated: bool,
+}
+
+/// A call site, as stored in `.log_strings`.
+#[derive(Debug)]
+pub struct Entry<'a> {
+    /// 1 (error) to 5 (trace).
+    pub level: u8,
+    pub module_path: &'a str,
+    pub fmt: &'a str,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xffff;
+
+    for byte in data {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+fn cobs_decode(data: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut i = 0;
+
+    while i < data.len() {
+        let code = data[i] as usize;
+        if code == 0 {
+            return None;
+        }
+
+        let block = data.get(i + 1..i + code)?;
+        out.extend_from_slice(block);
+        i += code;
+
+        if code < 0xff && i < data.len() {
+            out.push(0);
+        }
+    }
+
+    Some(out)
+}
+
+/// Cursor over the argument bytes.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Option<u8> {
+        let (first, rest) = self.data.split_first()?;
+        self.data = rest;
+
+        Some(*first)
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.data.len() < len {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+
+        Some(bytes)
+    }
+
+    fn leb128(&mut self) -> Option<u128> {
+        let mut value: u128 = 0;
+
+        for shift in (0..128).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u128) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+
+        None
+    }
+
+    fn value(&mut self) -> Option<Value> {
+        let value = match self.byte()? {
+            TAG_UNSIGNED => Value::Unsigned(self.leb128()?),
+            TAG_SIGNED => {
+                let zigzag = self.leb128()?;
+                Value::Signed((zigzag >> 1) as i128 ^ -((zigzag & 1) as i128))
+            }
+            TAG_F32 => Value::F32(f32::from_le_bytes(self.bytes(4)?.try_into().unwrap())),
+            TAG_F64 => Value::F64(f64::from_le_bytes(self.bytes(8)?.try_into().unwrap())),
+            TAG_BOOL => Value::Bool(self.byte()? != 0),
+            TAG_CHAR => Value::Char(char::from_u32(self.leb128()? as u32)?),
+            TAG_STR => {
+                let len = u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()) as usize;
+                // Strings cut off by the target end early, take what is there.
+                let len = usize::min(len, self.data.len());
+                Value::Str(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+            }
+            _ => return None,
+        };
+
+        Some(value)
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Decode one stretch of bytes found between two zero bytes.
+///
+/// Returns `None` if it is not a valid frame, in which case it is most likely plain text.
+pub fn decode(encoded: &[u8]) -> Option<Frame> {
+    let payload = cobs_decode(encoded)?;
+    if payload.len() < MIN_PAYLOAD_LEN {
+        return None;
+    }
+
+    let (data, crc) = payload.split_at(payload.len() - 2);
+    if crc16(data) != u16::from_le_bytes(crc.try_into().unwrap()) {
+        return None;
+    }
+
+    let index = u32::from_le_bytes(data[0..4].try_into().unwrap());
+    let ticks = u64::from_le_bytes(data[4..12].try_into().unwrap());
+
+    let mut reader = Reader { data: &data[12..] };
+    let mut args = Vec::new();
+    let mut truncated = false;
+    while !reader.data.is_empty() {
+        match reader.value() {
+            Some(value) => args.push(value),
+            None => {
+                truncated = true;
+                break;
+            }
+        }
+    }
+
+    Some(Frame {
+        index,
+        ticks,
+        args,
+        truncated,
+    })
+}
+
+/// Look up the entry at `index` in the contents of `.log_strings`.
+pub fn entry(log_strings: &[u8], index: u32) -> Option<Entry<'_>> {
+    let data = log_strings.get(index as usize..)?;
+    let (level, data) = data.split_first()?;
+    if !(1..=5).contains(level) {
+        return None;
+    }
+
+    let mut parts = data.splitn(3, |b| *b == 0);
+    let module_path = std::str::from_utf8(parts.next()?).ok()?;
+    let fmt = std::str::from_utf8(parts.next()?).ok()?;
+    // The format string must be NUL terminated as well.
+    parts.next()?;
+
+    Some(Entry {
+        level: *level,
+        module_path,
+        fmt,
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frames and text as sent by the kernel's encoder, see `fixtures/capture.txt` for the text.
+    const CAPTURE: &[u8] = include_bytes!("../fixtures/capture.bin");
+    const LOG_STRINGS: &[u8] = include_bytes!("../fixtures/log_strings.bin");
+
+    fn segments() -> Vec<&'static [u8]> {
+        CAPTURE
+            .split(|b| *b == 0)
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+
+    fn frames() -> Vec<Frame> {
+        segments().into_iter().filter_map(decode).collect()
+    }
+
+    /// Same as `cobs_encode()` in the kernel.
+    fn cobs_encode(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        for block in data.split(|byte| *byte == 0) {
+            let mut chunks = block.chunks(254).peekable();
+            if chunks.peek().is_none() {
+                out.push(1);
+            }
+
+            while let Some(chunk) = chunks.next() {
+                out.push(chunk.len() as u8 + 1);
+                out.extend_from_slice(chunk);
+
+                if chunk.len() == 254 && chunks.peek().is_none() {
+                    out.push(1);
+                }
+            }
+        }
+
+        out
+    }
+
+    #[test]
+    fn captured_frames_and_text() {
+        let segments = segments();
+        let frames = frames();
+
+        assert_eq!(segments.len(), 9);
+        assert_eq!(frames.len(), 7);
+        assert_eq!(frames[0].index, META_INDEX);
+        assert_eq!(frames[0].args, vec![Value::Unsigned(54_000_000)]);
+        // Plain text is not mistaken for a frame.
+        assert!(decode(b"booting\r\n").is_none());
+        assert!(decode(b"> gpio set 5 1\r\n").is_none());
+    }
+
+    #[test]
+    fn ticks_with_zero_bytes() {
+        assert_eq!(frames()[2].ticks, 0x0000_0001_0000_0000);
+    }
+
+    #[test]
+    fn leb128_and_zigzag_extremes() {
+        assert_eq!(
+            frames()[5].args,
+            vec![
+                Value::Unsigned(u128::MAX),
+                Value::Signed(i128::MIN),
+                Value::Signed(-1),
+                Value::F64(1.5e-7),
+            ]
+        );
+
+        let mut reader = Reader {
+            data: &[0, 0xe5, 0x8e, 0x26, 1, 0x03, 1, 0x04],
+        };
+        assert_eq!(reader.value(), Some(Value::Unsigned(624_485)));
+        assert_eq!(reader.value(), Some(Value::Signed(-2)));
+        assert_eq!(reader.value(), Some(Value::Signed(2)));
+        assert_eq!(reader.value(), None);
+    }
+
+    #[test]
+    fn cobs_round_trip() {
+        let payloads: [Vec<u8>; 5] = [
+            vec![0],
+            vec![0, 0, 1, 0],
+            vec![7; 254],
+            (0..=255).cycle().take(600).collect(),
+            [vec![1; 253], vec![0], vec![2; 254]].concat(),
+        ];
+
+        for payload in payloads {
+            let encoded = cobs_encode(&payload);
+
+            assert!(!encoded.contains(&0));
+            assert_eq!(cobs_decode(&encoded), Some(payload));
+        }
+    }
+
+    #[test]
+    fn checksum() {
+        // The CRC-16/CCITT-FALSE check value.
+        assert_eq!(crc16(b"123456789"), 0x29b1);
+
+        let mut segment = segments()[2].to_vec();
+        let last = segment.len() - 1;
+        segment[last] ^= 0x01;
+        assert!(decode(&segment).is_none());
+    }
+
+    #[test]
+    fn oversized_frame_is_truncated() {
+        let frame = frames().pop().unwrap();
+
+        assert!(frame.truncated);
+        assert_eq!(frame.args, vec![Value::Str("x".repeat(220))]);
+    }
+
+    #[test]
+    fn entry_lookup() {
+        let frames = frames();
+
+        let entry = entry(LOG_STRINGS, frames[3].index).unwrap();
+        assert_eq!(entry.level, 2);
+        assert_eq!(entry.module_path, "labproc::time::pps");
+        assert_eq!(entry.fmt, "PPS offset {:+} ns, drift {:.3} ppm, locked {}");
+
+        // The middle of an entry, and past the end of the section.
+        assert!(super::entry(LOG_STRINGS, 1).is_none());
+        assert!(super::entry(LOG_STRINGS, LOG_STRINGS.len() as u32).is_none());
+    }
+}
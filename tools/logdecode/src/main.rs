@@ -0,0 +1,274 @@
+//! Decoder for the kernel's binary log output.
+//!
+//! Reads the format strings from the `.log_strings` section of the kernel ELF and a byte stream
+//! captured from the UART, and prints the log messages as the kernel would have in text mode.
+//! Plain text on the stream, like shell output, is passed through.
+//!
+//! ```console
+//! $ cargo build --release --features binary_log
+//! $ cargo run -p logdecode --target x86_64-unknown-linux-gnu -- \
+//!       target/aarch64-unknown-none-softfloat/release/kernel capture.bin
+//! ```
+//!
+//! The `--target` is needed because `.cargo/config.toml` makes the kernel's target the default
+//! for the whole workspace. Use the triple of your host.
+
+mod elf;
+mod format;
+mod frame;
+
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    process::ExitCode,
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const USAGE: &str = "\
+Usage: logdecode [options] <kernel-elf> [<capture>|-]
+
+Reads the capture from stdin if it is omitted or '-'.
+
+Options:
+  --freq <hz>   Counter frequency, if the capture lacks the metadata frame
+  --ticks       Print raw counter ticks instead of seconds
+  --modules     Print the module path of every message";
+
+struct Options {
+    elf_path: String,
+    capture_path: Option<String>,
+    freq: Option<u64>,
+    ticks: bool,
+    modules: bool,
+}
+
+struct Decoder<'a> {
+    log_strings: &'a [u8],
+    options: &'a Options,
+    freq: Option<u64>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn parse_args() -> Result<Options, String> {
+    let mut args = env::args().skip(1);
+    let mut positional = Vec::new();
+    let mut options = Options {
+        elf_path: String::new(),
+        capture_path: None,
+        freq: None,
+        ticks: false,
+        modules: false,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--freq" => {
+                let freq = args.next().ok_or("--freq needs a value")?;
+                options.freq = Some(freq.parse().map_err(|_| "invalid --freq")?);
+            }
+            "--ticks" => options.ticks = true,
+            "--modules" => options.modules = true,
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    options.elf_path = positional.next().ok_or("missing kernel ELF")?;
+    options.capture_path = positional.next().filter(|path| path != "-");
+    if positional.next().is_some() {
+        return Err("too many arguments".to_string());
+    }
+
+    Ok(options)
+}
+
+/// Same prefixes as `Level::prefix()` in the kernel.
+fn level_prefix(level: u8) -> &'static str {
+    match level {
+        1 => "E ",
+        2 => "W ",
+        3 => "  ",
+        4 => "D ",
+        _ => "T ",
+    }
+}
+
+impl Decoder<'_> {
+    fn timestamp(&self, ticks: u64) -> String {
+        match (self.options.ticks, self.freq) {
+            (false, Some(freq)) if freq > 0 => {
+                let secs = ticks / freq;
+                let micros = (ticks % freq) as u128 * 1_000_000 / freq as u128;
+                format!("{:>3}.{:06}", secs, micros)
+            }
+            _ => format!("{:>12}", ticks),
+        }
+    }
+
+    /// Handle one stretch between zero bytes.
+    fn segment(&mut self, segment: &[u8], out: &mut impl Write) -> io::Result<()> {
+        let frame = frame::decode(segment);
+
+        if let Some(frame) = frame.as_ref().filter(|f| f.index == frame::META_INDEX) {
+            // An explicit --freq wins.
+            if self.options.freq.is_none() {
+                if let Some(frame::Value::Unsigned(freq)) = frame.args.first() {
+                    self.freq = Some(*freq as u64);
+                }
+            }
+            return Ok(());
+        }
+
+        let Some((frame, entry)) =
+            frame.and_then(|f| frame::entry(self.log_strings, f.index).map(|e| (f, e)))
+        else {
+            // Not a frame, pass the text on. Line endings are the terminal's business.
+            let text = String::from_utf8_lossy(segment).replace('\r', "");
+            return out.write_all(text.as_bytes());
+        };
+
+        write!(
+            out,
+            "[{}{}] ",
+            level_prefix(entry.level),
+            self.timestamp(frame.ticks)
+        )?;
+        if self.options.modules {
+            write!(out, "{}: ", entry.module_path)?;
+        }
+        write!(out, "{}", format::format(entry.fmt, &frame.args))?;
+        if frame.truncated {
+            write!(out, " <truncated>")?;
+        }
+        writeln!(out)
+    }
+}
+
+fn run(options: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    let elf = fs::read(&options.elf_path)?;
+    let log_strings = elf::find_section(&elf, ".log_strings")?;
+
+    let capture = match &options.capture_path {
+        Some(path) => fs::read(path)?,
+        None => {
+            let mut capture = Vec::new();
+            io::stdin().read_to_end(&mut capture)?;
+            capture
+        }
+    };
+
+    let mut decoder = Decoder {
+        log_strings,
+        options,
+        freq: options.freq,
+    };
+
+    let stdout = io::stdout();
+    let mut out = io::BufWriter::new(stdout.lock());
+    for segment in capture.split(|b| *b == 0).filter(|s| !s.is_empty()) {
+        decoder.segment(segment, &mut out)?;
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+fn main() -> ExitCode {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(msg) => {
+            if !msg.is_empty() {
+                eprintln!("logdecode: {}", msg);
+            }
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(e) = run(&options) {
+        eprintln!("logdecode: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAPTURE: &[u8] = include_bytes!("../fixtures/capture.bin");
+    const LOG_STRINGS: &[u8] = include_bytes!("../fixtures/log_strings.bin");
+
+    fn options() -> Options {
+        Options {
+            elf_path: String::new(),
+            capture_path: None,
+            freq: None,
+            ticks: false,
+            modules: false,
+        }
+    }
+
+    fn decode(capture: &[u8], options: &Options) -> String {
+        let mut decoder = Decoder {
+            log_strings: LOG_STRINGS,
+            options,
+            freq: options.freq,
+        };
+
+        let mut out = Vec::new();
+        for segment in capture.split(|b| *b == 0).filter(|s| !s.is_empty()) {
+            decoder.segment(segment, &mut out).unwrap();
+        }
+
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn same_as_text_mode() {
+        // Formatted by Rust from the arguments that went into the frames.
+        let expected = include_str!("../fixtures/capture.txt");
+
+        assert_eq!(decode(CAPTURE, &options()), expected);
+    }
+
+    #[test]
+    fn ticks_and_modules() {
+        let options = Options {
+            ticks: true,
+            modules: true,
+            ..options()
+        };
+        let decoded = decode(CAPTURE, &options);
+
+        assert!(decoded.contains("[W     54000123] labproc::time::pps: PPS offset"));
+    }
+
+    #[test]
+    fn explicit_frequency_wins() {
+        let options = Options {
+            freq: Some(27_000_000),
+            ..options()
+        };
+        let decoded = decode(CAPTURE, &options);
+
+        assert!(decoded.contains("[    1.000000] labproc version 0.1.0"));
+    }
+}
@@ -0,0 +1,427 @@
+//! Timestamp analysis.
+//!
+//! Target timestamps are resolved to seconds on a single timeline, and, where the host receive time
+//! is known, fitted against it with least squares. The slope of the fit is the drift of the
+//! target's counter against the host clock, the residuals are the jitter: UART transmission, USB
+//! latency and host scheduling, all of which only ever add delay.
+
+use crate::parse::{Line, Timestamp};
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, Write},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+pub struct Options {
+    /// Counter frequency, to read integer timestamps as ticks instead of nanoseconds.
+    pub ticks_hz: Option<u64>,
+    /// Width of a jitter histogram bin in microseconds.
+    pub bin_us: f64,
+    /// Number of largest inter-message gaps to list.
+    pub top_gaps: usize,
+}
+
+/// A log line with its timestamps resolved.
+#[derive(Clone, Debug)]
+pub struct Sample {
+    /// Line number in the capture, from 1.
+    pub line_number: usize,
+    pub level: char,
+    pub sequence: Option<u64>,
+    /// Target time in seconds.
+    pub target: f64,
+    /// Host receive time in Unix seconds.
+    pub host: Option<f64>,
+    pub message: String,
+}
+
+/// Least-squares fit of host time against target time, relative to the first sample with both.
+#[derive(Copy, Clone, Debug)]
+pub struct Fit {
+    target_origin: f64,
+    host_origin: f64,
+    offset: f64,
+    slope: f64,
+}
+
+pub struct Report {
+    pub lines: usize,
+    pub samples: usize,
+    pub fit: Option<Fit>,
+    /// Standard deviation and largest absolute value of the residuals, in microseconds.
+    pub jitter_us: Option<(f64, f64)>,
+    /// Residuals per histogram bin, keyed by the bin's lower bound in bins.
+    pub histogram: BTreeMap<i64, usize>,
+    pub bin_us: f64,
+    /// Smallest, mean and largest gap between consecutive messages, in seconds.
+    pub gaps: Option<(f64, f64, f64)>,
+    /// Largest gaps, with the line number of the message ending them.
+    pub largest_gaps: Vec<(usize, f64)>,
+    /// Ranges of missing sequence numbers, inclusive.
+    pub dropped: Vec<(u64, u64)>,
+    /// Number of times the sequence numbers started over, e.g. after a reboot.
+    pub restarts: usize,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), x| (sum + x, count + 1));
+
+    (count > 0).then(|| sum / count as f64)
+}
+
+fn sequence_drops(samples: &[Sample]) -> (Vec<(u64, u64)>, usize) {
+    let mut dropped = Vec::new();
+    let mut restarts = 0;
+    let mut previous: Option<u64> = None;
+
+    for sequence in samples.iter().filter_map(|s| s.sequence) {
+        match previous {
+            Some(p) if sequence > p + 1 => dropped.push((p + 1, sequence - 1)),
+            Some(p) if sequence <= p => restarts += 1,
+            _ => (),
+        }
+        previous = Some(sequence);
+    }
+
+    (dropped, restarts)
+}
+
+/// Gaps between consecutive messages. Negative ones, from a reboot, are skipped.
+fn gaps(samples: &[Sample]) -> Vec<(usize, f64)> {
+    samples
+        .windows(2)
+        .map(|w| (w[1].line_number, w[1].target - w[0].target))
+        .filter(|(_, gap)| *gap >= 0.0)
+        .collect()
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn optional(value: Option<f64>, precision: usize) -> String {
+    value.map_or(String::new(), |x| format!("{:.*}", precision, x))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Resolve the target timestamps of the log lines to seconds.
+///
+/// Integers are nanoseconds, or ticks if `ticks_hz` is given. Deltas count from the previous
+/// message, or from zero for the first one.
+pub fn resolve(lines: &[Line], ticks_hz: Option<u64>) -> Vec<Sample> {
+    let mut samples: Vec<Sample> = Vec::new();
+
+    for (i, line) in lines.iter().enumerate() {
+        let Some(log) = &line.log else {
+            continue;
+        };
+
+        let target = match log.timestamp {
+            Timestamp::Uptime(secs) | Timestamp::WallClock(secs) => secs,
+            Timestamp::Integer(x) => match ticks_hz {
+                Some(hz) => x as f64 / hz as f64,
+                None => x as f64 / 1e9,
+            },
+            Timestamp::Delta(delta) => samples.last().map_or(0.0, |s| s.target) + delta,
+        };
+
+        samples.push(Sample {
+            line_number: i + 1,
+            level: log.level,
+            sequence: log.sequence,
+            target,
+            host: line.host_time,
+            message: log.message.clone(),
+        });
+    }
+
+    samples
+}
+
+impl Fit {
+    /// Fit the samples that have a host time. Needs at least two with distinct target times.
+    pub fn new(samples: &[Sample]) -> Option<Self> {
+        let points: Vec<(f64, f64)> = samples
+            .iter()
+            .filter_map(|s| s.host.map(|host| (s.target, host)))
+            .collect();
+        let (target_origin, host_origin) = *points.first()?;
+
+        // Relative to the first point, so wall-clock seconds don't eat the precision.
+        let points: Vec<(f64, f64)> = points
+            .iter()
+            .map(|(t, h)| (t - target_origin, h - host_origin))
+            .collect();
+
+        let mean_t = mean(points.iter().map(|p| p.0))?;
+        let mean_h = mean(points.iter().map(|p| p.1))?;
+        let var_t: f64 = points.iter().map(|p| (p.0 - mean_t).powi(2)).sum();
+        let cov: f64 = points.iter().map(|p| (p.0 - mean_t) * (p.1 - mean_h)).sum();
+
+        if var_t == 0.0 {
+            return None;
+        }
+        let slope = cov / var_t;
+
+        Some(Self {
+            target_origin,
+            host_origin,
+            offset: mean_h - slope * mean_t,
+            slope,
+        })
+    }
+
+    /// Drift of the target clock against the host clock in parts per million. Positive if the
+    /// target is slow.
+    pub fn drift_ppm(&self) -> f64 {
+        (self.slope - 1.0) * 1e6
+    }
+
+    /// Host time minus target time at the first sample, in seconds, as fitted.
+    pub fn offset(&self) -> f64 {
+        self.host_origin + self.offset - self.target_origin
+    }
+
+    /// Actual minus predicted host time of a sample, in microseconds.
+    pub fn residual_us(&self, sample: &Sample) -> Option<f64> {
+        sample.host.map(|host| {
+            let predicted = self.offset + self.slope * (sample.target - self.target_origin);
+            ((host - self.host_origin) - predicted) * 1e6
+        })
+    }
+}
+
+/// Analyze the resolved samples of a capture with `lines` lines.
+pub fn analyze(lines: usize, samples: &[Sample], options: &Options) -> Report {
+    let fit = Fit::new(samples);
+
+    let residuals: Vec<f64> = fit
+        .map(|fit| samples.iter().filter_map(|s| fit.residual_us(s)).collect())
+        .unwrap_or_default();
+    let jitter_us = mean(residuals.iter().map(|r| r * r)).map(|variance| {
+        let max = residuals.iter().fold(0.0, |max: f64, r| max.max(r.abs()));
+        (variance.sqrt(), max)
+    });
+
+    let mut histogram = BTreeMap::new();
+    for residual in &residuals {
+        *histogram
+            .entry((residual / options.bin_us).floor() as i64)
+            .or_insert(0) += 1;
+    }
+
+    let gaps = gaps(samples);
+    let gap_stats = mean(gaps.iter().map(|g| g.1)).map(|mean| {
+        let min = gaps.iter().fold(f64::INFINITY, |min, g| min.min(g.1));
+        let max = gaps.iter().fold(0.0, |max: f64, g| max.max(g.1));
+        (min, mean, max)
+    });
+    let mut largest_gaps = gaps;
+    largest_gaps.sort_by(|a, b| b.1.total_cmp(&a.1));
+    largest_gaps.truncate(options.top_gaps);
+
+    let (dropped, restarts) = sequence_drops(samples);
+
+    Report {
+        lines,
+        samples: samples.len(),
+        fit,
+        jitter_us,
+        histogram,
+        bin_us: options.bin_us,
+        gaps: gap_stats,
+        largest_gaps,
+        dropped,
+        restarts,
+    }
+}
+
+/// Write one CSV row per sample.
+pub fn write_csv(samples: &[Sample], fit: Option<&Fit>, out: &mut impl Write) -> io::Result<()> {
+    writeln!(
+        out,
+        "line,sequence,level,target_s,host_s,offset_s,residual_us,gap_us,message"
+    )?;
+
+    let mut previous: Option<f64> = None;
+    for sample in samples {
+        let offset = sample.host.map(|host| host - sample.target);
+        let residual_us = fit.and_then(|fit| fit.residual_us(sample));
+        let gap_us = previous.map(|p| (sample.target - p) * 1e6);
+        previous = Some(sample.target);
+
+        writeln!(
+            out,
+            "{},{},{},{:.9},{},{},{},{},{}",
+            sample.line_number,
+            sample.sequence.map_or(String::new(), |s| s.to_string()),
+            sample.level,
+            sample.target,
+            optional(sample.host, 9),
+            optional(offset, 9),
+            optional(residual_us, 3),
+            optional(gap_us, 3),
+            csv_field(&sample.message)
+        )?;
+    }
+
+    Ok(())
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} lines, {} log messages", self.lines, self.samples)?;
+
+        match (&self.fit, self.jitter_us) {
+            (Some(fit), Some((stddev, max))) => {
+                writeln!(f, "offset:  {:.6} s (host - target)", fit.offset())?;
+                writeln!(f, "drift:   {:+.3} ppm", fit.drift_ppm())?;
+                writeln!(f, "jitter:  {:.1} us stddev, {:.1} us max", stddev, max)?;
+
+                let peak = self.histogram.values().copied().max().unwrap_or(1);
+                for (bin, count) in &self.histogram {
+                    writeln!(
+                        f,
+                        "  {:>9.1} .. {:>9.1} us {:>6} {}",
+                        *bin as f64 * self.bin_us,
+                        (*bin + 1) as f64 * self.bin_us,
+                        count,
+                        "#".repeat((count * 50).div_ceil(peak))
+                    )?;
+                }
+            }
+            _ => writeln!(f, "no host receive times, skipping drift and jitter")?,
+        }
+
+        if let Some((min, mean, max)) = self.gaps {
+            writeln!(
+                f,
+                "gaps:    {:.6} s min, {:.6} s mean, {:.6} s max",
+                min, mean, max
+            )?;
+            for (line, gap) in &self.largest_gaps {
+                writeln!(f, "  {:.6} s before line {}", gap, line)?;
+            }
+        }
+
+        let missing: u64 = self.dropped.iter().map(|(a, b)| b - a + 1).sum();
+        if missing > 0 || self.restarts > 0 {
+            writeln!(
+                f,
+                "dropped: {} messages, {} restarts",
+                missing, self.restarts
+            )?;
+            for (first, last) in &self.dropped {
+                if first == last {
+                    writeln!(f, "  #{}", first)?;
+                } else {
+                    writeln!(f, "  #{} to #{}", first, last)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::parse_line;
+
+    fn load(capture: &str, ticks_hz: Option<u64>) -> (usize, Vec<Sample>) {
+        let lines: Vec<Line> = capture.lines().map(parse_line).collect();
+
+        (lines.len(), resolve(&lines, ticks_hz))
+    }
+
+    fn options() -> Options {
+        Options {
+            ticks_hz: None,
+            bin_us: 100.0,
+            top_gaps: 3,
+        }
+    }
+
+    #[test]
+    fn drift_and_jitter_of_live_capture() {
+        let (lines, samples) = load(include_str!("../fixtures/live_uptime.cap"), None);
+        let report = analyze(lines, &samples, &options());
+
+        // Recorded from a target whose counter runs 50 ppm slow, with up to 300 us of latency.
+        let fit = report.fit.unwrap();
+        assert!((fit.drift_ppm() - 50.0).abs() < 5.0, "{}", fit.drift_ppm());
+
+        let (stddev, max) = report.jitter_us.unwrap();
+        assert!(stddev > 10.0 && stddev < 300.0, "{}", stddev);
+        assert!(max < 300.0, "{}", max);
+        assert_eq!(report.histogram.values().sum::<usize>(), report.samples);
+
+        // The shell prompt and command output are not log lines.
+        assert!(report.samples < report.lines);
+    }
+
+    #[test]
+    fn dropped_sequence_numbers() {
+        let (lines, samples) = load(include_str!("../fixtures/delta_seq.log"), None);
+        let report = analyze(lines, &samples, &options());
+
+        assert!(report.fit.is_none());
+        assert_eq!(report.dropped, vec![(5, 5), (9, 11)]);
+        assert_eq!(report.restarts, 1);
+    }
+
+    #[test]
+    fn deltas_accumulate() {
+        let (_, samples) = load(include_str!("../fixtures/delta_seq.log"), None);
+
+        assert_eq!(samples[0].target, 0.0);
+        assert!((samples[2].target - 0.0015).abs() < 1e-9);
+    }
+
+    #[test]
+    fn ticks_need_the_frequency() {
+        let capture = include_str!("../fixtures/ticks.log");
+
+        let (_, as_nanos) = load(capture, None);
+        let (lines, as_ticks) = load(capture, Some(54_000_000));
+        let report = analyze(lines, &as_ticks, &options());
+
+        assert!((as_ticks[1].target - 1.0).abs() < 1e-9);
+        assert!((as_nanos[1].target - 0.054).abs() < 1e-9);
+        assert_eq!(report.largest_gaps[0], (4, 2.0));
+    }
+
+    #[test]
+    fn csv_has_a_row_per_message() {
+        let (_, samples) = load(include_str!("../fixtures/live_uptime.cap"), None);
+        let fit = Fit::new(&samples);
+
+        let mut csv = Vec::new();
+        write_csv(&samples, fit.as_ref(), &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+
+        assert_eq!(csv.lines().count(), samples.len() + 1);
+        assert!(csv.lines().nth(1).unwrap().starts_with("1,0,I,"));
+        assert!(csv.contains("\"Loopback: sent 64, matched 64, \"\"ok\"\"\""));
+    }
+}
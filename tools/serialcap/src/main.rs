@@ -0,0 +1,211 @@
+//! Serial capture and timestamp analysis.
+//!
+//! Reads the kernel's text log output, from a capture file or live from a serial device or pty,
+//! parses the log prefixes in any of the kernel's timestamp formats, and reports the drift of the
+//! target's clock against the host, the jitter of the host receive times, the gaps between
+//! messages and, with sequence numbers enabled (`log seq on`), dropped messages.
+//!
+//! ```console
+//! $ stty -F /dev/ttyUSB0 921600 raw
+//! $ cargo run -p serialcap --target x86_64-unknown-linux-gnu -- --live /dev/ttyUSB0 \
+//!       --record capture.cap
+//! $ cargo run -p serialcap --target x86_64-unknown-linux-gnu -- capture.cap --csv capture.csv
+//! ```
+//!
+//! Live mode stamps every line with the host receive time, which drift and jitter are measured
+//! against. It runs until the device or pipe is closed, so record the capture and analyze it
+//! afterwards when in doubt. Captures without host times only get the gap and sequence analysis.
+//!
+//! The `--target` is needed because `.cargo/config.toml` makes the kernel's target the default
+//! for the whole workspace. Use the triple of your host.
+
+mod analysis;
+mod parse;
+
+use std::{
+    env,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Write},
+    process::ExitCode,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+//--------------------------------------------------------------------------------------------------
+// Private Definitions
+//--------------------------------------------------------------------------------------------------
+
+const USAGE: &str = "\
+Usage: serialcap [options] [<capture>|-]
+       serialcap [options] --live <device>|-
+
+Reads the capture from stdin if it is omitted or '-'.
+
+Options:
+  --live            Read from a serial device or pty, stamping lines with the host time
+  --record <file>   Save the stamped lines, live mode only
+  --echo            Print the lines as they arrive, live mode only
+  --csv <file>      Export the log messages as CSV
+  --ticks <hz>      Read integer timestamps as counter ticks instead of nanoseconds
+  --bin <us>        Width of the jitter histogram bins, default 50
+  --gaps <n>        Number of largest gaps to list, default 5";
+
+struct Options {
+    input_path: Option<String>,
+    live: bool,
+    record_path: Option<String>,
+    echo: bool,
+    csv_path: Option<String>,
+    analysis: analysis::Options,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+fn parse_value<T: std::str::FromStr>(
+    args: &mut impl Iterator<Item = String>,
+    name: &str,
+) -> Result<T, String> {
+    let value = args
+        .next()
+        .ok_or_else(|| format!("{} needs a value", name))?;
+
+    value.parse().map_err(|_| format!("invalid {}", name))
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut args = env::args().skip(1);
+    let mut positional = Vec::new();
+    let mut options = Options {
+        input_path: None,
+        live: false,
+        record_path: None,
+        echo: false,
+        csv_path: None,
+        analysis: analysis::Options {
+            ticks_hz: None,
+            bin_us: 50.0,
+            top_gaps: 5,
+        },
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--live" => options.live = true,
+            "--record" => options.record_path = Some(parse_value(&mut args, "--record")?),
+            "--echo" => options.echo = true,
+            "--csv" => options.csv_path = Some(parse_value(&mut args, "--csv")?),
+            "--ticks" => options.analysis.ticks_hz = Some(parse_value(&mut args, "--ticks")?),
+            "--bin" => options.analysis.bin_us = parse_value(&mut args, "--bin")?,
+            "--gaps" => options.analysis.top_gaps = parse_value(&mut args, "--gaps")?,
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ => positional.push(arg),
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    options.input_path = positional.next().filter(|path| path != "-");
+    if positional.next().is_some() {
+        return Err("too many arguments".to_string());
+    }
+    if !options.live && (options.record_path.is_some() || options.echo) {
+        return Err("--record and --echo need --live".to_string());
+    }
+    if options.analysis.bin_us <= 0.0 {
+        return Err("invalid --bin".to_string());
+    }
+
+    Ok(options)
+}
+
+fn host_time() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64())
+}
+
+/// Read lines as they arrive and stamp them with the host receive time.
+fn read_live(input: impl io::Read, options: &Options) -> io::Result<Vec<parse::Line>> {
+    let mut record = match &options.record_path {
+        Some(path) => Some(File::create(path)?),
+        None => None,
+    };
+
+    let mut lines = Vec::new();
+    let mut reader = BufReader::new(input);
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        // Taken as soon as the line is complete, before any processing.
+        let now = host_time();
+
+        let stamped = parse::stamp_line(now, &String::from_utf8_lossy(&buf));
+        if let Some(record) = &mut record {
+            // Unbuffered, so an interrupted run keeps everything received so far.
+            writeln!(record, "{}", stamped)?;
+        }
+        if options.echo {
+            println!("{}", stamped);
+        }
+
+        lines.push(parse::parse_line(&stamped));
+    }
+
+    Ok(lines)
+}
+
+fn run(options: &Options) -> Result<(), Box<dyn std::error::Error>> {
+    let lines = match (&options.input_path, options.live) {
+        (Some(path), true) => read_live(File::open(path)?, options)?,
+        (None, true) => read_live(io::stdin(), options)?,
+        (Some(path), false) => String::from_utf8_lossy(&fs::read(path)?)
+            .lines()
+            .map(parse::parse_line)
+            .collect(),
+        (None, false) => io::stdin()
+            .lock()
+            .lines()
+            .map(|line| line.map(|line| parse::parse_line(&line)))
+            .collect::<io::Result<_>>()?,
+    };
+
+    let samples = analysis::resolve(&lines, options.analysis.ticks_hz);
+    let report = analysis::analyze(lines.len(), &samples, &options.analysis);
+    print!("{}", report);
+
+    if let Some(path) = &options.csv_path {
+        let mut out = io::BufWriter::new(File::create(path)?);
+        analysis::write_csv(&samples, report.fit.as_ref(), &mut out)?;
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+fn main() -> ExitCode {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(msg) => {
+            if !msg.is_empty() {
+                eprintln!("serialcap: {}", msg);
+            }
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(e) = run(&options) {
+        eprintln!("serialcap: {}", e);
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,220 @@
+//! Parsing of captured lines.
+//!
+//! A kernel log line looks like `[E   1.234567 #42] message`: the level prefix (see
+//! `Level::prefix()` in the kernel), the timestamp in one of the kernel's `TimestampFormat`s, the
+//! optional sequence number and the message. Anything else, like shell output, is not a log line.
+//!
+//! Lines recorded by `serialcap --live` carry the host receive time in front, as Unix seconds:
+//! `@1714564800.123456789 [   1.234567] message`.
+
+//--------------------------------------------------------------------------------------------------
+// Public Definitions
+//--------------------------------------------------------------------------------------------------
+
+/// Target timestamp of a log line, as printed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Timestamp {
+    /// Seconds since power-on.
+    Uptime(f64),
+    /// Raw counter value, or nanoseconds since power-on. Both are plain integers.
+    Integer(u64),
+    /// Seconds since the previous log message.
+    Delta(f64),
+    /// Unix seconds.
+    WallClock(f64),
+}
+
+/// A kernel log line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogLine {
+    /// One of `E`, `W`, `I`, `D` and `T`.
+    pub level: char,
+    pub timestamp: Timestamp,
+    pub sequence: Option<u64>,
+    pub message: String,
+}
+
+/// A captured line.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    /// Host receive time in Unix seconds, if recorded.
+    pub host_time: Option<f64>,
+    /// The parsed log line, `None` for other output.
+    pub log: Option<LogLine>,
+}
+
+//--------------------------------------------------------------------------------------------------
+// Private Code
+//--------------------------------------------------------------------------------------------------
+
+/// Days since the Unix epoch of a date.
+///
+/// See <http://howardhinnant.github.io/date_algorithms.html#days_from_civil>.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Parse `2024-05-01T12:00:00.000000Z` into Unix seconds.
+fn parse_iso8601(s: &str) -> Option<f64> {
+    let s = s.strip_suffix('Z')?;
+    let (date, time) = s.split_once('T')?;
+
+    let mut date = date.splitn(3, '-').map(|x| x.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+
+    let mut time = time.splitn(3, ':');
+    let hours: i64 = time.next()?.parse().ok()?;
+    let minutes: i64 = time.next()?.parse().ok()?;
+    let seconds: f64 = time.next()?.parse().ok()?;
+
+    let days = days_from_civil(year, month, day);
+
+    Some((days * 86_400 + hours * 3600 + minutes * 60) as f64 + seconds)
+}
+
+fn parse_timestamp(s: &str) -> Option<Timestamp> {
+    if let Some(delta) = s.strip_prefix('+') {
+        return delta.trim().parse().ok().map(Timestamp::Delta);
+    }
+    if s.ends_with('Z') {
+        return parse_iso8601(s).map(Timestamp::WallClock);
+    }
+    if s.contains('.') {
+        return s.parse().ok().map(Timestamp::Uptime);
+    }
+
+    s.parse().ok().map(Timestamp::Integer)
+}
+
+fn parse_log(line: &str) -> Option<LogLine> {
+    let (header, message) = line.strip_prefix('[')?.split_once(']')?;
+    let message = message.strip_prefix(' ').unwrap_or(message);
+
+    let mut chars = header.chars();
+    let level = match (chars.next()?, chars.next()?) {
+        (c @ ('E' | 'W' | 'D' | 'T'), ' ') => c,
+        (' ', ' ') => 'I',
+        _ => return None,
+    };
+
+    let rest = chars.as_str().trim();
+    let (timestamp, sequence) = match rest.rsplit_once(" #") {
+        Some((timestamp, sequence)) => (timestamp.trim(), Some(sequence.parse().ok()?)),
+        None => (rest, None),
+    };
+
+    Some(LogLine {
+        level,
+        timestamp: parse_timestamp(timestamp)?,
+        sequence,
+        message: message.to_string(),
+    })
+}
+
+//--------------------------------------------------------------------------------------------------
+// Public Code
+//--------------------------------------------------------------------------------------------------
+
+/// Parse a captured line, with or without the host receive time.
+pub fn parse_line(line: &str) -> Line {
+    let line = line.trim_end_matches(['\r', '\n']);
+
+    let (host_time, rest) = match line
+        .strip_prefix('@')
+        .and_then(|x| x.split_once(' '))
+        .and_then(|(time, rest)| time.parse().ok().map(|time| (time, rest)))
+    {
+        Some((time, rest)) => (Some(time), rest),
+        None => (None, line),
+    };
+
+    Line {
+        host_time,
+        log: parse_log(rest),
+    }
+}
+
+/// Format a line with its host receive time, as `parse_line()` expects it.
+pub fn stamp_line(host_time: f64, line: &str) -> String {
+    format!("@{:.9} {}", host_time, line.trim_end_matches(['\r', '\n']))
+}
+
+//--------------------------------------------------------------------------------------------------
+// Testing
+//--------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uptime_line() {
+        let line = parse_line("[W   1.234567] Something odd\r\n");
+
+        assert_eq!(line.host_time, None);
+        assert_eq!(
+            line.log,
+            Some(LogLine {
+                level: 'W',
+                timestamp: Timestamp::Uptime(1.234567),
+                sequence: None,
+                message: "Something odd".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn info_level_has_no_letter() {
+        let log = parse_line("[  123.000001] hello").log.unwrap();
+
+        assert_eq!(log.level, 'I');
+        assert_eq!(log.timestamp, Timestamp::Uptime(123.000001));
+    }
+
+    #[test]
+    fn all_timestamp_formats() {
+        let timestamp = |line: &str| parse_line(line).log.unwrap().timestamp;
+
+        assert_eq!(
+            timestamp("[    123456789012] x"),
+            Timestamp::Integer(123456789012)
+        );
+        assert_eq!(timestamp("[D +  0.000250] x"), Timestamp::Delta(0.00025));
+        assert_eq!(
+            timestamp("[  2024-05-01T12:00:01.500000Z] x"),
+            Timestamp::WallClock(1714564801.5)
+        );
+    }
+
+    #[test]
+    fn sequence_number_and_host_time() {
+        let line = parse_line("@1714564800.250000000 [E   2.000000 #17] failed: x] y");
+        let log = line.log.unwrap();
+
+        assert_eq!(line.host_time, Some(1714564800.25));
+        assert_eq!(log.sequence, Some(17));
+        assert_eq!(log.message, "failed: x] y");
+    }
+
+    #[test]
+    fn other_output_is_not_a_log_line() {
+        assert_eq!(parse_line("> uptime").log, None);
+        assert_eq!(parse_line("[not a log line] x").log, None);
+        assert_eq!(parse_line("").log, None);
+    }
+
+    #[test]
+    fn stamp_round_trip() {
+        let line = parse_line(&stamp_line(12.5, "[    0.000100] boot\r\n"));
+
+        assert_eq!(line.host_time, Some(12.5));
+        assert_eq!(line.log.unwrap().message, "boot");
+    }
+}